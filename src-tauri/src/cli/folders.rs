@@ -0,0 +1,314 @@
+//! Expanding a directory argument into the images inside it.
+//!
+//! Files are picked by their content rather than their extension, so
+//! screenshots saved without one are found too. Hidden entries are skipped,
+//! and symlinked directories are not followed to avoid walking in circles.
+
+use std::cmp::Ordering;
+use std::path::{Path, PathBuf};
+
+use super::validate::{self, RejectReason};
+
+/// Most images taken from one directory argument. Tabs beyond the limit set
+/// in the frontend are offered to open later, so this is well above it.
+const MAX_IMAGES: usize = 1000;
+/// Levels of subdirectories searched with `recursive`
+const MAX_DEPTH: usize = 16;
+/// Most directory entries looked at in one walk, images or not, so pointing
+/// at a home directory does not open every file in it
+const MAX_ENTRIES: usize = 50_000;
+
+/// The images found in a directory argument
+pub struct FolderImages {
+    pub paths: Vec<PathBuf>,
+    /// Whether the walk stopped at a limit, leaving images out
+    pub truncated: bool,
+}
+
+/// The images in directory `dir`, rejected if there are none
+pub fn images(dir: &Path, recursive: bool) -> Result<FolderImages, RejectReason> {
+    let mut walk = Walk::new(recursive);
+    walk.dir(dir, 0).map_err(|e| RejectReason::Unreadable {
+        message: format!("{}: {}", dir.display(), e),
+    })?;
+    if walk.files.is_empty() && !walk.truncated {
+        return Err(RejectReason::NoImages { recursive });
+    }
+    Ok(FolderImages {
+        paths: walk.files,
+        truncated: walk.truncated,
+    })
+}
+
+/// Images and projects under a directory, in natural order (`shot-2` before
+/// `shot-10`). With `recursive`, subdirectories are searched too and their
+/// files follow those of their parent.
+struct Walk {
+    recursive: bool,
+    max_images: usize,
+    max_depth: usize,
+    max_entries: usize,
+    files: Vec<PathBuf>,
+    entries: usize,
+    truncated: bool,
+}
+
+impl Walk {
+    fn new(recursive: bool) -> Self {
+        Self {
+            recursive,
+            max_images: MAX_IMAGES,
+            max_depth: MAX_DEPTH,
+            max_entries: MAX_ENTRIES,
+            files: Vec::new(),
+            entries: 0,
+            truncated: false,
+        }
+    }
+
+    fn dir(&mut self, dir: &Path, depth: usize) -> std::io::Result<()> {
+        let mut files = Vec::new();
+        let mut subdirs = Vec::new();
+        for entry in std::fs::read_dir(dir)? {
+            if self.entries == self.max_entries {
+                self.truncated = true;
+                break;
+            }
+            self.entries += 1;
+            let Ok(entry) = entry else { continue };
+            if entry.file_name().to_string_lossy().starts_with('.') {
+                continue;
+            }
+            let path = entry.path();
+            match entry.file_type() {
+                Ok(kind) if kind.is_dir() => subdirs.push(path),
+                // Follows file symlinks, unlike the entry's own type
+                Ok(_) if path.is_file() && validate::is_openable(&path) => files.push(path),
+                _ => {}
+            }
+        }
+
+        files.sort_by(|a, b| natural_cmp(&file_name(a), &file_name(b)));
+        let room = self.max_images - self.files.len();
+        if files.len() > room {
+            files.truncate(room);
+            self.truncated = true;
+        }
+        self.files.extend(files);
+        if !self.recursive || self.truncated || subdirs.is_empty() {
+            return Ok(());
+        }
+        if depth == self.max_depth {
+            eprintln!(
+                "Not searching below {}: more than {} levels deep",
+                dir.display(),
+                self.max_depth
+            );
+            return Ok(());
+        }
+
+        subdirs.sort_by(|a, b| natural_cmp(&file_name(a), &file_name(b)));
+        for subdir in subdirs {
+            if self.truncated {
+                break;
+            }
+            // An unreadable subdirectory should not hide everything else
+            if let Err(e) = self.dir(&subdir, depth + 1) {
+                eprintln!("Skipping {}: {}", subdir.display(), e);
+            }
+        }
+        Ok(())
+    }
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+/// Compare names case-insensitively, with runs of digits compared by value
+fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let (mut a_chunks, mut b_chunks) = (chunks(a), chunks(b));
+    loop {
+        match (a_chunks.next(), b_chunks.next()) {
+            (None, None) => return a.cmp(b),
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(x), Some(y)) => {
+                let order = match (is_number(x), is_number(y)) {
+                    (true, true) => {
+                        let (x, y) = (x.trim_start_matches('0'), y.trim_start_matches('0'));
+                        x.len().cmp(&y.len()).then_with(|| x.cmp(y))
+                    }
+                    _ => x.to_lowercase().cmp(&y.to_lowercase()),
+                };
+                if order != Ordering::Equal {
+                    return order;
+                }
+            }
+        }
+    }
+}
+
+fn is_number(chunk: &str) -> bool {
+    chunk.starts_with(|c: char| c.is_ascii_digit())
+}
+
+/// Split `s` into alternating runs of ASCII digits and everything else
+fn chunks(s: &str) -> impl Iterator<Item = &str> {
+    let mut rest = s;
+    std::iter::from_fn(move || {
+        let first = rest.chars().next()?;
+        let digits = first.is_ascii_digit();
+        let end = rest
+            .find(|c: char| c.is_ascii_digit() != digits)
+            .unwrap_or(rest.len());
+        let (chunk, tail) = rest.split_at(end);
+        rest = tail;
+        Some(chunk)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    fn png() -> Vec<u8> {
+        let mut bytes = Vec::new();
+        image::RgbaImage::new(1, 1)
+            .write_to(&mut Cursor::new(&mut bytes), image::ImageFormat::Png)
+            .unwrap();
+        bytes
+    }
+
+    /// A fresh directory unique to `test`, with a PNG at each of `images`
+    /// and a text file at each of `others`
+    fn tree(test: &str, images: &[&str], others: &[&str]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "ursamarkup-folders-{}-{}",
+            test,
+            std::process::id()
+        ));
+        std::fs::remove_dir_all(&dir).ok();
+        for (names, contents) in [(images, png()), (others, b"notes".to_vec())] {
+            for name in names {
+                let path = dir.join(name);
+                std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+                std::fs::write(path, &contents).unwrap();
+            }
+        }
+        dir
+    }
+
+    fn relative(dir: &Path, paths: &[PathBuf]) -> Vec<String> {
+        paths
+            .iter()
+            .map(|path| path.strip_prefix(dir).unwrap().display().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn sorts_names_naturally() {
+        let mut names = vec![
+            "shot-10.png",
+            "Shot-9.png",
+            "shot-2.png",
+            "shot-02.png",
+            "shot.png",
+            "a.png",
+        ];
+        names.sort_by(|a, b| natural_cmp(a, b));
+        assert_eq!(
+            names,
+            [
+                "a.png",
+                "shot-02.png",
+                "shot-2.png",
+                "Shot-9.png",
+                "shot-10.png",
+                "shot.png"
+            ]
+        );
+        assert_eq!(natural_cmp("b", "B"), Ordering::Greater);
+        assert_eq!(natural_cmp("img", "img1"), Ordering::Less);
+        assert_eq!(
+            natural_cmp("9999999999999999999999", "10"),
+            Ordering::Greater
+        );
+    }
+
+    #[test]
+    fn finds_images_by_content() {
+        let dir = tree(
+            "content",
+            &["shot-10", "shot-2.png", ".hidden.png", "sub/nested.png"],
+            &["notes.png", "readme.txt"],
+        );
+        let found = images(&dir, false).unwrap();
+        assert_eq!(relative(&dir, &found.paths), ["shot-2.png", "shot-10"]);
+        assert!(!found.truncated);
+    }
+
+    #[test]
+    fn recursive_walk_puts_subdirectories_after_their_parent() {
+        let dir = tree(
+            "recursive",
+            &[
+                "b.png",
+                "sub-10/c.png",
+                "sub-2/d.png",
+                "sub-2/deeper/e.png",
+                ".git/f.png",
+            ],
+            &[],
+        );
+        let found = images(&dir, true).unwrap();
+        assert_eq!(
+            relative(&dir, &found.paths),
+            ["b.png", "sub-2/d.png", "sub-2/deeper/e.png", "sub-10/c.png"]
+        );
+    }
+
+    #[test]
+    fn rejects_directories_without_images() {
+        let dir = tree("empty", &["sub/a.png"], &["notes.txt"]);
+        assert!(matches!(
+            images(&dir, false),
+            Err(RejectReason::NoImages { recursive: false })
+        ));
+        assert!(images(&dir, true).is_ok());
+    }
+
+    #[test]
+    fn stops_at_the_limits() {
+        let dir = tree(
+            "limits",
+            &["1.png", "2.png", "3.png", "a/4.png", "a/b/5.png"],
+            &[],
+        );
+        let walk = |configure: fn(&mut Walk)| {
+            let mut walk = Walk::new(true);
+            configure(&mut walk);
+            walk.dir(&dir, 0).unwrap();
+            (relative(&dir, &walk.files), walk.truncated)
+        };
+
+        let (files, truncated) = walk(|walk| walk.max_images = 2);
+        assert_eq!(
+            (files, truncated),
+            (vec!["1.png".into(), "2.png".into()], true)
+        );
+
+        // Too deep is skipped rather than truncated
+        let (files, truncated) = walk(|walk| walk.max_depth = 1);
+        assert_eq!(files.len(), 4);
+        assert!(!truncated);
+
+        let (files, truncated) = walk(|walk| walk.max_entries = 2);
+        assert!(files.len() <= 2);
+        assert!(truncated);
+    }
+}
@@ -0,0 +1,164 @@
+//! Turning file arguments into paths. Desktop launchers pass `file://` URIs
+//! (the `%U` field code of `.desktop` files), so those are accepted next to
+//! plain paths.
+
+use std::path::{Path, PathBuf};
+
+use percent_encoding::percent_decode_str;
+use url::Url;
+
+use crate::error::AppError;
+
+/// Absolute, canonical path for a file argument.
+///
+/// Relative paths are resolved against `cwd`. URIs other than local `file://`
+/// ones are rejected rather than mistaken for relative paths, and so is `-`,
+/// since standard input is read with `--pipe` instead.
+pub fn resolve(arg: &str, cwd: Option<&Path>) -> Result<PathBuf, AppError> {
+    if arg == "-" {
+        return Err(AppError::invalid(
+            "Cannot open -: use --pipe to read an image from standard input",
+        ));
+    }
+    let path = match uri_scheme(arg) {
+        Some(scheme) if scheme.eq_ignore_ascii_case("file") => file_uri_path(arg)?,
+        Some(scheme) => {
+            return Err(AppError::invalid(format!(
+                "Cannot open {}: unsupported URI scheme \"{}\", only file:// URIs can be opened",
+                arg, scheme
+            )))
+        }
+        None => plain_path(arg, cwd),
+    };
+    Ok(path.canonicalize().unwrap_or(path))
+}
+
+/// The scheme of `arg` if it is a URI. Single letters are Windows drives.
+fn uri_scheme(arg: &str) -> Option<&str> {
+    let (scheme, _) = arg.split_once(':')?;
+    let mut chars = scheme.chars();
+    let is_scheme = scheme.len() > 1
+        && chars.next().is_some_and(|c| c.is_ascii_alphabetic())
+        && chars.all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.'));
+    is_scheme.then_some(scheme)
+}
+
+fn file_uri_path(uri: &str) -> Result<PathBuf, AppError> {
+    let url =
+        Url::parse(uri).map_err(|e| AppError::invalid(format!("Cannot open {}: {}", uri, e)))?;
+    // `to_file_path` percent-decodes, and refuses hosts other than localhost
+    url.to_file_path()
+        .map_err(|_| AppError::invalid(format!("Cannot open {}: not a local file", uri)))
+}
+
+/// Some launchers percent-encode plain paths too; the decoded form is only
+/// used when the path as given does not exist, since `%` is valid in names
+fn plain_path(arg: &str, cwd: Option<&Path>) -> PathBuf {
+    let join = |path: &Path| match cwd {
+        Some(cwd) => cwd.join(path),
+        None => path.to_path_buf(),
+    };
+    let path = join(Path::new(arg));
+    if path.exists() || !arg.contains('%') {
+        return path;
+    }
+    match percent_decode_str(arg).decode_utf8() {
+        Ok(decoded) if join(Path::new(decoded.as_ref())).exists() => {
+            join(Path::new(decoded.as_ref()))
+        }
+        _ => path,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A canonical directory unique to `test`, holding an empty file `name`
+    fn dir_with(test: &str, name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("ursamarkup-inputs-{}-{}", test, std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join(name), []).unwrap();
+        dir.canonicalize().unwrap()
+    }
+
+    #[test]
+    fn resolves_relative_paths_against_cwd() {
+        let dir = dir_with("relative", "shot.png");
+        let resolved = resolve("shot.png", Some(&dir)).unwrap();
+        assert_eq!(resolved, dir.join("shot.png"));
+        assert!(resolved.is_absolute());
+
+        let parent = dir.parent().unwrap();
+        let name = dir.file_name().unwrap().to_str().unwrap();
+        let dotted = format!("./{}/../{}/shot.png", name, name);
+        assert_eq!(
+            resolve(&dotted, Some(parent)).unwrap(),
+            dir.join("shot.png")
+        );
+    }
+
+    #[test]
+    fn keeps_missing_paths_for_validation_to_report() {
+        let dir = dir_with("missing", "shot.png");
+        assert_eq!(
+            resolve("gone.png", Some(&dir)).unwrap(),
+            dir.join("gone.png")
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn resolves_file_uris() {
+        let dir = dir_with("uri", "my shot.png");
+        let expected = dir.join("my shot.png");
+        let encoded = format!("file://{}/my%20shot.png", dir.display());
+        assert_eq!(resolve(&encoded, None).unwrap(), expected);
+        let localhost = format!("FILE://localhost{}/my%20shot.png", dir.display());
+        assert_eq!(resolve(&localhost, None).unwrap(), expected);
+    }
+
+    #[test]
+    fn rejects_remote_and_other_uris() {
+        for arg in [
+            "file://example.com/shot.png",
+            "https://example.com/shot.png",
+            "data:image/png;base64,AAAA",
+        ] {
+            let error = resolve(arg, None).unwrap_err();
+            assert!(matches!(error, AppError::InvalidInput { .. }), "{}", arg);
+        }
+    }
+
+    #[test]
+    fn decodes_percent_encoded_paths_only_when_needed() {
+        let dir = dir_with("percent", "my shot.png");
+        assert_eq!(
+            resolve("my%20shot.png", Some(&dir)).unwrap(),
+            dir.join("my shot.png")
+        );
+
+        // A name with a literal `%` is taken as given
+        let dir = dir_with("literal", "100%25.png");
+        assert_eq!(
+            resolve("100%25.png", Some(&dir)).unwrap(),
+            dir.join("100%25.png")
+        );
+    }
+
+    #[test]
+    fn rejects_stdin_with_a_hint() {
+        let error = resolve("-", None).unwrap_err();
+        assert!(matches!(error, AppError::InvalidInput { .. }));
+        assert!(error.to_string().contains("--pipe"));
+    }
+
+    #[test]
+    fn treats_drive_letters_as_paths() {
+        assert_eq!(uri_scheme("C:/shots/a.png"), None);
+        assert_eq!(uri_scheme("file:///a.png"), Some("file"));
+        assert_eq!(uri_scheme("x-scheme+1:rest"), Some("x-scheme+1"));
+        assert_eq!(uri_scheme("1x:rest"), None);
+    }
+}
@@ -0,0 +1,302 @@
+//! The command line, shared by the first launch, the arguments a second
+//! instance forwards to the running one, and subcommands such as `render`
+//! that run without a window or tray.
+
+mod folders;
+mod inputs;
+mod pipe;
+mod render;
+mod session;
+mod validate;
+mod wait;
+
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+
+use clap::{Args, CommandFactory, Parser, Subcommand};
+use serde::Serialize;
+
+pub use pipe::{close_pipe_session, finish_pipe_session, read_pipe_input};
+pub use session::Sessions;
+pub use validate::{is_openable, AcceptedFile, RejectReason};
+pub use wait::finish_wait_session;
+
+#[derive(Parser)]
+#[command(
+    name = "ursamarkup",
+    version,
+    about = "Ursa Markup - Image annotation tool",
+    args_conflicts_with_subcommands = true
+)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+    /// Image files to open, or directories to open the images in
+    files: Vec<String>,
+    /// Also open images in subdirectories of directory arguments
+    #[arg(short, long)]
+    recursive: bool,
+    /// Read an image from stdin and write the annotated PNG to stdout once
+    /// its tab is saved or closed; exits with 1 if it is discarded
+    #[arg(long, conflicts_with = "files")]
+    pipe: bool,
+    /// Wait until the tabs of the files are closed; exits with 1 if changes
+    /// were discarded
+    #[arg(long, requires = "files", conflicts_with = "pipe")]
+    wait: bool,
+    /// With --wait, print the paths the files were saved to
+    #[arg(long, requires = "wait")]
+    print_path: bool,
+    #[command(flatten)]
+    flags: LaunchFlags,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Draw annotations onto an image and save the result, without opening a window
+    Render(render::RenderArgs),
+}
+
+/// Options given alongside the files to open
+#[derive(Args, Clone, Default, Serialize)]
+pub struct LaunchFlags {
+    /// Image piped to another process, which waits for the result
+    #[arg(long, hide = true)]
+    pub pipe_session: Option<String>,
+    /// Files another process waits on until their tabs are closed
+    #[arg(long, hide = true)]
+    pub wait_session: Option<String>,
+}
+
+/// Files for the frontend to open, with the options they were given
+#[derive(Clone, Default, Serialize)]
+pub struct OpenFilesPayload {
+    /// Files that passed validation, in the order they were given
+    pub accepted: Vec<AcceptedFile>,
+    /// Arguments and files that cannot be opened
+    pub rejected: Vec<RejectedFile>,
+    /// Working directory of the instance the files were given to
+    pub cwd: Option<String>,
+    pub flags: LaunchFlags,
+}
+
+#[derive(Clone, Serialize)]
+pub struct RejectedFile {
+    /// The argument as given, or the file's path if it came from a directory
+    pub argument: String,
+    pub reason: RejectReason,
+}
+
+impl OpenFilesPayload {
+    pub fn is_empty(&self) -> bool {
+        self.accepted.is_empty()
+            && self.rejected.is_empty()
+            && self.flags.pipe_session.is_none()
+            && self.flags.wait_session.is_none()
+    }
+
+    /// Validate the file for a resolved argument, or the images inside if it
+    /// is a directory
+    fn add_path(&mut self, argument: String, path: &Path, recursive: bool) {
+        if !path.is_dir() {
+            return self.add_file(argument, path);
+        }
+        match folders::images(path, recursive) {
+            Ok(images) => {
+                let opened = images.paths.len();
+                for image in images.paths {
+                    self.add_file(image.display().to_string(), &image);
+                }
+                if images.truncated {
+                    self.rejected.push(RejectedFile {
+                        argument,
+                        reason: RejectReason::TooManyImages { opened },
+                    });
+                }
+            }
+            Err(reason) => self.rejected.push(RejectedFile { argument, reason }),
+        }
+    }
+
+    /// Resolve and validate file arguments, relative to `cwd`
+    fn add_arguments(&mut self, arguments: Vec<String>, cwd: Option<&Path>, recursive: bool) {
+        for argument in arguments {
+            match inputs::resolve(&argument, cwd) {
+                Ok(path) => self.add_path(argument, &path, recursive),
+                Err(error) => self.rejected.push(RejectedFile {
+                    argument,
+                    reason: RejectReason::InvalidArgument {
+                        message: error.to_string(),
+                    },
+                }),
+            }
+        }
+    }
+
+    fn add_file(&mut self, argument: String, path: &Path) {
+        match validate::check(path) {
+            Ok(file) => self.accepted.push(file),
+            Err(reason) => self.rejected.push(RejectedFile { argument, reason }),
+        }
+    }
+
+    /// Take the sessions this launch came with, turning a failure into an
+    /// error for the frontend to show
+    pub fn accept_sessions(&mut self, sessions: &Sessions) {
+        let flags = &mut self.flags;
+        for (arg, session) in [
+            (pipe::SESSION_ARG, &mut flags.pipe_session),
+            (wait::SESSION_ARG, &mut flags.wait_session),
+        ] {
+            let Some(id) = session else {
+                continue;
+            };
+            if let Err(error) = sessions.accept(id) {
+                self.rejected.push(RejectedFile {
+                    argument: format!("{} {}", arg, id),
+                    reason: RejectReason::InvalidArgument {
+                        message: error.to_string(),
+                    },
+                });
+                *session = None;
+            }
+        }
+    }
+}
+
+/// Run a headless subcommand, or print help or the version, if `args` asks
+/// for one; returns the exit code. `None` means the GUI should start.
+pub fn run_headless<I, T>(args: I) -> Option<i32>
+where
+    I: IntoIterator<Item = T>,
+    T: Into<OsString> + Clone,
+{
+    let args: Vec<OsString> = args.into_iter().map(Into::into).collect();
+    let is_subcommand = args
+        .get(1)
+        .and_then(|arg| arg.to_str())
+        .is_some_and(|arg| Cli::command().find_subcommand(arg).is_some());
+
+    match Cli::try_parse_from(&args) {
+        Ok(Cli {
+            command: Some(Command::Render(args)),
+            ..
+        }) => Some(render::run(&args)),
+        Ok(Cli { pipe: true, .. }) => Some(pipe::run_client()),
+        Ok(Cli {
+            wait: true,
+            files,
+            recursive,
+            print_path,
+            ..
+        }) => Some(wait::run_client(files, recursive, print_path)),
+        Ok(_) => None,
+        Err(e) if is_subcommand || !e.use_stderr() => {
+            // Help and version go to stdout, usage errors to stderr
+            e.print().ok();
+            Some(e.exit_code())
+        }
+        // Reported again by `launch_payload` once the GUI is up
+        Err(_) => None,
+    }
+}
+
+/// Parse the arguments of a GUI launch, resolving relative paths against `cwd`
+pub fn launch_payload<I, T>(args: I, cwd: Option<&Path>) -> Result<OpenFilesPayload, clap::Error>
+where
+    I: IntoIterator<Item = T>,
+    T: Into<OsString> + Clone,
+{
+    let cli = Cli::try_parse_from(args)?;
+    let mut payload = OpenFilesPayload {
+        cwd: cwd.map(|cwd| cwd.display().to_string()),
+        flags: cli.flags,
+        ..Default::default()
+    };
+    payload.add_arguments(cli.files, cwd, cli.recursive);
+    Ok(payload)
+}
+
+/// The images in `dir`, chosen from the tray, as if it had been passed on
+/// the command line
+pub fn folder_payload(dir: &Path) -> OpenFilesPayload {
+    let mut payload = OpenFilesPayload::default();
+    payload.add_path(dir.display().to_string(), dir, false);
+    payload
+}
+
+type LaunchJob = Box<dyn FnOnce() -> Option<OpenFilesPayload> + Send>;
+
+/// Builds payloads on a thread of its own, since validating files and
+/// walking directories reads from disk and would stall the main thread.
+/// Jobs run one at a time, so files open in the order they were given.
+pub struct LaunchWorker {
+    jobs: mpsc::Sender<LaunchJob>,
+}
+
+impl LaunchWorker {
+    /// Start the worker, which hands each payload to `deliver`
+    pub fn new(deliver: impl Fn(OpenFilesPayload) + Send + 'static) -> Self {
+        let (jobs, queue) = mpsc::channel::<LaunchJob>();
+        std::thread::spawn(move || {
+            for job in queue {
+                if let Some(payload) = job() {
+                    deliver(payload);
+                }
+            }
+        });
+        Self { jobs }
+    }
+
+    pub fn submit(&self, job: impl FnOnce() -> Option<OpenFilesPayload> + Send + 'static) {
+        // The worker only stops with the app
+        self.jobs.send(Box::new(job)).ok();
+    }
+
+    /// Queue the arguments of a launch; see [`launch_payload`]
+    pub fn launch(&self, args: Vec<OsString>, cwd: Option<PathBuf>) {
+        self.submit(move || match launch_payload(args, cwd.as_deref()) {
+            Ok(payload) => Some(payload),
+            Err(e) => {
+                eprintln!("Ignoring command line arguments: {}", e);
+                None
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn worker_delivers_launches_in_order() {
+        let (sender, delivered) = mpsc::channel();
+        let worker = LaunchWorker::new(move |payload| sender.send(payload.cwd).unwrap());
+
+        worker.submit(|| {
+            std::thread::sleep(Duration::from_millis(50));
+            Some(OpenFilesPayload {
+                cwd: Some("first".into()),
+                ..Default::default()
+            })
+        });
+        // Unparseable arguments deliver nothing
+        worker.launch(vec!["ursamarkup".into(), "--no-such-flag".into()], None);
+        worker.launch(vec!["ursamarkup".into()], Some(PathBuf::from("second")));
+
+        let timeout = Duration::from_secs(5);
+        assert_eq!(
+            delivered.recv_timeout(timeout).unwrap().as_deref(),
+            Some("first")
+        );
+        assert_eq!(
+            delivered.recv_timeout(timeout).unwrap().as_deref(),
+            Some("second")
+        );
+        assert!(delivered.recv_timeout(Duration::from_millis(100)).is_err());
+    }
+}
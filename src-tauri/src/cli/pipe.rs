@@ -0,0 +1,219 @@
+//! `ursamarkup --pipe`: annotate an image read from stdin and write the
+//! result to stdout, for screenshot scripts such as `grim -g "$(slurp)" - |
+//! ursamarkup --pipe > shot.png`.
+//!
+//! The image goes to the GUI through a session (see `session`), which
+//! writes the result back as `output.png`.
+
+use std::io::{Read, Write};
+use std::path::Path;
+
+use tauri::ipc::{Request, Response};
+use tauri::State;
+
+use super::session::{cancel, finish, session_dir, ClientSession, Sessions};
+use super::validate::MAX_FILE_SIZE;
+use crate::error::AppError;
+use crate::headers::{framed, rgba_body, text_header};
+use crate::imaging::{self, ExportFormat, Metadata};
+
+/// The annotated image was written to stdout
+pub const EXIT_OK: i32 = 0;
+/// The tab was closed without keeping the result, or the app quit
+pub const EXIT_CANCELLED: i32 = 1;
+/// Nothing usable on stdin, or the GUI could not be reached or died first
+pub const EXIT_ERROR: i32 = 2;
+
+/// `LaunchFlags::pipe_session`
+pub const SESSION_ARG: &str = "--pipe-session";
+
+const INPUT_FILE: &str = "input";
+const OUTPUT_FILE: &str = "output.png";
+
+/// Entry point of `ursamarkup --pipe`. Returns the process exit code.
+pub fn run_client() -> i32 {
+    match pipe_through_gui() {
+        Ok(Some(png)) => {
+            let mut stdout = std::io::stdout().lock();
+            match stdout.write_all(&png).and_then(|_| stdout.flush()) {
+                Ok(()) => EXIT_OK,
+                Err(e) => {
+                    eprintln!("ursamarkup --pipe: {}", e);
+                    EXIT_ERROR
+                }
+            }
+        }
+        Ok(None) => EXIT_CANCELLED,
+        Err(e) => {
+            eprintln!("ursamarkup --pipe: {}", e);
+            EXIT_ERROR
+        }
+    }
+}
+
+/// The annotated PNG, or `None` when cancelled
+fn pipe_through_gui() -> Result<Option<Vec<u8>>, AppError> {
+    let input = read_limited(std::io::stdin().lock(), MAX_FILE_SIZE)?;
+    if image::guess_format(&input).is_err() {
+        return Err(AppError::decode(None, "stdin is not a supported image"));
+    }
+
+    let session = ClientSession::create()?;
+    let path = session.dir.join(INPUT_FILE);
+    std::fs::write(&path, &input).map_err(|e| AppError::io(Some(&path), e))?;
+    session.spawn_gui([SESSION_ARG, &session.id])?;
+    session.wait_for(OUTPUT_FILE)
+}
+
+/// Read all of `reader`, refusing more than `limit` bytes like a file given
+/// as an argument would be
+fn read_limited(reader: impl Read, limit: u64) -> Result<Vec<u8>, AppError> {
+    let mut input = Vec::new();
+    reader.take(limit + 1).read_to_end(&mut input)?;
+    if input.len() as u64 > limit {
+        return Err(AppError::invalid(format!(
+            "stdin is more than the {} MiB limit",
+            limit / (1024 * 1024)
+        )));
+    }
+    Ok(input)
+}
+
+fn read_input(dir: &Path) -> Result<Vec<u8>, AppError> {
+    let path = dir.join(INPUT_FILE);
+    std::fs::read(&path).map_err(|e| AppError::io(Some(&path), e))
+}
+
+fn encode_png(pixels: &[u8], width: u32, height: u32) -> Result<Vec<u8>, AppError> {
+    let format = ExportFormat::for_path(Path::new(OUTPUT_FILE));
+    imaging::encode_rgba(pixels, width, height, format, &Metadata::default())
+}
+
+/// Decode a piped image for a tab.
+///
+/// The response is framed like `decode_image`'s: image info JSON, then the
+/// RGBA pixels.
+#[tauri::command]
+pub async fn read_pipe_input(session: String) -> Result<Response, AppError> {
+    let decoded = tokio::task::spawn_blocking(move || {
+        let input = read_input(&session_dir(&session)?)?;
+        imaging::decode_bytes(&input, None)
+    })
+    .await
+    .map_err(|e| AppError::internal(format!("Task join error: {}", e)))??;
+
+    Ok(Response::new(framed(&decoded.info, decoded.rgba.as_raw())?))
+}
+
+/// Send the annotated image to the waiting process.
+///
+/// Takes the same RGBA body and `x-width`/`x-height` headers as
+/// `export_image`, plus the session id in `x-session`.
+#[tauri::command]
+pub async fn finish_pipe_session(
+    request: Request<'_>,
+    sessions: State<'_, Sessions>,
+) -> Result<(), AppError> {
+    let (width, height, pixels) = rgba_body(&request)?;
+    let id = text_header(&request, "x-session")
+        .ok_or_else(|| AppError::invalid("Missing header: x-session"))?;
+    let Some(dir) = sessions.take(&id)? else {
+        return Err(AppError::not_found(format!(
+            "Pipe session {} is closed",
+            id
+        )));
+    };
+    let pixels = pixels.to_vec();
+
+    tokio::task::spawn_blocking(move || {
+        finish(&dir, OUTPUT_FILE, &encode_png(&pixels, width, height)?)
+    })
+    .await
+    .map_err(|e| AppError::internal(format!("Task join error: {}", e)))?
+}
+
+/// End a session whose tab was closed without confirming. Unless `discard`
+/// is set, the unchanged image is sent back as a PNG.
+#[tauri::command]
+pub async fn close_pipe_session(
+    session: String,
+    discard: bool,
+    sessions: State<'_, Sessions>,
+) -> Result<(), AppError> {
+    // Already finished from the tab
+    let Some(dir) = sessions.take(&session)? else {
+        return Ok(());
+    };
+    tokio::task::spawn_blocking(move || close(&dir, discard))
+        .await
+        .map_err(|e| AppError::internal(format!("Task join error: {}", e)))?
+}
+
+fn close(dir: &Path, discard: bool) -> Result<(), AppError> {
+    if discard {
+        cancel(dir);
+        return Ok(());
+    }
+    let rgba = imaging::decode_bytes(&read_input(dir)?, None)?.rgba;
+    let png = encode_png(rgba.as_raw(), rgba.width(), rgba.height())?;
+    finish(dir, OUTPUT_FILE, &png)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::session::CANCELLED_FILE;
+    use super::*;
+
+    fn pipe_dir(test: &str) -> std::path::PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("ursamarkup-pipe-{}-{}", test, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let mut input = Vec::new();
+        image::RgbaImage::from_pixel(2, 2, image::Rgba([10, 20, 30, 255]))
+            .write_to(
+                &mut std::io::Cursor::new(&mut input),
+                image::ImageFormat::Bmp,
+            )
+            .unwrap();
+        std::fs::write(dir.join(INPUT_FILE), input).unwrap();
+        dir
+    }
+
+    #[test]
+    fn caps_stdin() {
+        assert_eq!(read_limited(&[7u8; 16][..], 16).unwrap(), [7; 16]);
+        let error = read_limited(&[7u8; 17][..], 16).unwrap_err();
+        assert!(matches!(error, AppError::InvalidInput { .. }));
+    }
+
+    #[test]
+    fn closing_sends_back_the_unchanged_image_as_png() {
+        let dir = pipe_dir("keep");
+        close(&dir, false).unwrap();
+        let output = std::fs::read(dir.join(OUTPUT_FILE)).unwrap();
+        let cancelled = dir.join(CANCELLED_FILE).exists();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert!(!cancelled);
+        assert_eq!(
+            image::guess_format(&output).unwrap(),
+            image::ImageFormat::Png
+        );
+        let image = image::load_from_memory(&output).unwrap().to_rgba8();
+        assert_eq!(image.dimensions(), (2, 2));
+        assert!(image.pixels().all(|px| px.0 == [10, 20, 30, 255]));
+    }
+
+    #[test]
+    fn discarding_cancels_the_session() {
+        let dir = pipe_dir("discard");
+        close(&dir, true).unwrap();
+        let output = dir.join(OUTPUT_FILE).exists();
+        let cancelled = dir.join(CANCELLED_FILE).exists();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert!(!output);
+        assert!(cancelled);
+    }
+}
@@ -0,0 +1,322 @@
+//! `ursamarkup render`: flatten annotations onto an image without a window,
+//! for regenerating annotated screenshots in CI.
+
+use std::path::{Path, PathBuf};
+
+use clap::Args;
+
+use crate::error::AppError;
+use crate::imaging::{self, ExportFormat, Metadata};
+use crate::project;
+use crate::render::{self, StrokeHistory};
+
+/// Rendered and written
+pub const EXIT_OK: i32 = 0;
+/// Rendering or encoding failed
+pub const EXIT_FAILURE: i32 = 1;
+/// Bad arguments; also what clap exits with
+pub const EXIT_USAGE: i32 = 2;
+/// The input image is missing or cannot be decoded
+pub const EXIT_INPUT: i32 = 3;
+/// The annotations are missing or malformed
+pub const EXIT_ANNOTATIONS: i32 = 4;
+/// The output could not be written
+pub const EXIT_OUTPUT: i32 = 5;
+
+#[derive(Args)]
+pub struct RenderArgs {
+    /// Image to draw on
+    input: PathBuf,
+    /// Stroke history JSON, a .ursa project or a PNG exported with its project
+    #[arg(long, short)]
+    annotations: PathBuf,
+    /// Where to write the result
+    #[arg(long, short)]
+    out: PathBuf,
+    /// Output format; defaults to the extension of --out, which it must match
+    #[arg(long, value_parser = ["png", "jpg", "jpeg", "webp"])]
+    format: Option<String>,
+    /// JPEG quality, or lossy WebP quality (1-100)
+    #[arg(long, value_parser = clap::value_parser!(u8).range(1..=100))]
+    quality: Option<u8>,
+    /// Resize the output by this factor
+    #[arg(long, default_value_t = 1.0, value_parser = parse_scale)]
+    scale: f32,
+}
+
+fn parse_scale(value: &str) -> Result<f32, String> {
+    match value.parse::<f32>() {
+        Ok(scale) if scale.is_finite() && scale > 0.0 => Ok(scale),
+        _ => Err("expected a number greater than 0".to_string()),
+    }
+}
+
+/// Run the subcommand, returning the exit code
+pub fn run(args: &RenderArgs) -> i32 {
+    match render_file(args) {
+        Ok(()) => EXIT_OK,
+        Err((code, e)) => {
+            eprintln!("ursamarkup render: {}", e);
+            code
+        }
+    }
+}
+
+fn render_file(args: &RenderArgs) -> Result<(), (i32, AppError)> {
+    let format = output_format(args).map_err(|e| (EXIT_USAGE, e))?;
+    let history = load_history(&args.annotations).map_err(|e| (EXIT_ANNOTATIONS, e))?;
+    let base = imaging::decode_file(&args.input).map_err(|e| (EXIT_INPUT, e))?;
+
+    let rendered =
+        render::render(&base.rgba, &history, args.scale).map_err(|e| (EXIT_FAILURE, e))?;
+    let bytes = imaging::encode_rgba(
+        rendered.as_raw(),
+        rendered.width(),
+        rendered.height(),
+        format,
+        &Metadata::default(),
+    )
+    .map_err(|e| (EXIT_FAILURE, e))?;
+    imaging::write_atomic(&args.out, &bytes, false).map_err(|e| (EXIT_OUTPUT, e))?;
+    Ok(())
+}
+
+/// `--format` or the output's extension, with `--quality` applied
+fn output_format(args: &RenderArgs) -> Result<ExportFormat, AppError> {
+    let format = match &args.format {
+        Some(name) => {
+            let format = ExportFormat::named(name)
+                .ok_or_else(|| AppError::invalid(format!("Unknown format: {}", name)))?;
+            let extension = args.out.extension().unwrap_or_default().to_string_lossy();
+            // A .png file holding a JPEG would only confuse whoever opens it
+            if ExportFormat::named(&extension).is_some_and(|named| {
+                std::mem::discriminant(&named) != std::mem::discriminant(&format)
+            }) {
+                return Err(AppError::invalid(format!(
+                    "--format {} does not match {}",
+                    name,
+                    args.out.display()
+                )));
+            }
+            format
+        }
+        None => ExportFormat::for_path(&args.out),
+    };
+    let Some(quality) = args.quality else {
+        return Ok(format);
+    };
+    Ok(match format {
+        ExportFormat::Jpeg { subsampling, .. } => ExportFormat::Jpeg {
+            quality,
+            subsampling,
+        },
+        ExportFormat::WebP { .. } => ExportFormat::WebP {
+            lossless: false,
+            quality,
+        },
+        ExportFormat::Png { .. } => {
+            eprintln!("ursamarkup render: --quality has no effect on PNG output");
+            format
+        }
+    })
+}
+
+/// The stroke history from a JSON file, or from a project
+fn load_history(path: &Path) -> Result<StrokeHistory, AppError> {
+    let bytes = std::fs::read(path).map_err(|e| AppError::io(Some(path), e))?;
+    let invalid = |e: serde_json::Error| AppError::invalid(format!("{}: {}", path.display(), e));
+
+    let history = if project::is_project_path(path) {
+        project::read_project(&bytes)?.state.history
+    } else if image::guess_format(&bytes).is_ok_and(|f| f == image::ImageFormat::Png) {
+        let archive = project::embedded_project(&bytes)?.ok_or_else(|| {
+            AppError::not_found(format!("No editable annotations in {}", path.display()))
+        })?;
+        project::read_project(&archive)?.state.history
+    } else {
+        return serde_json::from_slice(&bytes).map_err(invalid);
+    };
+    serde_json::from_value(history).map_err(invalid)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::imaging::ChromaSubsampling;
+    use crate::project::ProjectState;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("ursamarkup-render-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn history() -> serde_json::Value {
+        json!({
+            "groups": [{
+                "strokes": [{
+                    "color": "#000",
+                    "toolConfig": { "tool": "pen", "size": 2, "opacity": 100 },
+                    "points": [{ "x": 1, "y": 4 }, { "x": 7, "y": 4 }]
+                }]
+            }],
+            "currentIndex": 0
+        })
+    }
+
+    fn png() -> Vec<u8> {
+        let mut bytes = Vec::new();
+        image::RgbaImage::from_pixel(8, 8, image::Rgba([255, 255, 255, 255]))
+            .write_to(
+                &mut std::io::Cursor::new(&mut bytes),
+                image::ImageFormat::Png,
+            )
+            .unwrap();
+        bytes
+    }
+
+    /// Render `input.png` with `history.json` to `out.png`, all in `dir`
+    fn args_in(dir: &Path) -> RenderArgs {
+        RenderArgs {
+            input: dir.join("input.png"),
+            annotations: dir.join("history.json"),
+            out: dir.join("out.png"),
+            format: None,
+            quality: None,
+            scale: 1.0,
+        }
+    }
+
+    fn fixture(name: &str) -> PathBuf {
+        let dir = temp_dir(name);
+        std::fs::write(dir.join("input.png"), png()).unwrap();
+        std::fs::write(dir.join("history.json"), history().to_string()).unwrap();
+        dir
+    }
+
+    fn output_args(out: &str, format: Option<&str>, quality: Option<u8>) -> RenderArgs {
+        RenderArgs {
+            out: PathBuf::from(out),
+            format: format.map(String::from),
+            quality,
+            ..args_in(Path::new(""))
+        }
+    }
+
+    #[test]
+    fn renders_onto_the_input() {
+        let dir = fixture("ok");
+        let args = args_in(&dir);
+        let code = run(&args);
+        let rendered = image::open(&args.out).map(|image| image.to_rgba8());
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(code, EXIT_OK);
+        let rendered = rendered.unwrap();
+        assert_eq!(rendered.get_pixel(4, 4).0, [0, 0, 0, 255]);
+        assert_eq!(rendered.get_pixel(4, 0).0, [255, 255, 255, 255]);
+    }
+
+    #[test]
+    fn exit_codes_name_what_failed() {
+        let dir = fixture("codes");
+        std::fs::write(dir.join("broken.json"), r#"{ "groups": "#).unwrap();
+        let run_with = |edit: &dyn Fn(&mut RenderArgs)| {
+            let mut args = args_in(&dir);
+            edit(&mut args);
+            run(&args)
+        };
+
+        let codes = [
+            run_with(&|args| args.input = dir.join("missing.png")),
+            run_with(&|args| args.annotations = dir.join("broken.json")),
+            run_with(&|args| args.annotations = dir.join("missing.json")),
+            // The output's directory is a file
+            run_with(&|args| args.out = dir.join("input.png").join("out.png")),
+            run_with(&|args| args.format = Some("jpeg".into())),
+        ];
+        let written = dir.join("out.png").exists();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(
+            codes,
+            [
+                EXIT_INPUT,
+                EXIT_ANNOTATIONS,
+                EXIT_ANNOTATIONS,
+                EXIT_OUTPUT,
+                EXIT_USAGE
+            ]
+        );
+        assert!(!written);
+    }
+
+    #[test]
+    fn picks_the_output_format() {
+        let format = |out, format, quality| output_format(&output_args(out, format, quality));
+
+        assert!(matches!(
+            format("out.PNG", None, None),
+            Ok(ExportFormat::Png { .. })
+        ));
+        assert!(matches!(
+            format("out.jpg", Some("jpeg"), Some(70)),
+            Ok(ExportFormat::Jpeg {
+                quality: 70,
+                subsampling: ChromaSubsampling::Yuv420
+            })
+        ));
+        // --quality makes WebP lossy
+        assert!(matches!(
+            format("out.webp", None, Some(60)),
+            Ok(ExportFormat::WebP {
+                lossless: false,
+                quality: 60
+            })
+        ));
+        // Without a known extension, --format decides
+        assert!(matches!(
+            format("out", Some("webp"), None),
+            Ok(ExportFormat::WebP { .. })
+        ));
+        assert!(format("out.png", Some("jpg"), None).is_err());
+        assert!(format("out.webp", Some("png"), None).is_err());
+    }
+
+    #[test]
+    fn loads_history_from_json_and_projects() {
+        let dir = temp_dir("history");
+        let state = ProjectState {
+            history: history(),
+            view: json!({ "zoom": 1.0 }),
+        };
+        let archive = project::write_project(&png(), &state).unwrap();
+        std::fs::write(dir.join("history.json"), history().to_string()).unwrap();
+        std::fs::write(dir.join("shot.ursa"), &archive).unwrap();
+        let format = ExportFormat::named("png").unwrap();
+        let metadata = Metadata {
+            project: Some(archive),
+            ..Metadata::default()
+        };
+        let exported = imaging::encode_rgba(&[0; 4], 1, 1, format, &metadata).unwrap();
+        std::fs::write(dir.join("exported.png"), exported).unwrap();
+        std::fs::write(dir.join("plain.png"), png()).unwrap();
+
+        let loaded: Vec<_> = ["history.json", "shot.ursa", "exported.png", "plain.png"]
+            .iter()
+            .map(|name| load_history(&dir.join(name)))
+            .collect();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        for history in &loaded[..3] {
+            let history = history.as_ref().unwrap();
+            assert_eq!(history.current_index, 0);
+            assert_eq!(history.groups[0].strokes.len(), 1);
+        }
+        assert!(matches!(loaded[3], Err(AppError::NotFound { .. })));
+    }
+}
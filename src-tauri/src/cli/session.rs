@@ -0,0 +1,392 @@
+//! Sessions that let a command line process wait on a tab in the GUI.
+//!
+//! The waiting process never shows a window itself. It creates a session
+//! directory and starts a copy of the app with a session flag, which either
+//! becomes the GUI or, through the single-instance plugin, hands the session
+//! to the one already running. The GUI writes the result (or a cancel
+//! marker) back into the directory, which the waiting process polls for.
+//! Its output is therefore done as soon as the tab is, even though the app
+//! keeps running in the tray.
+//!
+//! Session directories live in a per-user directory that nobody else may
+//! read or write, since the GUI opens whatever input it finds there. While
+//! the GUI holds a session it rewrites the accepted marker every few
+//! seconds, so the waiting process notices when the app dies without
+//! answering.
+
+use std::collections::HashSet;
+use std::ffi::OsString;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::error::AppError;
+use crate::imaging;
+
+/// Written by the GUI once it has taken the session, and rewritten with its
+/// process id every [`HEARTBEAT_INTERVAL`] while it holds it
+const ACCEPTED_FILE: &str = "accepted";
+pub(super) const CANCELLED_FILE: &str = "cancelled";
+
+/// How long the GUI may take to start and pick up the session
+const ACCEPT_TIMEOUT: Duration = Duration::from_secs(30);
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(2);
+/// How long the accepted marker may go unchanged before the GUI is taken
+/// for dead
+const HEARTBEAT_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// Per-user directory holding one subdirectory per session, created private
+/// to this user if it does not exist yet
+fn sessions_dir() -> Result<PathBuf, AppError> {
+    let dir = match std::env::var_os("XDG_RUNTIME_DIR") {
+        Some(runtime) => PathBuf::from(runtime).join("ursamarkup-sessions"),
+        // Shared with other users on most systems, so the name cannot be
+        // one they could have taken first
+        None => std::env::temp_dir().join(format!("ursamarkup-sessions-{}", user_id())),
+    };
+    match private_dir_builder().create(&dir) {
+        Ok(()) => Ok(dir),
+        Err(e) if e.kind() == ErrorKind::AlreadyExists => {
+            check_private(&dir)?;
+            Ok(dir)
+        }
+        Err(e) => Err(AppError::io(Some(&dir), e)),
+    }
+}
+
+#[cfg(unix)]
+fn user_id() -> u32 {
+    // SAFETY: geteuid has no preconditions and cannot fail
+    unsafe { libc::geteuid() }
+}
+
+#[cfg(not(unix))]
+fn user_id() -> String {
+    std::env::var("USERNAME").unwrap_or_default()
+}
+
+/// Creates a single directory, failing if it exists, only its owner can enter
+fn private_dir_builder() -> std::fs::DirBuilder {
+    #[allow(unused_mut)]
+    let mut builder = std::fs::DirBuilder::new();
+    #[cfg(unix)]
+    std::os::unix::fs::DirBuilderExt::mode(&mut builder, 0o700);
+    builder
+}
+
+/// Refuse a directory that is a symlink, or on Unix belongs to someone else
+/// or is open to other users
+fn check_private(dir: &Path) -> Result<(), AppError> {
+    let metadata = std::fs::symlink_metadata(dir).map_err(|e| AppError::io(Some(dir), e))?;
+    let refuse = |reason: &str| AppError::PermissionDenied {
+        path: Some(dir.display().to_string()),
+        message: format!("Refusing to use the sessions directory: {}", reason),
+    };
+    if !metadata.is_dir() {
+        return Err(refuse("not a directory"));
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        if metadata.uid() != user_id() {
+            return Err(refuse("owned by another user"));
+        }
+        if metadata.mode() & 0o077 != 0 {
+            return Err(refuse("accessible to other users"));
+        }
+    }
+    Ok(())
+}
+
+/// Directory of session `id`. Ids come from the command line of another
+/// process, so anything that could leave the sessions directory is refused.
+pub fn session_dir(id: &str) -> Result<PathBuf, AppError> {
+    let is_valid = !id.is_empty() && id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-');
+    if !is_valid {
+        return Err(AppError::invalid(format!("Invalid session: {}", id)));
+    }
+    Ok(sessions_dir()?.join(id))
+}
+
+/// A session created by the waiting process, removed again when dropped
+pub struct ClientSession {
+    pub id: String,
+    pub dir: PathBuf,
+}
+
+impl ClientSession {
+    pub fn create() -> Result<Self, AppError> {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_nanos())
+            .unwrap_or_default();
+        let id = format!("{}-{}", std::process::id(), nanos);
+        let dir = session_dir(&id)?;
+        // Fails if the directory exists, so nothing planted there is used
+        private_dir_builder()
+            .create(&dir)
+            .map_err(|e| AppError::io(Some(&dir), e))?;
+        Ok(Self { id, dir })
+    }
+
+    /// Start the GUI with `args`, detached so it outlives this process
+    pub fn spawn_gui<I, T>(&self, args: I) -> Result<(), AppError>
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<OsString>,
+    {
+        let exe = std::env::current_exe()?;
+        let mut command = Command::new(&exe);
+        command
+            .args(args.into_iter().map(Into::into))
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null());
+        #[cfg(unix)]
+        std::os::unix::process::CommandExt::process_group(&mut command, 0);
+        #[cfg(windows)]
+        {
+            const DETACHED_PROCESS: u32 = 0x0000_0008;
+            std::os::windows::process::CommandExt::creation_flags(&mut command, DETACHED_PROCESS);
+        }
+        command
+            .spawn()
+            .map(drop)
+            .map_err(|e| AppError::io(Some(&exe), e))
+    }
+
+    /// Block until the GUI writes `result_file`, returning its contents, or
+    /// cancels the session (`None`). Fails if the GUI does not take the
+    /// session in time, or stops its heartbeat before answering.
+    pub fn wait_for(&self, result_file: &str) -> Result<Option<Vec<u8>>, AppError> {
+        self.poll(result_file, ACCEPT_TIMEOUT, HEARTBEAT_TIMEOUT)
+    }
+
+    fn poll(
+        &self,
+        result_file: &str,
+        accept_timeout: Duration,
+        heartbeat_timeout: Duration,
+    ) -> Result<Option<Vec<u8>>, AppError> {
+        let started = Instant::now();
+        let result = self.dir.join(result_file);
+        let accepted = self.dir.join(ACCEPTED_FILE);
+        // Last change of the marker, and when this process first saw it.
+        // Elapsed time is measured locally so clock changes do not count.
+        let mut last_beat: Option<(SystemTime, Instant)> = None;
+        loop {
+            if result.exists() {
+                return std::fs::read(&result)
+                    .map(Some)
+                    .map_err(|e| AppError::io(Some(&result), e));
+            }
+            if self.dir.join(CANCELLED_FILE).exists() {
+                return Ok(None);
+            }
+            match std::fs::metadata(&accepted).and_then(|m| m.modified()) {
+                Ok(modified) => match last_beat {
+                    Some((seen, _)) if seen == modified => {}
+                    _ => last_beat = Some((modified, Instant::now())),
+                },
+                Err(_) if last_beat.is_none() && started.elapsed() > accept_timeout => {
+                    return Err(AppError::timeout("Starting Ursa Markup", accept_timeout));
+                }
+                Err(_) => {}
+            }
+            if last_beat.is_some_and(|(_, at)| at.elapsed() > heartbeat_timeout) {
+                return Err(AppError::unavailable(
+                    Some("Ursa Markup"),
+                    "the app quit or stopped responding before finishing",
+                ));
+            }
+            std::thread::sleep(POLL_INTERVAL);
+        }
+    }
+}
+
+impl Drop for ClientSession {
+    fn drop(&mut self) {
+        std::fs::remove_dir_all(&self.dir).ok();
+    }
+}
+
+/// Sessions the GUI has taken and not yet answered
+#[derive(Default)]
+pub struct Sessions {
+    active: Arc<Mutex<HashSet<String>>>,
+    heartbeat: OnceLock<()>,
+}
+
+impl Sessions {
+    /// Take session `id`, telling the waiting process the GUI has it
+    pub fn accept(&self, id: &str) -> Result<(), AppError> {
+        let dir = session_dir(id)?;
+        if !dir.is_dir() {
+            return Err(AppError::not_found(format!(
+                "Session {} does not exist",
+                id
+            )));
+        }
+        beat(&dir).map_err(|e| AppError::io(Some(&dir.join(ACCEPTED_FILE)), e))?;
+        self.active.lock().unwrap().insert(id.to_string());
+        self.heartbeat.get_or_init(|| self.start_heartbeat());
+        Ok(())
+    }
+
+    /// Keep touching the accepted marker of every open session for as long
+    /// as the app runs
+    fn start_heartbeat(&self) {
+        let active = Arc::clone(&self.active);
+        std::thread::spawn(move || loop {
+            std::thread::sleep(HEARTBEAT_INTERVAL);
+            let ids: Vec<String> = active.lock().unwrap().iter().cloned().collect();
+            for id in ids {
+                // Fails once the waiting process has gone and removed it
+                if let Ok(dir) = session_dir(&id) {
+                    beat(&dir).ok();
+                }
+            }
+        });
+    }
+
+    /// Remove session `id`, returning its directory if it was still open
+    pub fn take(&self, id: &str) -> Result<Option<PathBuf>, AppError> {
+        let dir = session_dir(id)?;
+        Ok(self.active.lock().unwrap().remove(id).then_some(dir))
+    }
+
+    /// Cancel every open session, since nobody is left to finish them
+    pub fn cancel_all(&self) {
+        let ids: Vec<String> = self.active.lock().unwrap().drain().collect();
+        for id in ids {
+            if let Ok(dir) = session_dir(&id) {
+                cancel(&dir);
+            }
+        }
+    }
+}
+
+fn beat(dir: &Path) -> std::io::Result<()> {
+    std::fs::write(dir.join(ACCEPTED_FILE), std::process::id().to_string())
+}
+
+/// Tell the waiting process its session ended without a result
+pub fn cancel(dir: &Path) {
+    let path = dir.join(CANCELLED_FILE);
+    if let Err(e) = std::fs::write(&path, []) {
+        eprintln!("Failed to write {}: {}", path.display(), e);
+    }
+}
+
+/// Write the result of a session, all at once so the waiting process never
+/// reads half of it
+pub fn finish(dir: &Path, result_file: &str, contents: &[u8]) -> Result<(), AppError> {
+    imaging::write_atomic(&dir.join(result_file), contents, false).map(drop)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_path(test: &str) -> PathBuf {
+        std::env::temp_dir().join(format!(
+            "ursamarkup-session-{}-{}",
+            test,
+            std::process::id()
+        ))
+    }
+
+    /// A session in a fresh directory, removed again when dropped
+    fn session(test: &str) -> ClientSession {
+        let dir = temp_path(test);
+        std::fs::remove_dir_all(&dir).ok();
+        private_dir_builder().create(&dir).unwrap();
+        ClientSession {
+            id: test.to_string(),
+            dir,
+        }
+    }
+
+    const SHORT: Duration = Duration::from_millis(400);
+
+    #[test]
+    fn returns_the_result_or_cancellation() {
+        let session = session("result");
+        std::fs::write(session.dir.join("result.json"), b"{}").unwrap();
+        let result = session.poll("result.json", SHORT, SHORT).unwrap();
+        assert_eq!(result.as_deref(), Some(&b"{}"[..]));
+
+        let session = self::session("cancel");
+        cancel(&session.dir);
+        assert!(session.poll("result.json", SHORT, SHORT).unwrap().is_none());
+    }
+
+    #[test]
+    fn times_out_when_nobody_accepts() {
+        let session = session("accept");
+        let error = session.poll("result.json", SHORT, SHORT).unwrap_err();
+        assert!(matches!(error, AppError::Timeout { .. }));
+    }
+
+    #[test]
+    fn fails_when_the_heartbeat_stops() {
+        let session = session("stale");
+        beat(&session.dir).unwrap();
+        let started = Instant::now();
+        let error = session
+            .poll("result.json", Duration::from_secs(60), SHORT)
+            .unwrap_err();
+        assert!(matches!(error, AppError::BackendUnavailable { .. }));
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn keeps_waiting_while_the_heartbeat_goes_on() {
+        let session = session("alive");
+        let dir = session.dir.clone();
+        let gui = std::thread::spawn(move || {
+            for _ in 0..8 {
+                beat(&dir).unwrap();
+                std::thread::sleep(SHORT / 4);
+            }
+            finish(&dir, "result.json", b"done").unwrap();
+        });
+        let result = session.poll("result.json", SHORT, SHORT * 2).unwrap();
+        assert_eq!(result.as_deref(), Some(&b"done"[..]));
+        gui.join().unwrap();
+    }
+
+    #[test]
+    fn refuses_existing_session_directories() {
+        let session = session("exists");
+        let error = private_dir_builder().create(&session.dir).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::AlreadyExists);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn accepts_only_private_directories() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let session = session("private");
+        assert!(check_private(&session.dir).is_ok());
+
+        let open = session.dir.join("open");
+        std::fs::create_dir(&open).unwrap();
+        std::fs::set_permissions(&open, std::fs::Permissions::from_mode(0o777)).unwrap();
+        assert!(matches!(
+            check_private(&open),
+            Err(AppError::PermissionDenied { .. })
+        ));
+
+        let link = session.dir.join("link");
+        std::os::unix::fs::symlink(&session.dir, &link).unwrap();
+        assert!(matches!(
+            check_private(&link),
+            Err(AppError::PermissionDenied { .. })
+        ));
+    }
+}
@@ -0,0 +1,307 @@
+//! Checking files from the command line before they are queued for the
+//! frontend, so a missing or non-image file is reported once instead of
+//! ending up as an empty tab.
+
+use std::fmt;
+use std::fs::File;
+use std::io::{BufReader, ErrorKind, Read};
+use std::path::Path;
+
+use image::ImageReader;
+use serde::Serialize;
+
+use crate::error::AppError;
+use crate::imaging::{self, ImageSummary};
+use crate::project;
+
+/// Largest file opened from the command line
+pub const MAX_FILE_SIZE: u64 = 256 * 1024 * 1024;
+
+/// Bytes read from each file to recognise it
+const SNIFF_LEN: u64 = 64;
+const ZIP_MAGIC: &[u8] = b"PK\x03\x04";
+
+/// A file that passed validation
+#[derive(Clone, Serialize)]
+pub struct AcceptedFile {
+    /// Absolute path
+    pub path: String,
+    /// Lowercase image format, or `"ursa"` for projects
+    pub format: &'static str,
+    /// Dimensions after EXIF orientation; of the image inside for projects
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Why a file argument cannot be opened.
+///
+/// Serialized with a `kind` tag like `AppError`.
+#[derive(Clone, Debug, Serialize)]
+#[serde(tag = "kind")]
+pub enum RejectReason {
+    /// Not a path, such as an `https://` URI, or a bad session id
+    InvalidArgument {
+        message: String,
+    },
+    NotFound,
+    /// Exists but cannot be read, e.g. for lack of permission
+    Unreadable {
+        message: String,
+    },
+    /// A directory with no images inside
+    NoImages {
+        recursive: bool,
+    },
+    /// A directory with more images or entries than are walked; the first
+    /// `opened` images are accepted
+    TooManyImages {
+        opened: usize,
+    },
+    /// Neither an image format the app opens nor a project
+    NotAnImage,
+    TooLarge {
+        size: u64,
+        limit: u64,
+    },
+    /// Recognised, but its header or project archive is broken
+    Corrupt {
+        message: String,
+    },
+}
+
+impl fmt::Display for RejectReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RejectReason::InvalidArgument { message }
+            | RejectReason::Unreadable { message }
+            | RejectReason::Corrupt { message } => f.write_str(message),
+            RejectReason::NotFound => f.write_str("No such file or directory"),
+            RejectReason::NoImages { recursive: false } => f.write_str("No images in directory"),
+            RejectReason::NoImages { recursive: true } => {
+                f.write_str("No images in directory or its subdirectories")
+            }
+            RejectReason::TooManyImages { opened } => write!(
+                f,
+                "Directory is too large to open in full, stopped after {} images",
+                opened
+            ),
+            RejectReason::NotAnImage => f.write_str("Not a supported image or project"),
+            RejectReason::TooLarge { size, limit } => write!(
+                f,
+                "File is {} MiB, more than the {} MiB limit",
+                size / (1024 * 1024),
+                limit / (1024 * 1024)
+            ),
+        }
+    }
+}
+
+impl RejectReason {
+    fn io(error: std::io::Error) -> Self {
+        match error.kind() {
+            ErrorKind::NotFound => RejectReason::NotFound,
+            _ => RejectReason::Unreadable {
+                message: error.to_string(),
+            },
+        }
+    }
+}
+
+/// What the content of a file says it is
+enum Sniffed {
+    Image,
+    Project,
+}
+
+/// Recognise `path` from its first bytes. Projects are zip archives, so
+/// they also need the project extension.
+fn sniff(path: &Path) -> Result<Option<Sniffed>, std::io::Error> {
+    let mut head = Vec::with_capacity(SNIFF_LEN as usize);
+    File::open(path)?.take(SNIFF_LEN).read_to_end(&mut head)?;
+    let sniffed = if imaging::is_supported_image(&head) {
+        Some(Sniffed::Image)
+    } else if project::is_project_path(path) && head.starts_with(ZIP_MAGIC) {
+        Some(Sniffed::Project)
+    } else {
+        None
+    };
+    Ok(sniffed)
+}
+
+/// Whether `path` looks like something the app opens, going by its content
+pub fn is_openable(path: &Path) -> bool {
+    matches!(sniff(path), Ok(Some(_)))
+}
+
+/// Check that `path` is a readable image or project within the size limit
+pub fn check(path: &Path) -> Result<AcceptedFile, RejectReason> {
+    let metadata = std::fs::metadata(path).map_err(RejectReason::io)?;
+    if !metadata.is_file() {
+        return Err(RejectReason::NotAnImage);
+    }
+    if metadata.len() > MAX_FILE_SIZE {
+        return Err(RejectReason::TooLarge {
+            size: metadata.len(),
+            limit: MAX_FILE_SIZE,
+        });
+    }
+
+    let summary = match sniff(path).map_err(RejectReason::io)? {
+        Some(Sniffed::Image) => probe_image(path),
+        Some(Sniffed::Project) => project::probe_project_file(path),
+        None => return Err(RejectReason::NotAnImage),
+    };
+    let ImageSummary {
+        format,
+        width,
+        height,
+    } = summary.map_err(|e| RejectReason::Corrupt {
+        message: e.to_string(),
+    })?;
+    Ok(AcceptedFile {
+        path: path.to_string_lossy().into_owned(),
+        format,
+        width,
+        height,
+    })
+}
+
+fn probe_image(path: &Path) -> Result<ImageSummary, AppError> {
+    let file = File::open(path).map_err(|e| AppError::io(Some(path), e))?;
+    imaging::probe(ImageReader::new(BufReader::new(file)))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+    use std::path::PathBuf;
+
+    use serde_json::json;
+
+    use super::*;
+
+    fn dir(test: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "ursamarkup-validate-{}-{}",
+            test,
+            std::process::id()
+        ));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn png(width: u32, height: u32) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        image::RgbaImage::new(width, height)
+            .write_to(&mut Cursor::new(&mut bytes), image::ImageFormat::Png)
+            .unwrap();
+        bytes
+    }
+
+    fn project(image: &[u8]) -> Vec<u8> {
+        let state = project::ProjectState {
+            history: json!({ "groups": [], "currentIndex": -1 }),
+            view: json!({}),
+        };
+        project::write_project(image, &state).unwrap()
+    }
+
+    #[test]
+    fn sniffs_content_rather_than_extension() {
+        let dir = dir("sniff");
+        let write = |name: &str, contents: &[u8]| {
+            let path = dir.join(name);
+            std::fs::write(&path, contents).unwrap();
+            path
+        };
+
+        assert!(is_openable(&write("no-extension", &png(1, 1))));
+        assert!(!is_openable(&write("fake.png", b"\x89PNG but not really")));
+        assert!(!is_openable(&write("empty.png", b"")));
+        assert!(is_openable(&write("doc.ursa", &project(&png(1, 1)))));
+        assert!(is_openable(&write("DOC.URSA", &project(&png(1, 1)))));
+        // Zip archives are only projects with the project extension
+        assert!(!is_openable(&write("doc.zip", &project(&png(1, 1)))));
+        assert!(!is_openable(&dir.join("missing.png")));
+    }
+
+    #[test]
+    fn accepts_images_and_projects() {
+        let dir = dir("accept");
+        let image = dir.join("shot.png");
+        std::fs::write(&image, png(5, 3)).unwrap();
+        let file = check(&image).unwrap();
+        assert_eq!((file.format, file.width, file.height), ("png", 5, 3));
+
+        // Recognised by content, not extension
+        let renamed = dir.join("shot.jpg");
+        std::fs::write(&renamed, png(5, 3)).unwrap();
+        assert_eq!(check(&renamed).unwrap().format, "png");
+
+        let project_path = dir.join("shot.ursa");
+        std::fs::write(&project_path, project(&png(7, 2))).unwrap();
+        let file = check(&project_path).unwrap();
+        assert_eq!((file.format, file.width, file.height), ("ursa", 7, 2));
+    }
+
+    #[test]
+    fn classifies_rejections() {
+        let dir = dir("reject");
+        assert!(matches!(
+            check(&dir.join("missing.png")),
+            Err(RejectReason::NotFound)
+        ));
+        assert!(matches!(check(&dir), Err(RejectReason::NotAnImage)));
+
+        let text = dir.join("notes.png");
+        std::fs::write(&text, "not an image").unwrap();
+        assert!(matches!(check(&text), Err(RejectReason::NotAnImage)));
+
+        // A zip is only a project with the project extension
+        let zip = dir.join("archive.zip");
+        std::fs::write(&zip, project(&png(1, 1))).unwrap();
+        assert!(matches!(check(&zip), Err(RejectReason::NotAnImage)));
+
+        let truncated = dir.join("truncated.png");
+        std::fs::write(&truncated, &png(4, 4)[..20]).unwrap();
+        assert!(matches!(
+            check(&truncated),
+            Err(RejectReason::Corrupt { .. })
+        ));
+
+        let broken_project = dir.join("broken.ursa");
+        std::fs::write(&broken_project, &project(&png(1, 1))[..40]).unwrap();
+        assert!(matches!(
+            check(&broken_project),
+            Err(RejectReason::Corrupt { .. })
+        ));
+    }
+
+    #[test]
+    fn rejects_files_over_the_size_limit() {
+        let path = dir("large").join("huge.png");
+        let file = File::create(&path).unwrap();
+        file.set_len(MAX_FILE_SIZE + 1).unwrap();
+        match check(&path) {
+            Err(RejectReason::TooLarge { size, limit }) => {
+                assert_eq!((size, limit), (MAX_FILE_SIZE + 1, MAX_FILE_SIZE));
+            }
+            other => panic!("expected TooLarge, got {:?}", other.err()),
+        }
+        std::fs::remove_file(&path).ok();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn reports_unreadable_files() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let path = dir("unreadable").join("locked.png");
+        std::fs::write(&path, png(1, 1)).unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o000)).unwrap();
+        // Permissions do not stop root
+        if File::open(&path).is_err() {
+            assert!(matches!(check(&path), Err(RejectReason::Unreadable { .. })));
+        }
+    }
+}
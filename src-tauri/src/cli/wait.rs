@@ -0,0 +1,164 @@
+//! `ursamarkup --wait file.png`: open files and block until their tabs are
+//! closed, so the app can serve as an `$EDITOR`-style step in scripts and
+//! git hooks.
+//!
+//! The files go to the GUI through a session (see `session`), which writes
+//! what happened to them back as `result.json` once the last tab is closed.
+//! If the GUI dies first, the session's heartbeat stops and the wait ends
+//! with [`EXIT_ERROR`] instead of blocking forever.
+
+use std::ffi::OsString;
+use std::io::Write;
+
+use serde::{Deserialize, Serialize};
+use tauri::State;
+
+use super::session::{finish, ClientSession, Sessions};
+use super::{OpenFilesPayload, RejectedFile};
+use crate::error::AppError;
+
+/// Every tab was saved or closed without changes
+pub const EXIT_OK: i32 = 0;
+/// Changes were discarded in at least one tab, or the app quit first
+pub const EXIT_DISCARDED: i32 = 1;
+/// A file argument was unusable, or the GUI could not be reached or died
+/// before the tabs were closed
+pub const EXIT_ERROR: i32 = 2;
+
+/// `LaunchFlags::wait_session`
+pub const SESSION_ARG: &str = "--wait-session";
+
+const RESULT_FILE: &str = "result.json";
+
+/// How the tabs of a session were closed
+#[derive(Serialize, Deserialize)]
+pub struct WaitResult {
+    /// Paths the tabs were saved to, in the order they were saved
+    pub saved: Vec<String>,
+    /// Whether unsaved changes were thrown away in any tab
+    pub discarded: bool,
+}
+
+/// Entry point of `ursamarkup --wait`. Returns the process exit code.
+pub fn run_client(files: Vec<String>, recursive: bool, print_path: bool) -> i32 {
+    let cwd = std::env::current_dir().ok();
+    let mut payload = OpenFilesPayload::default();
+    payload.add_arguments(files, cwd.as_deref(), recursive);
+    if !payload.rejected.is_empty() {
+        for RejectedFile { argument, reason } in payload.rejected {
+            eprintln!("ursamarkup --wait: {}: {}", argument, reason);
+        }
+        return EXIT_ERROR;
+    }
+
+    let paths = payload.accepted.into_iter().map(|file| file.path);
+    let outcome = wait_for_gui(paths);
+    report(outcome, print_path, &mut std::io::stdout().lock())
+}
+
+/// Print what `--wait` prints for `outcome` and return its exit code
+fn report(
+    outcome: Result<Option<WaitResult>, AppError>,
+    print_path: bool,
+    out: &mut impl Write,
+) -> i32 {
+    match outcome {
+        Ok(Some(result)) if !result.discarded => {
+            if print_path {
+                for path in &result.saved {
+                    writeln!(out, "{}", path).ok();
+                }
+            }
+            EXIT_OK
+        }
+        Ok(_) => EXIT_DISCARDED,
+        Err(e) => {
+            eprintln!("ursamarkup --wait: {}", e);
+            EXIT_ERROR
+        }
+    }
+}
+
+/// What happened to the files, or `None` if the app quit first
+fn wait_for_gui(paths: impl Iterator<Item = String>) -> Result<Option<WaitResult>, AppError> {
+    let session = ClientSession::create()?;
+    let args = [SESSION_ARG.into(), session.id.clone().into(), "--".into()]
+        .into_iter()
+        .chain(paths.map(OsString::from));
+    session.spawn_gui(args)?;
+
+    let Some(json) = session.wait_for(RESULT_FILE)? else {
+        return Ok(None);
+    };
+    serde_json::from_slice(&json)
+        .map(Some)
+        .map_err(|e| AppError::internal(format!("Invalid wait result: {}", e)))
+}
+
+/// Report how the tabs of a `--wait` session were closed, releasing the
+/// waiting process
+#[tauri::command]
+pub async fn finish_wait_session(
+    session: String,
+    result: WaitResult,
+    sessions: State<'_, Sessions>,
+) -> Result<(), AppError> {
+    let Some(dir) = sessions.take(&session)? else {
+        return Err(AppError::not_found(format!(
+            "Wait session {} is closed",
+            session
+        )));
+    };
+    let json = serde_json::to_vec(&result)
+        .map_err(|e| AppError::internal(format!("Failed to serialize wait result: {}", e)))?;
+    finish(&dir, RESULT_FILE, &json)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    fn report_to_string(outcome: Result<Option<WaitResult>, AppError>) -> (i32, String) {
+        let mut out = Vec::new();
+        let code = report(outcome, true, &mut out);
+        (code, String::from_utf8(out).unwrap())
+    }
+
+    #[test]
+    fn prints_saved_paths_when_nothing_was_discarded() {
+        let result = WaitResult {
+            saved: vec!["/tmp/a.png".into(), "/tmp/b.png".into()],
+            discarded: false,
+        };
+        assert_eq!(
+            report_to_string(Ok(Some(result))),
+            (EXIT_OK, "/tmp/a.png\n/tmp/b.png\n".into())
+        );
+    }
+
+    #[test]
+    fn discarded_changes_and_quitting_share_a_code() {
+        let result = WaitResult {
+            saved: vec!["/tmp/a.png".into()],
+            discarded: true,
+        };
+        assert_eq!(
+            report_to_string(Ok(Some(result))),
+            (EXIT_DISCARDED, String::new())
+        );
+        assert_eq!(report_to_string(Ok(None)), (EXIT_DISCARDED, String::new()));
+    }
+
+    #[test]
+    fn gui_dying_is_an_error() {
+        let died = AppError::unavailable(Some("Ursa Markup"), "stopped responding");
+        assert_eq!(report_to_string(Err(died)), (EXIT_ERROR, String::new()));
+        let never_started = AppError::timeout("Starting Ursa Markup", Duration::from_secs(30));
+        assert_eq!(
+            report_to_string(Err(never_started)),
+            (EXIT_ERROR, String::new())
+        );
+    }
+}
@@ -0,0 +1,410 @@
+use std::io::{Read, Write};
+use std::process::{Command, Stdio};
+use std::sync::{mpsc, Arc};
+use std::time::{Duration, Instant};
+
+use super::ClipboardPayload;
+use crate::error::AppError;
+
+/// Identifies a clipboard backend in settings and copy results
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum BackendKind {
+    Arboard,
+    WlClipboard,
+    WlCopy,
+    Xclip,
+    Xsel,
+    /// In-memory clipboard for tests, never offered in settings
+    #[cfg(test)]
+    Mock,
+}
+
+impl BackendKind {
+    /// Name as used in settings
+    pub fn name(&self) -> &'static str {
+        match self {
+            BackendKind::Arboard => "arboard",
+            BackendKind::WlClipboard => "wl-clipboard",
+            BackendKind::WlCopy => "wl-copy",
+            BackendKind::Xclip => "xclip",
+            BackendKind::Xsel => "xsel",
+            #[cfg(test)]
+            BackendKind::Mock => "mock",
+        }
+    }
+
+    pub fn default_timeout(&self) -> Duration {
+        match self {
+            BackendKind::Arboard | BackendKind::WlClipboard => Duration::from_secs(2),
+            #[cfg(test)]
+            BackendKind::Mock => Duration::from_secs(2),
+            BackendKind::WlCopy | BackendKind::Xclip | BackendKind::Xsel => Duration::from_secs(5),
+        }
+    }
+}
+
+/// Which selection a copy is written to
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Selection {
+    /// The regular clipboard, pasted with Ctrl+V
+    Clipboard,
+    /// The X11/Wayland primary selection, pasted with middle-click (Linux only)
+    Primary,
+}
+
+impl Selection {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Selection::Clipboard => "CLIPBOARD",
+            Selection::Primary => "PRIMARY",
+        }
+    }
+}
+
+/// A way of putting an image on the system clipboard
+pub trait ClipboardBackend: Send + Sync {
+    fn kind(&self) -> BackendKind;
+
+    /// Write the payload to `selection`, giving up after `timeout`
+    fn set_image(
+        &self,
+        payload: &Arc<ClipboardPayload>,
+        selection: Selection,
+        timeout: Duration,
+    ) -> Result<(), AppError>;
+
+    /// Whether `set_image` publishes the payload's extra formats too
+    fn supports_multiple_formats(&self) -> bool {
+        false
+    }
+
+    /// Whether the published image is the payload's encoded bytes, so a
+    /// byte budget holds for what is pasted
+    fn publishes_encoded(&self) -> bool {
+        true
+    }
+
+    /// MIME types a successful `set_image` leaves on the clipboard
+    fn published_formats(&self, payload: &ClipboardPayload) -> Vec<&'static str> {
+        if self.supports_multiple_formats() {
+            payload.mime_types()
+        } else {
+            vec![payload.encoding.mime_type()]
+        }
+    }
+}
+
+/// Run `f` on a helper thread and stop waiting for it after `timeout`.
+///
+/// A timed-out thread is left detached; it cannot be cancelled safely.
+fn run_with_timeout<F>(kind: BackendKind, timeout: Duration, f: F) -> Result<(), AppError>
+where
+    F: FnOnce() -> Result<(), AppError> + Send + 'static,
+{
+    let (tx, rx) = mpsc::channel();
+    std::thread::spawn(move || {
+        let _ = tx.send(f());
+    });
+    match rx.recv_timeout(timeout) {
+        Ok(result) => result,
+        Err(mpsc::RecvTimeoutError::Timeout) => Err(AppError::timeout(kind.name(), timeout)),
+        Err(mpsc::RecvTimeoutError::Disconnected) => Err(AppError::internal(format!(
+            "{} thread panicked",
+            kind.name()
+        ))),
+    }
+}
+
+fn primary_unsupported(kind: BackendKind) -> AppError {
+    AppError::unavailable(
+        Some(kind.name()),
+        "The PRIMARY selection only exists on Linux",
+    )
+}
+
+/// Map arboard errors onto stable kinds
+pub(super) fn arboard_error(error: arboard::Error) -> AppError {
+    use arboard::Error;
+
+    match error {
+        Error::ContentNotAvailable => AppError::not_found("Clipboard holds no matching content"),
+        Error::ConversionFailure => AppError::encode("clipboard image", &error),
+        // Unsupported, occupied or unknown
+        _ => AppError::unavailable(Some(BackendKind::Arboard.name()), error.to_string()),
+    }
+}
+
+/// Long-lived arboard handle. On X11 and Wayland the owning process serves the
+/// clipboard, and dropping the last handle tears that server down, so the most
+/// recent handle is kept for as long as the app runs.
+#[cfg(target_os = "linux")]
+static ARBOARD_OWNER: std::sync::Mutex<Option<arboard::Clipboard>> = std::sync::Mutex::new(None);
+
+/// Native clipboard access through arboard (X11, Wayland data-control, macOS, Windows)
+pub struct ArboardBackend;
+
+impl ClipboardBackend for ArboardBackend {
+    fn kind(&self) -> BackendKind {
+        BackendKind::Arboard
+    }
+
+    fn set_image(
+        &self,
+        payload: &Arc<ClipboardPayload>,
+        selection: Selection,
+        timeout: Duration,
+    ) -> Result<(), AppError> {
+        use arboard::{Clipboard, ImageData};
+        use std::borrow::Cow;
+
+        if cfg!(not(target_os = "linux")) && selection == Selection::Primary {
+            return Err(primary_unsupported(self.kind()));
+        }
+        let payload = Arc::clone(payload);
+        run_with_timeout(self.kind(), timeout, move || {
+            let mut clipboard = Clipboard::new().map_err(arboard_error)?;
+            let image = ImageData {
+                width: payload.image.width as usize,
+                height: payload.image.height as usize,
+                bytes: Cow::Borrowed(payload.image.pixels.as_slice()),
+            };
+
+            #[cfg(target_os = "linux")]
+            {
+                use arboard::{LinuxClipboardKind, SetExtLinux};
+
+                let kind = match selection {
+                    Selection::Clipboard => LinuxClipboardKind::Clipboard,
+                    Selection::Primary => LinuxClipboardKind::Primary,
+                };
+                clipboard
+                    .set()
+                    .clipboard(kind)
+                    .image(image)
+                    .map_err(arboard_error)?;
+                *ARBOARD_OWNER.lock().unwrap() = Some(clipboard);
+            }
+            #[cfg(not(target_os = "linux"))]
+            clipboard.set_image(image).map_err(arboard_error)?;
+            Ok(())
+        })
+    }
+
+    /// arboard converts raw RGBA to PNG itself, whatever the copy encoding
+    fn published_formats(&self, _payload: &ClipboardPayload) -> Vec<&'static str> {
+        vec!["image/png"]
+    }
+
+    fn publishes_encoded(&self) -> bool {
+        false
+    }
+}
+
+/// Wayland data-control through wl-clipboard-rs, which can offer every
+/// format of a payload in one selection. The offer is served from a
+/// background thread until another client takes the clipboard.
+pub struct WlClipboardBackend;
+
+impl ClipboardBackend for WlClipboardBackend {
+    fn kind(&self) -> BackendKind {
+        BackendKind::WlClipboard
+    }
+
+    #[cfg(target_os = "linux")]
+    fn set_image(
+        &self,
+        payload: &Arc<ClipboardPayload>,
+        selection: Selection,
+        timeout: Duration,
+    ) -> Result<(), AppError> {
+        use wl_clipboard_rs::copy::{ClipboardType, MimeSource, MimeType, Options, Source};
+
+        if std::env::var_os("WAYLAND_DISPLAY").is_none() {
+            return Err(AppError::unavailable(
+                Some(self.kind().name()),
+                "Not running under Wayland",
+            ));
+        }
+        let sources = payload
+            .representations()?
+            .iter()
+            .map(|representation| MimeSource {
+                source: Source::Bytes(representation.bytes.clone().into_boxed_slice()),
+                mime_type: MimeType::Specific(representation.mime_type.to_string()),
+            })
+            .collect();
+        let mut options = Options::new();
+        options.clipboard(match selection {
+            Selection::Clipboard => ClipboardType::Regular,
+            Selection::Primary => ClipboardType::Primary,
+        });
+        let kind = self.kind();
+        run_with_timeout(kind, timeout, move || {
+            options
+                .copy_multi(sources)
+                .map_err(|e| AppError::unavailable(Some(kind.name()), e.to_string()))
+        })
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn set_image(
+        &self,
+        _payload: &Arc<ClipboardPayload>,
+        _selection: Selection,
+        _timeout: Duration,
+    ) -> Result<(), AppError> {
+        Err(AppError::unavailable(
+            Some(self.kind().name()),
+            "Only available on Linux",
+        ))
+    }
+
+    fn supports_multiple_formats(&self) -> bool {
+        true
+    }
+}
+
+/// Clipboard access by piping encoded bytes into a CLI tool
+pub struct CommandBackend {
+    kind: BackendKind,
+    program: &'static str,
+    args: fn(&str, Selection) -> Vec<String>,
+}
+
+impl CommandBackend {
+    pub fn wl_copy() -> Self {
+        Self {
+            kind: BackendKind::WlCopy,
+            program: "wl-copy",
+            args: |mime, selection| {
+                let mut args = vec!["--type".into(), mime.into()];
+                if selection == Selection::Primary {
+                    args.push("--primary".into());
+                }
+                args
+            },
+        }
+    }
+
+    pub fn xclip() -> Self {
+        Self {
+            kind: BackendKind::Xclip,
+            program: "xclip",
+            args: |mime, selection| {
+                vec![
+                    "-selection".into(),
+                    match selection {
+                        Selection::Clipboard => "clipboard".into(),
+                        Selection::Primary => "primary".into(),
+                    },
+                    "-t".into(),
+                    mime.into(),
+                    "-i".into(),
+                ]
+            },
+        }
+    }
+
+    /// xsel cannot advertise a MIME type, so targets only see the raw bytes.
+    /// It is kept as a last resort for minimal X11 setups.
+    pub fn xsel() -> Self {
+        Self {
+            kind: BackendKind::Xsel,
+            program: "xsel",
+            args: |_, selection| {
+                let flag = match selection {
+                    Selection::Clipboard => "--clipboard",
+                    Selection::Primary => "--primary",
+                };
+                vec![flag.into(), "--input".into()]
+            },
+        }
+    }
+}
+
+impl ClipboardBackend for CommandBackend {
+    fn kind(&self) -> BackendKind {
+        self.kind
+    }
+
+    fn set_image(
+        &self,
+        payload: &Arc<ClipboardPayload>,
+        selection: Selection,
+        timeout: Duration,
+    ) -> Result<(), AppError> {
+        // Encode up front so failures are reported before spawning anything
+        payload.encoded()?;
+        let deadline = Instant::now() + timeout;
+
+        let mut child = Command::new(self.program)
+            .args((self.args)(payload.encoding.mime_type(), selection))
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| {
+                AppError::unavailable(
+                    Some(self.kind.name()),
+                    format!("Failed to spawn {}: {}", self.program, e),
+                )
+            })?;
+
+        // Feed stdin from a helper thread so a stalled child cannot block past the deadline
+        let stdin = child.stdin.take();
+        let writer_payload = Arc::clone(payload);
+        let writer = std::thread::spawn(move || -> std::io::Result<()> {
+            if let (Some(mut stdin), Ok(bytes)) = (stdin, writer_payload.encoded()) {
+                stdin.write_all(bytes)?;
+            }
+            Ok(())
+        });
+
+        let status = loop {
+            match child.try_wait()? {
+                Some(status) => break status,
+                None if payload.is_cancelled() => {
+                    let _ = child.kill();
+                    let _ = child.wait();
+                    return Err(AppError::Cancelled);
+                }
+                None if Instant::now() >= deadline => {
+                    let _ = child.kill();
+                    let _ = child.wait();
+                    return Err(AppError::timeout(self.kind.name(), timeout));
+                }
+                None => std::thread::sleep(Duration::from_millis(10)),
+            }
+        };
+
+        let write_result = writer.join().unwrap_or(Ok(()));
+        if status.success() {
+            return write_result.map_err(AppError::from);
+        }
+
+        // Only read stderr on failure: on success these tools fork a server
+        // that inherits the pipe and keeps it open
+        let mut stderr = String::new();
+        if let Some(mut pipe) = child.stderr.take() {
+            let _ = pipe.read_to_string(&mut stderr);
+        }
+        Err(AppError::unavailable(
+            Some(self.kind.name()),
+            format!("{} exited with {}: {}", self.program, status, stderr.trim()),
+        ))
+    }
+}
+
+pub fn create_backend(kind: BackendKind) -> Box<dyn ClipboardBackend> {
+    match kind {
+        BackendKind::Arboard => Box::new(ArboardBackend),
+        BackendKind::WlClipboard => Box::new(WlClipboardBackend),
+        BackendKind::WlCopy => Box::new(CommandBackend::wl_copy()),
+        BackendKind::Xclip => Box::new(CommandBackend::xclip()),
+        BackendKind::Xsel => Box::new(CommandBackend::xsel()),
+        #[cfg(test)]
+        BackendKind::Mock => Box::new(super::MockBackend::default()),
+    }
+}
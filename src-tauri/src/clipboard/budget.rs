@@ -0,0 +1,274 @@
+//! Fitting copies into size limits, for chat apps that reject or badly
+//! recompress large images.
+//!
+//! The image is first downscaled to the dimension limit. If a byte budget is
+//! set, the copy's encoding is tried at decreasing JPEG quality (PNG has no
+//! quality knob), then the image is downscaled further in proportion to how
+//! far over budget it still is, until it fits.
+//!
+//! Fitting runs on the copy thread and checks between steps whether a newer
+//! copy has replaced this one, so superseded auto-copies stop early.
+
+use std::borrow::Cow;
+
+use image::imageops::FilterType;
+use image::{ImageBuffer, Rgba};
+
+use super::{CopyEncoding, RgbaImage};
+use crate::error::AppError;
+
+const MIN_JPEG_QUALITY: u8 = 40;
+const JPEG_QUALITY_STEP: u8 = 10;
+/// Downscale attempts before giving up on the byte budget
+const MAX_SCALE_STEPS: usize = 8;
+
+/// Optional limits for a single copy; `None` means unlimited
+#[derive(Clone, Copy, Default)]
+pub struct CopyBudget {
+    /// Longest side in pixels
+    pub max_dimension: Option<u32>,
+    /// Size of the encoded image
+    pub max_bytes: Option<usize>,
+}
+
+/// An image that fits its budget, with the encoding that made it fit
+pub struct FittedImage {
+    pub image: RgbaImage,
+    pub encoding: CopyEncoding,
+    /// Already encoded bytes, when the byte budget had to be checked
+    pub encoded: Option<Vec<u8>>,
+    pub scaled: bool,
+}
+
+impl CopyBudget {
+    pub fn is_unlimited(&self) -> bool {
+        self.max_dimension.is_none() && self.max_bytes.is_none()
+    }
+
+    /// Fit `image` into the budget, failing with [`AppError::Cancelled`] as
+    /// soon as `is_cancelled` returns true between steps
+    pub fn fit(
+        &self,
+        image: &RgbaImage,
+        encoding: CopyEncoding,
+        is_cancelled: impl Fn() -> bool,
+    ) -> Result<FittedImage, AppError> {
+        let check = || {
+            if is_cancelled() {
+                Err(AppError::Cancelled)
+            } else {
+                Ok(())
+            }
+        };
+
+        let mut image = Cow::Borrowed(image);
+        let mut scaled = false;
+        if let Some(max) = self.max_dimension {
+            let longest = image.width.max(image.height);
+            if longest > max {
+                image = Cow::Owned(resize(&image, max as f64 / longest as f64));
+                scaled = true;
+            }
+        }
+
+        let Some(max_bytes) = self.max_bytes else {
+            return Ok(FittedImage {
+                image: image.into_owned(),
+                encoding,
+                encoded: None,
+                scaled,
+            });
+        };
+
+        // Always downscale from the same source to avoid compounding resampling
+        let mut current: Option<RgbaImage> = None;
+        let mut scale = 1.0;
+        for _ in 0..MAX_SCALE_STEPS {
+            let candidate = current.as_ref().unwrap_or(&image);
+            let smallest = match encode_within(candidate, encoding, max_bytes, &check)? {
+                Attempt::Fits(encoding, encoded) => {
+                    return Ok(FittedImage {
+                        image: current.unwrap_or_else(|| image.into_owned()),
+                        encoding,
+                        encoded: Some(encoded),
+                        scaled,
+                    })
+                }
+                Attempt::TooLarge(smallest) => smallest,
+            };
+
+            // Encoded size grows roughly with pixel count; undershoot a little
+            scale *= (max_bytes as f64 / smallest as f64).sqrt() * 0.9;
+            check()?;
+            let next = resize(&image, scale);
+            if next.width <= 1 && next.height <= 1 {
+                break;
+            }
+            current = Some(next);
+            scaled = true;
+        }
+
+        Err(AppError::encode(
+            encoding_name(encoding),
+            format!("image does not fit in {} bytes", max_bytes),
+        ))
+    }
+}
+
+enum Attempt {
+    Fits(CopyEncoding, Vec<u8>),
+    /// Smallest encoded size reached
+    TooLarge(usize),
+}
+
+/// Encode `image`, stepping JPEG quality down until it fits `max_bytes`
+fn encode_within(
+    image: &RgbaImage,
+    encoding: CopyEncoding,
+    max_bytes: usize,
+    check: &impl Fn() -> Result<(), AppError>,
+) -> Result<Attempt, AppError> {
+    let mut attempt = encoding;
+    loop {
+        check()?;
+        let bytes = image.encode(attempt)?;
+        if bytes.len() <= max_bytes {
+            return Ok(Attempt::Fits(attempt, bytes));
+        }
+        match attempt {
+            CopyEncoding::Jpeg { quality } if quality > MIN_JPEG_QUALITY => {
+                attempt = CopyEncoding::Jpeg {
+                    quality: quality
+                        .saturating_sub(JPEG_QUALITY_STEP)
+                        .max(MIN_JPEG_QUALITY),
+                };
+            }
+            _ => return Ok(Attempt::TooLarge(bytes.len())),
+        }
+    }
+}
+
+fn encoding_name(encoding: CopyEncoding) -> &'static str {
+    match encoding {
+        CopyEncoding::Png => "PNG",
+        CopyEncoding::Jpeg { .. } => "JPEG",
+    }
+}
+
+fn resize(image: &RgbaImage, scale: f64) -> RgbaImage {
+    let width = ((image.width as f64 * scale).round() as u32).max(1);
+    let height = ((image.height as f64 * scale).round() as u32).max(1);
+    let Some(source) =
+        ImageBuffer::<Rgba<u8>, &[u8]>::from_raw(image.width, image.height, &image.pixels)
+    else {
+        return RgbaImage {
+            width: image.width,
+            height: image.height,
+            pixels: image.pixels.clone(),
+        };
+    };
+    let resized = image::imageops::resize(&source, width, height, FilterType::Lanczos3);
+    RgbaImage {
+        width,
+        height,
+        pixels: resized.into_raw(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Noise, so encoded sizes grow with the pixel count
+    fn noise(width: u32, height: u32) -> RgbaImage {
+        let mut seed = 0x2545_f491_u32;
+        let pixels = (0..width * height * 4)
+            .map(|i| {
+                seed ^= seed << 13;
+                seed ^= seed >> 17;
+                seed ^= seed << 5;
+                if i % 4 == 3 {
+                    255
+                } else {
+                    seed as u8
+                }
+            })
+            .collect();
+        RgbaImage {
+            width,
+            height,
+            pixels,
+        }
+    }
+
+    #[test]
+    fn unlimited_budget_keeps_image() {
+        let image = noise(8, 4);
+        let fitted = CopyBudget::default()
+            .fit(&image, CopyEncoding::Png, || false)
+            .unwrap();
+        assert_eq!((fitted.image.width, fitted.image.height), (8, 4));
+        assert!(!fitted.scaled);
+        assert!(fitted.encoded.is_none());
+    }
+
+    #[test]
+    fn downscales_to_max_dimension() {
+        let budget = CopyBudget {
+            max_dimension: Some(4),
+            max_bytes: None,
+        };
+        let fitted = budget
+            .fit(&noise(16, 8), CopyEncoding::Png, || false)
+            .unwrap();
+        assert_eq!((fitted.image.width, fitted.image.height), (4, 2));
+        assert!(fitted.scaled);
+    }
+
+    #[test]
+    fn lowers_jpeg_quality_before_downscaling() {
+        let image = noise(64, 64);
+        let full = image.encode(CopyEncoding::Jpeg { quality: 90 }).unwrap();
+        let lowest = image
+            .encode(CopyEncoding::Jpeg {
+                quality: MIN_JPEG_QUALITY,
+            })
+            .unwrap();
+        let budget = CopyBudget {
+            max_dimension: None,
+            max_bytes: Some((full.len() + lowest.len()) / 2),
+        };
+
+        let fitted = budget
+            .fit(&image, CopyEncoding::Jpeg { quality: 90 }, || false)
+            .unwrap();
+        assert!(!fitted.scaled);
+        assert!(matches!(fitted.encoding, CopyEncoding::Jpeg { quality } if quality < 90));
+        assert!(fitted.encoded.unwrap().len() <= budget.max_bytes.unwrap());
+    }
+
+    #[test]
+    fn downscales_png_into_byte_budget() {
+        let image = noise(64, 64);
+        let full = image.encode(CopyEncoding::Png).unwrap();
+        let budget = CopyBudget {
+            max_dimension: None,
+            max_bytes: Some(full.len() / 4),
+        };
+
+        let fitted = budget.fit(&image, CopyEncoding::Png, || false).unwrap();
+        assert!(fitted.scaled);
+        assert!(fitted.image.width < 64);
+        assert!(fitted.encoded.unwrap().len() <= full.len() / 4);
+    }
+
+    #[test]
+    fn stops_when_cancelled() {
+        let budget = CopyBudget {
+            max_dimension: None,
+            max_bytes: Some(1),
+        };
+        let result = budget.fit(&noise(16, 16), CopyEncoding::Png, || true);
+        assert!(matches!(result, Err(AppError::Cancelled)));
+    }
+}
@@ -0,0 +1,321 @@
+//! Extra representations published alongside the main clipboard image.
+//!
+//! Besides the image in the copy's own encoding, a copy can also offer PNG,
+//! JPEG, a `text/uri-list` pointing at a file in the cache directory (for
+//! apps that only accept file pastes) and `text/html` with the image inlined.
+//!
+//! Files written for `text/uri-list` must outlive the copy, since the paste
+//! can happen much later. They are cleaned up in two ways: every new file
+//! trims the directory to the [`MAX_TEMP_FILES`] newest, and at startup
+//! anything older than [`TEMP_FILE_MAX_AGE`] is removed.
+
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use base64::Engine;
+
+use super::{CopyEncoding, RgbaImage};
+use crate::error::AppError;
+
+/// Temp files kept around for pastes of recent copies
+pub const MAX_TEMP_FILES: usize = 5;
+/// Temp files older than this are removed at startup
+pub const TEMP_FILE_MAX_AGE: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Which extra formats to publish, in addition to the copy's own encoding
+#[derive(Clone, Copy, Default, serde::Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ClipboardFormats {
+    pub png: bool,
+    pub jpeg: bool,
+    pub file_uri: bool,
+    pub html: bool,
+}
+
+impl ClipboardFormats {
+    /// Extra formats for a copy whose main representation is `primary`.
+    ///
+    /// Formats that duplicate `primary` are skipped.
+    pub fn extras(&self, primary: CopyEncoding, jpeg_quality: u8) -> Vec<ExtraFormat> {
+        let mut extras = Vec::new();
+        if self.png && !matches!(primary, CopyEncoding::Png) {
+            extras.push(ExtraFormat::Image(CopyEncoding::Png));
+        }
+        if self.jpeg && !matches!(primary, CopyEncoding::Jpeg { .. }) {
+            extras.push(ExtraFormat::Image(CopyEncoding::Jpeg {
+                quality: jpeg_quality,
+            }));
+        }
+        if self.file_uri {
+            extras.push(ExtraFormat::FileUri);
+        }
+        if self.html {
+            extras.push(ExtraFormat::Html);
+        }
+        extras
+    }
+}
+
+#[derive(Clone, Copy)]
+pub enum ExtraFormat {
+    Image(CopyEncoding),
+    /// `text/uri-list` with a temp file holding the main encoding
+    FileUri,
+    /// `text/html` with the main encoding inlined as a data URL
+    Html,
+}
+
+impl ExtraFormat {
+    pub fn mime_type(&self) -> &'static str {
+        match self {
+            ExtraFormat::Image(encoding) => encoding.mime_type(),
+            ExtraFormat::FileUri => "text/uri-list",
+            ExtraFormat::Html => "text/html",
+        }
+    }
+}
+
+/// One MIME type and the bytes offered under it
+pub struct Representation {
+    pub mime_type: &'static str,
+    pub bytes: Vec<u8>,
+}
+
+/// Build the bytes for `format`, given the already encoded main image
+pub fn build_extra(
+    format: ExtraFormat,
+    image: &RgbaImage,
+    primary: CopyEncoding,
+    encoded: &[u8],
+    temp_dir: Option<&Path>,
+) -> Result<Representation, AppError> {
+    let bytes = match format {
+        ExtraFormat::Image(encoding) => image.encode(encoding)?,
+        ExtraFormat::FileUri => {
+            let dir = temp_dir
+                .ok_or_else(|| AppError::not_found("No cache directory for clipboard files"))?;
+            let path = write_temp_file(dir, primary, encoded)?;
+            let uri = url::Url::from_file_path(&path).map_err(|_| {
+                AppError::invalid(format!("Cannot build a file URI for {}", path.display()))
+            })?;
+            // RFC 2483 lines end in CRLF
+            format!("{}\r\n", uri).into_bytes()
+        }
+        ExtraFormat::Html => format!(
+            r#"<img src="data:{};base64,{}" width="{}" height="{}">"#,
+            primary.mime_type(),
+            base64::engine::general_purpose::STANDARD.encode(encoded),
+            image.width,
+            image.height
+        )
+        .into_bytes(),
+    };
+    Ok(Representation {
+        mime_type: format.mime_type(),
+        bytes,
+    })
+}
+
+fn write_temp_file(dir: &Path, encoding: CopyEncoding, bytes: &[u8]) -> Result<PathBuf, AppError> {
+    std::fs::create_dir_all(dir).map_err(|e| AppError::io(Some(dir), e))?;
+
+    let millis = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis();
+    let extension = match encoding {
+        CopyEncoding::Png => "png",
+        CopyEncoding::Jpeg { .. } => "jpg",
+    };
+    let path = dir.join(format!("copy-{}.{}", millis, extension));
+    std::fs::write(&path, bytes).map_err(|e| AppError::io(Some(&path), e))?;
+
+    clean_temp_dir(dir, Some(MAX_TEMP_FILES), None);
+    Ok(path)
+}
+
+/// Remove temp files beyond the `keep` newest, and any older than `max_age`.
+///
+/// Cleanup is best-effort: files that cannot be inspected or removed are left alone.
+pub fn clean_temp_dir(dir: &Path, keep: Option<usize>, max_age: Option<Duration>) {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return;
+    };
+    let mut files: Vec<(SystemTime, PathBuf)> = entries
+        .filter_map(Result::ok)
+        .filter(|entry| entry.file_name().to_string_lossy().starts_with("copy-"))
+        .filter_map(|entry| {
+            let modified = entry.metadata().ok()?.modified().ok()?;
+            Some((modified, entry.path()))
+        })
+        .collect();
+    files.sort_by_key(|(modified, _)| std::cmp::Reverse(*modified));
+
+    let now = SystemTime::now();
+    for (index, (modified, path)) in files.iter().enumerate() {
+        let over_count = keep.is_some_and(|keep| index >= keep);
+        let too_old = max_age
+            .is_some_and(|max_age| now.duration_since(*modified).is_ok_and(|age| age > max_age));
+        if over_count || too_old {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "ursamarkup-formats-{}-{}",
+            name,
+            std::process::id()
+        ));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// Create `name` in `dir`, last modified `age` ago
+    fn touch(dir: &Path, name: &str, age: Duration) {
+        let file = File::create(dir.join(name)).unwrap();
+        file.set_modified(SystemTime::now() - age).unwrap();
+    }
+
+    fn names(dir: &Path) -> Vec<String> {
+        let mut names: Vec<_> = std::fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        names
+    }
+
+    fn image() -> RgbaImage {
+        RgbaImage {
+            width: 2,
+            height: 1,
+            pixels: vec![255, 0, 0, 255, 0, 0, 255, 255],
+        }
+    }
+
+    #[test]
+    fn keeps_the_newest_temp_files() {
+        let dir = temp_dir("keep");
+        for (name, minutes) in [("copy-a.png", 3), ("copy-b.png", 1), ("copy-c.jpg", 2)] {
+            touch(&dir, name, Duration::from_secs(minutes * 60));
+        }
+        touch(&dir, "notes.txt", Duration::from_secs(10 * 60));
+
+        clean_temp_dir(&dir, Some(2), None);
+        let left = names(&dir);
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(left, ["copy-b.png", "copy-c.jpg", "notes.txt"]);
+    }
+
+    #[test]
+    fn removes_temp_files_past_their_age() {
+        let dir = temp_dir("age");
+        touch(&dir, "copy-old.png", TEMP_FILE_MAX_AGE * 2);
+        touch(&dir, "copy-new.png", Duration::ZERO);
+        touch(&dir, "old.png", TEMP_FILE_MAX_AGE * 2);
+
+        clean_temp_dir(&dir, None, Some(TEMP_FILE_MAX_AGE));
+        let left = names(&dir);
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(left, ["copy-new.png", "old.png"]);
+    }
+
+    #[test]
+    fn temp_files_are_capped() {
+        let dir = temp_dir("cap");
+        let mut paths = Vec::new();
+        for i in 0..MAX_TEMP_FILES + 2 {
+            paths.push(write_temp_file(&dir, CopyEncoding::Png, &[i as u8]).unwrap());
+            // File names have millisecond resolution
+            std::thread::sleep(Duration::from_millis(5));
+        }
+        let left = names(&dir);
+        let newest = std::fs::read(paths.last().unwrap()).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(left.len(), MAX_TEMP_FILES);
+        assert!(left.iter().all(|name| name.ends_with(".png")));
+        assert_eq!(newest, [MAX_TEMP_FILES as u8 + 1]);
+    }
+
+    #[test]
+    fn file_uri_points_at_the_encoded_image() {
+        let dir = temp_dir("uri");
+        let encoded = b"\xff\xd8 jpeg bytes";
+        let primary = CopyEncoding::Jpeg { quality: 85 };
+        let extra =
+            build_extra(ExtraFormat::FileUri, &image(), primary, encoded, Some(&dir)).unwrap();
+
+        let uri = String::from_utf8(extra.bytes).unwrap();
+        let path = url::Url::parse(uri.strip_suffix("\r\n").unwrap())
+            .unwrap()
+            .to_file_path()
+            .unwrap();
+        let contents = std::fs::read(&path).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(extra.mime_type, "text/uri-list");
+        assert_eq!(path.extension().unwrap(), "jpg");
+        assert_eq!(contents, encoded);
+        assert!(build_extra(ExtraFormat::FileUri, &image(), primary, encoded, None).is_err());
+    }
+
+    #[test]
+    fn html_inlines_the_encoded_image() {
+        let extra =
+            build_extra(ExtraFormat::Html, &image(), CopyEncoding::Png, b"png", None).unwrap();
+        assert_eq!(extra.mime_type, "text/html");
+        assert_eq!(
+            String::from_utf8(extra.bytes).unwrap(),
+            r#"<img src="data:image/png;base64,cG5n" width="2" height="1">"#
+        );
+    }
+
+    #[test]
+    fn image_extras_are_encoded_separately() {
+        let jpeg = CopyEncoding::Jpeg { quality: 85 };
+        let extra = build_extra(
+            ExtraFormat::Image(jpeg),
+            &image(),
+            CopyEncoding::Png,
+            b"",
+            None,
+        )
+        .unwrap();
+        assert_eq!(extra.mime_type, "image/jpeg");
+        assert_eq!(extra.bytes[..2], [0xff, 0xd8]);
+    }
+
+    #[test]
+    fn extras_skip_the_primary_encoding() {
+        let all = ClipboardFormats {
+            png: true,
+            jpeg: true,
+            file_uri: true,
+            html: true,
+        };
+        let mime_types = |primary| -> Vec<_> {
+            all.extras(primary, 85)
+                .iter()
+                .map(ExtraFormat::mime_type)
+                .collect()
+        };
+        assert_eq!(
+            mime_types(CopyEncoding::Png),
+            ["image/jpeg", "text/uri-list", "text/html"]
+        );
+        assert_eq!(
+            mime_types(CopyEncoding::Jpeg { quality: 85 }),
+            ["image/png", "text/uri-list", "text/html"]
+        );
+    }
+}
@@ -0,0 +1,652 @@
+//! Ring of recent successful copies, so an earlier annotation can be put back
+//! on the clipboard after something else has overwritten it.
+//!
+//! Entries are keyed by document id and version: copying the same version
+//! again moves its entry to the front instead of adding a duplicate. Pixels
+//! of the newest entries stay in memory up to [`MEMORY_BUDGET`]. Copies are
+//! often screenshots, so writing them to disk is opt-in: only then is every
+//! entry also saved as PNG alongside an `index.json`, so the ring survives
+//! restarts and older entries are decoded from disk on demand.
+
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use base64::Engine;
+use tauri::{AppHandle, Manager, State};
+
+use crate::error::AppError;
+use crate::imaging::write_atomic;
+
+use super::{
+    ClipboardPayload, ClipboardState, CopyBudget, CopyEncoding, CopyJob, CopyQueue, RgbaImage,
+};
+
+pub const DEFAULT_CAPACITY: usize = 10;
+/// Pixels kept in memory across all entries; older ones are reloaded from disk
+const MEMORY_BUDGET: usize = 256 * 1024 * 1024;
+/// Longest side of entry thumbnails, sized for menu icons
+const THUMBNAIL_SIZE: u32 = 32;
+const INDEX_FILE: &str = "index.json";
+
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+pub struct HistoryEntry {
+    pub id: String,
+    pub document_id: String,
+    pub version: u32,
+    /// Milliseconds since the Unix epoch
+    pub timestamp: u64,
+    pub width: u32,
+    pub height: u32,
+    /// Short description for menus, e.g. `screenshot.png (1920×1080)`
+    pub label: String,
+    /// Base64 PNG, at most `THUMBNAIL_SIZE` pixels on its longest side
+    pub thumbnail: String,
+    /// Quality of the original JPEG encoding, or `None` if it was copied as PNG
+    pub jpeg_quality: Option<u8>,
+}
+
+impl HistoryEntry {
+    pub fn encoding(&self) -> CopyEncoding {
+        match self.jpeg_quality {
+            Some(quality) => CopyEncoding::Jpeg { quality },
+            None => CopyEncoding::Png,
+        }
+    }
+
+    /// Decode the thumbnail for use as a menu icon
+    pub fn thumbnail_rgba(&self) -> Option<RgbaImage> {
+        let png = base64::engine::general_purpose::STANDARD
+            .decode(&self.thumbnail)
+            .ok()?;
+        let image = image::load_from_memory(&png).ok()?.to_rgba8();
+        Some(RgbaImage {
+            width: image.width(),
+            height: image.height(),
+            pixels: image.into_raw(),
+        })
+    }
+}
+
+struct Slot {
+    entry: HistoryEntry,
+    /// Present while the entry fits the memory budget
+    payload: Option<Arc<ClipboardPayload>>,
+}
+
+enum DiskOp {
+    Save(String, Arc<ClipboardPayload>),
+    Remove(String),
+    Index(Vec<HistoryEntry>),
+    /// Delete the whole directory
+    Clear,
+}
+
+type ChangeHandler = dyn Fn(&[HistoryEntry]) + Send + Sync;
+
+struct HistoryState {
+    /// Newest first
+    slots: VecDeque<Slot>,
+    capacity: usize,
+    /// [`MEMORY_BUDGET`], lowered in tests
+    memory_budget: usize,
+}
+
+struct HistoryInner {
+    state: Mutex<HistoryState>,
+    dir: Option<PathBuf>,
+    disk: Option<mpsc::Sender<DiskOp>>,
+    /// Whether entries are written to `dir`
+    persist: AtomicBool,
+    on_change: Mutex<Option<Box<ChangeHandler>>>,
+}
+
+/// Bounded history of copied images, shared by the copy queue, commands and tray
+#[derive(Clone)]
+pub struct CopyHistory {
+    inner: Arc<HistoryInner>,
+}
+
+impl CopyHistory {
+    /// Load the ring from `dir`, where it can be persisted once enabled with
+    /// [`set_persist`](Self::set_persist). A ring left there by an earlier run
+    /// is loaded and kept persisting until the setting says otherwise.
+    pub fn open(dir: Option<PathBuf>) -> Self {
+        let slots: VecDeque<Slot> = dir
+            .as_deref()
+            .map(load_index)
+            .unwrap_or_default()
+            .into_iter()
+            .map(|entry| Slot {
+                entry,
+                payload: None,
+            })
+            .collect();
+        let persist = !slots.is_empty();
+
+        let disk = dir.clone().and_then(|dir| {
+            let (tx, rx) = mpsc::channel();
+            std::thread::Builder::new()
+                .name("clipboard-history".into())
+                .spawn(move || {
+                    for op in rx {
+                        if let Err(e) = apply_disk_op(&dir, op) {
+                            eprintln!("Clipboard history: {}", e);
+                        }
+                    }
+                })
+                .ok()
+                .map(|_| tx)
+        });
+
+        Self {
+            inner: Arc::new(HistoryInner {
+                state: Mutex::new(HistoryState {
+                    slots,
+                    capacity: DEFAULT_CAPACITY,
+                    memory_budget: MEMORY_BUDGET,
+                }),
+                dir,
+                disk,
+                persist: AtomicBool::new(persist),
+                on_change: Mutex::new(None),
+            }),
+        }
+    }
+
+    /// Called with the current entries, newest first, whenever the ring changes
+    pub fn set_on_change<F>(&self, on_change: F)
+    where
+        F: Fn(&[HistoryEntry]) + Send + Sync + 'static,
+    {
+        *self.inner.on_change.lock().unwrap() = Some(Box::new(on_change));
+    }
+
+    pub fn entries(&self) -> Vec<HistoryEntry> {
+        self.inner
+            .state
+            .lock()
+            .unwrap()
+            .slots
+            .iter()
+            .map(|slot| slot.entry.clone())
+            .collect()
+    }
+
+    pub fn set_capacity(&self, capacity: usize) {
+        let changed = {
+            let mut state = self.inner.state.lock().unwrap();
+            if state.capacity == capacity {
+                return;
+            }
+            state.capacity = capacity;
+            let changed = self.trim(&mut state);
+            if changed {
+                self.save_index(&state);
+            }
+            changed
+        };
+        if changed {
+            self.notify();
+        }
+    }
+
+    /// Start or stop writing entries to disk. Stopping deletes what was
+    /// written, along with entries that only existed there.
+    pub fn set_persist(&self, persist: bool) {
+        let changed = {
+            let mut state = self.inner.state.lock().unwrap();
+            if self.inner.persist.swap(persist, Ordering::SeqCst) == persist {
+                return;
+            }
+            if persist {
+                for slot in &state.slots {
+                    if let Some(payload) = &slot.payload {
+                        self.send(DiskOp::Save(slot.entry.id.clone(), Arc::clone(payload)));
+                    }
+                }
+                self.save_index(&state);
+                false
+            } else {
+                self.send_always(DiskOp::Clear);
+                let before = state.slots.len();
+                state.slots.retain(|slot| slot.payload.is_some());
+                state.slots.len() != before
+            }
+        };
+        if changed {
+            self.notify();
+        }
+    }
+
+    /// Add a successful copy, replacing any entry for the same document version
+    pub fn record(&self, job: &CopyJob) {
+        let image = &job.payload.image;
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as u64;
+        let mut entry = HistoryEntry {
+            id: format!("{}-{}", timestamp, job.version),
+            document_id: job.document_id.clone(),
+            version: job.version,
+            timestamp,
+            width: image.width,
+            height: image.height,
+            label: format!(
+                "{} ({}×{})",
+                job.document_name.as_deref().unwrap_or("Untitled"),
+                image.width,
+                image.height
+            ),
+            thumbnail: thumbnail(image).unwrap_or_default(),
+            jpeg_quality: match job.payload.encoding {
+                CopyEncoding::Jpeg { quality } => Some(quality),
+                CopyEncoding::Png => None,
+            },
+        };
+
+        {
+            let mut state = self.inner.state.lock().unwrap();
+            if state.capacity == 0 {
+                return;
+            }
+            if let Some(index) = state.slots.iter().position(|slot| {
+                slot.entry.document_id == entry.document_id && slot.entry.version == entry.version
+            }) {
+                if let Some(old) = state.slots.remove(index) {
+                    // Re-copies from history don't know the document name
+                    if job.document_name.is_none() {
+                        entry.label = old.entry.label;
+                    }
+                    self.send(DiskOp::Remove(old.entry.id));
+                }
+            }
+            self.send(DiskOp::Save(entry.id.clone(), Arc::clone(&job.payload)));
+            state.slots.push_front(Slot {
+                entry,
+                payload: Some(Arc::clone(&job.payload)),
+            });
+            self.trim(&mut state);
+            self.save_index(&state);
+        }
+        self.notify();
+    }
+
+    /// The entry and its full image, from memory or disk
+    pub fn get(&self, id: &str) -> Result<(HistoryEntry, RgbaImage), AppError> {
+        let (entry, payload) = {
+            let state = self.inner.state.lock().unwrap();
+            let slot = state
+                .slots
+                .iter()
+                .find(|slot| slot.entry.id == id)
+                .ok_or_else(|| AppError::not_found(format!("No clipboard history entry {}", id)))?;
+            (slot.entry.clone(), slot.payload.clone())
+        };
+
+        if let Some(payload) = payload {
+            let image = RgbaImage {
+                width: payload.image.width,
+                height: payload.image.height,
+                pixels: payload.image.pixels.clone(),
+            };
+            return Ok((entry, image));
+        }
+
+        let dir = self.inner.dir.as_deref().ok_or_else(|| {
+            AppError::not_found(format!(
+                "Clipboard history entry {} is no longer available",
+                id
+            ))
+        })?;
+        let path = image_path(dir, id);
+        let image = image::open(&path)
+            .map_err(|e| match e {
+                image::ImageError::IoError(e) => AppError::io(Some(&path), e),
+                e => AppError::decode(Some("PNG"), e),
+            })?
+            .to_rgba8();
+        Ok((
+            entry,
+            RgbaImage {
+                width: image.width(),
+                height: image.height(),
+                pixels: image.into_raw(),
+            },
+        ))
+    }
+
+    pub fn clear(&self) {
+        {
+            let mut state = self.inner.state.lock().unwrap();
+            for slot in state.slots.drain(..) {
+                self.send(DiskOp::Remove(slot.entry.id));
+            }
+            self.save_index(&state);
+        }
+        self.notify();
+    }
+
+    /// Drop entries past the capacity and pixels past the memory budget
+    fn trim(&self, state: &mut HistoryState) -> bool {
+        let mut changed = false;
+        while state.slots.len() > state.capacity {
+            if let Some(old) = state.slots.pop_back() {
+                self.send(DiskOp::Remove(old.entry.id));
+                changed = true;
+            }
+        }
+
+        let mut in_memory = 0;
+        let on_disk = self.is_persisting();
+        state.slots.retain_mut(|slot| {
+            let Some(payload) = &slot.payload else {
+                return true;
+            };
+            in_memory += payload.image.pixels.len();
+            if in_memory <= state.memory_budget {
+                return true;
+            }
+            slot.payload = None;
+            // Without a disk copy the entry cannot be re-copied anymore
+            changed |= !on_disk;
+            on_disk
+        });
+        changed
+    }
+
+    fn save_index(&self, state: &HistoryState) {
+        self.send(DiskOp::Index(
+            state.slots.iter().map(|slot| slot.entry.clone()).collect(),
+        ));
+    }
+
+    fn is_persisting(&self) -> bool {
+        self.inner.disk.is_some() && self.inner.persist.load(Ordering::SeqCst)
+    }
+
+    /// Queue `op` for the disk thread if entries are being persisted
+    fn send(&self, op: DiskOp) {
+        if self.is_persisting() {
+            self.send_always(op);
+        }
+    }
+
+    fn send_always(&self, op: DiskOp) {
+        if let Some(disk) = &self.inner.disk {
+            let _ = disk.send(op);
+        }
+    }
+
+    fn notify(&self) {
+        let entries = self.entries();
+        if let Some(on_change) = self.inner.on_change.lock().unwrap().as_ref() {
+            on_change(&entries);
+        }
+    }
+}
+
+fn image_path(dir: &Path, id: &str) -> PathBuf {
+    dir.join(format!("{}.png", id))
+}
+
+fn load_index(dir: &Path) -> Vec<HistoryEntry> {
+    std::fs::read(dir.join(INDEX_FILE))
+        .ok()
+        .and_then(|bytes| serde_json::from_slice::<Vec<HistoryEntry>>(&bytes).ok())
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|entry| image_path(dir, &entry.id).is_file())
+        .collect()
+}
+
+fn apply_disk_op(dir: &Path, op: DiskOp) -> Result<(), AppError> {
+    if !matches!(op, DiskOp::Clear) {
+        std::fs::create_dir_all(dir).map_err(|e| AppError::io(Some(dir), e))?;
+    }
+    match op {
+        DiskOp::Save(id, payload) => {
+            let png = payload.image.encode(CopyEncoding::Png)?;
+            write_atomic(&image_path(dir, &id), &png, false).map(drop)
+        }
+        DiskOp::Remove(id) => {
+            let path = image_path(dir, &id);
+            match std::fs::remove_file(&path) {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                    Err(AppError::io(Some(&path), e))
+                }
+                _ => Ok(()),
+            }
+        }
+        DiskOp::Index(entries) => {
+            let json = serde_json::to_vec(&entries)
+                .map_err(|e| AppError::internal(format!("Failed to serialize index: {}", e)))?;
+            write_atomic(&dir.join(INDEX_FILE), &json, false).map(drop)
+        }
+        DiskOp::Clear => match std::fs::remove_dir_all(dir) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(AppError::io(Some(dir), e)),
+            _ => Ok(()),
+        },
+    }
+}
+
+fn thumbnail(image: &RgbaImage) -> Option<String> {
+    use image::codecs::png::PngEncoder;
+    use image::{ExtendedColorType, ImageEncoder};
+
+    let full = image::ImageBuffer::<image::Rgba<u8>, &[u8]>::from_raw(
+        image.width,
+        image.height,
+        &image.pixels,
+    )?;
+    let scale = THUMBNAIL_SIZE as f32 / image.width.max(image.height).max(1) as f32;
+    let (width, height) = if scale < 1.0 {
+        (
+            ((image.width as f32 * scale).round() as u32).max(1),
+            ((image.height as f32 * scale).round() as u32).max(1),
+        )
+    } else {
+        (image.width, image.height)
+    };
+    let small = image::imageops::thumbnail(&full, width, height);
+
+    let mut png = Vec::new();
+    PngEncoder::new(&mut png)
+        .write_image(&small, width, height, ExtendedColorType::Rgba8)
+        .ok()?;
+    Some(base64::engine::general_purpose::STANDARD.encode(png))
+}
+
+/// Put a history entry back on the clipboard through the copy queue
+pub fn recopy_from_history(app: &AppHandle, id: &str) -> Result<(), AppError> {
+    let history = app.state::<CopyHistory>();
+    let (entry, image) = history.get(id)?;
+
+    let state = app.state::<ClipboardState>();
+    let payload = state.with_configured_formats(
+        ClipboardPayload::new(image, entry.encoding()),
+        entry.jpeg_quality.unwrap_or(85),
+    );
+    let config = state.config.lock().unwrap().clone();
+    app.state::<CopyQueue>().push(CopyJob {
+        document_id: entry.document_id,
+        document_name: None,
+        version: entry.version,
+        from_history: true,
+        payload,
+        budget: CopyBudget::default(),
+        config,
+    });
+    Ok(())
+}
+
+#[tauri::command]
+pub fn get_clipboard_history(history: State<CopyHistory>) -> Vec<HistoryEntry> {
+    history.entries()
+}
+
+#[tauri::command]
+pub fn recopy_clipboard_history(app: AppHandle, id: String) -> Result<(), AppError> {
+    recopy_from_history(&app, &id)
+}
+
+#[tauri::command]
+pub fn clear_clipboard_history(history: State<CopyHistory>) {
+    history.clear();
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::*;
+    use crate::clipboard::ClipboardConfig;
+
+    fn job(document_id: &str, version: u32, name: Option<&str>, size: u32) -> CopyJob {
+        let image = RgbaImage {
+            width: size,
+            height: size,
+            pixels: vec![200; (size * size * 4) as usize],
+        };
+        CopyJob {
+            document_id: document_id.into(),
+            document_name: name.map(String::from),
+            version,
+            from_history: false,
+            payload: Arc::new(ClipboardPayload::new(image, CopyEncoding::Png)),
+            budget: CopyBudget::default(),
+            config: ClipboardConfig::default(),
+        }
+    }
+
+    fn versions(history: &CopyHistory) -> Vec<(String, u32)> {
+        history
+            .entries()
+            .into_iter()
+            .map(|entry| (entry.document_id, entry.version))
+            .collect()
+    }
+
+    /// Wait for the disk thread to catch up
+    fn wait_for(condition: impl Fn() -> bool) {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !condition() {
+            assert!(Instant::now() < deadline, "timed out waiting for disk");
+            std::thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "ursamarkup-history-{}-{}",
+            name,
+            std::process::id()
+        ));
+        let _ = std::fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn recopying_a_version_moves_it_to_the_front() {
+        let history = CopyHistory::open(None);
+        history.record(&job("a", 1, Some("a.png"), 2));
+        history.record(&job("a", 2, Some("a.png"), 2));
+        history.record(&job("b", 1, Some("b.png"), 2));
+        history.record(&job("a", 1, None, 2));
+
+        assert_eq!(
+            versions(&history),
+            [("a".into(), 1), ("b".into(), 1), ("a".into(), 2)]
+        );
+        // A re-copy from history keeps the label it was first recorded with
+        assert_eq!(history.entries()[0].label, "a.png (2×2)");
+    }
+
+    #[test]
+    fn trims_to_capacity() {
+        let history = CopyHistory::open(None);
+        for version in 1..=4 {
+            history.record(&job("a", version, None, 2));
+        }
+        history.set_capacity(2);
+        assert_eq!(versions(&history), [("a".into(), 4), ("a".into(), 3)]);
+
+        history.set_capacity(0);
+        history.record(&job("a", 5, None, 2));
+        assert!(history.entries().is_empty());
+    }
+
+    #[test]
+    fn drops_entries_past_the_memory_budget_without_disk() {
+        let history = CopyHistory::open(None);
+        // Room for two 4×4 images
+        history.inner.state.lock().unwrap().memory_budget = 2 * 4 * 4 * 4;
+        for version in 1..=3 {
+            history.record(&job("a", version, None, 4));
+        }
+        assert_eq!(versions(&history), [("a".into(), 3), ("a".into(), 2)]);
+        let (_, image) = history.get(&history.entries()[1].id).unwrap();
+        assert_eq!(image.pixels.len(), 4 * 4 * 4);
+    }
+
+    #[test]
+    fn clear_empties_the_ring_and_notifies() {
+        let history = CopyHistory::open(None);
+        history.record(&job("a", 1, None, 2));
+        let (tx, rx) = mpsc::channel();
+        history.set_on_change(move |entries| {
+            tx.send(entries.len()).ok();
+        });
+        history.clear();
+        assert!(history.entries().is_empty());
+        assert_eq!(rx.try_recv(), Ok(0));
+    }
+
+    #[test]
+    fn persisted_entries_survive_a_restart() {
+        let dir = temp_dir("persist");
+        let history = CopyHistory::open(Some(dir.clone()));
+        history.record(&job("a", 1, None, 2));
+        // Nothing is written until persisting is enabled
+        std::thread::sleep(Duration::from_millis(50));
+        assert!(!dir.exists());
+
+        history.set_persist(true);
+        history.record(&job("a", 2, None, 2));
+        let ids: Vec<_> = history.entries().into_iter().map(|e| e.id).collect();
+        wait_for(|| load_index(&dir).len() == 2);
+
+        let reopened = CopyHistory::open(Some(dir.clone()));
+        assert_eq!(versions(&reopened), [("a".into(), 2), ("a".into(), 1)]);
+        let (entry, image) = reopened.get(&ids[1]).unwrap();
+        assert_eq!(entry.version, 1);
+        assert_eq!(image.pixels, vec![200; 16]);
+
+        // Stopping deletes the files and the entries only they held
+        reopened.set_persist(false);
+        wait_for(|| !dir.exists());
+        assert!(reopened.entries().is_empty());
+    }
+
+    #[test]
+    fn entries_past_the_memory_budget_reload_from_disk() {
+        let dir = temp_dir("budget");
+        let history = CopyHistory::open(Some(dir.clone()));
+        history.set_persist(true);
+        history.inner.state.lock().unwrap().memory_budget = 4 * 4 * 4;
+        history.record(&job("a", 1, None, 4));
+        history.record(&job("a", 2, None, 4));
+        let oldest = history.entries()[1].id.clone();
+        assert!(history.inner.state.lock().unwrap().slots[1]
+            .payload
+            .is_none());
+
+        wait_for(|| load_index(&dir).len() == 2);
+        let (_, image) = history.get(&oldest).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(image.pixels, vec![200; 4 * 4 * 4]);
+    }
+}
@@ -0,0 +1,59 @@
+//! In-memory clipboard backend, so the copy path can be tested without a
+//! display server.
+
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use super::{BackendKind, ClipboardBackend, ClipboardPayload, Selection};
+use crate::error::AppError;
+
+/// A copy recorded by [`MockBackend`]
+pub type MockCopy = (Selection, Arc<ClipboardPayload>);
+
+/// Records copies instead of publishing them, or fails with `fail_with`
+#[derive(Clone, Default)]
+pub struct MockBackend {
+    pub copies: Arc<Mutex<Vec<MockCopy>>>,
+    pub fail_with: Option<AppError>,
+    /// How long a copy takes, so a newer one can cancel it midway
+    pub delay: Duration,
+    /// Publish a re-encoded image like arboard rather than our encoded bytes
+    pub reencodes: bool,
+}
+
+impl ClipboardBackend for MockBackend {
+    fn kind(&self) -> BackendKind {
+        BackendKind::Mock
+    }
+
+    fn set_image(
+        &self,
+        payload: &Arc<ClipboardPayload>,
+        selection: Selection,
+        _timeout: Duration,
+    ) -> Result<(), AppError> {
+        let deadline = Instant::now() + self.delay;
+        while Instant::now() < deadline {
+            if payload.is_cancelled() {
+                return Err(AppError::Cancelled);
+            }
+            std::thread::sleep(Duration::from_millis(5));
+        }
+        if let Some(error) = &self.fail_with {
+            return Err(error.clone());
+        }
+        self.copies
+            .lock()
+            .unwrap()
+            .push((selection, Arc::clone(payload)));
+        Ok(())
+    }
+
+    fn supports_multiple_formats(&self) -> bool {
+        true
+    }
+
+    fn publishes_encoded(&self) -> bool {
+        !self.reencodes
+    }
+}
@@ -0,0 +1,677 @@
+mod backend;
+mod budget;
+mod formats;
+mod history;
+#[cfg(test)]
+mod mock;
+mod persist;
+mod queue;
+mod read;
+
+pub use backend::{
+    create_backend, ArboardBackend, BackendKind, ClipboardBackend, CommandBackend, Selection,
+    WlClipboardBackend,
+};
+pub use budget::{CopyBudget, FittedImage};
+pub use formats::{
+    clean_temp_dir, ClipboardFormats, ExtraFormat, Representation, TEMP_FILE_MAX_AGE,
+};
+pub use history::{
+    clear_clipboard_history, get_clipboard_history, recopy_clipboard_history, recopy_from_history,
+    CopyHistory, HistoryEntry,
+};
+#[cfg(test)]
+pub use mock::MockBackend;
+pub use persist::{run_helper, HELPER_ARG};
+pub use queue::{CopyJob, CopyQueue};
+pub use read::{read_clipboard, read_clipboard_image, ClipboardContent, ClipboardReadResult};
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Duration;
+use tauri::{ipc::Request, AppHandle, Manager, State};
+
+use crate::error::{AppError, LogError};
+use crate::headers::{header_value, parse_header, rgba_body, text_header};
+use crate::imaging;
+
+/// Encoding used when a clipboard backend needs encoded bytes instead of raw RGBA
+#[derive(Clone, Copy)]
+pub enum CopyEncoding {
+    Png,
+    Jpeg { quality: u8 },
+}
+
+impl CopyEncoding {
+    pub fn mime_type(&self) -> &'static str {
+        match self {
+            CopyEncoding::Png => "image/png",
+            CopyEncoding::Jpeg { .. } => "image/jpeg",
+        }
+    }
+}
+
+/// Raw RGBA pixels as sent by the frontend
+#[derive(Clone)]
+pub struct RgbaImage {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>,
+}
+
+impl RgbaImage {
+    /// Encode the pixels. JPEG has no alpha channel, so transparent areas are
+    /// flattened onto white.
+    pub fn encode(&self, encoding: CopyEncoding) -> Result<Vec<u8>, AppError> {
+        use image::codecs::jpeg::JpegEncoder;
+        use image::codecs::png::PngEncoder;
+        use image::{ExtendedColorType, ImageEncoder};
+
+        let mut out = Vec::new();
+        match encoding {
+            CopyEncoding::Png => PngEncoder::new(&mut out)
+                .write_image(
+                    &self.pixels,
+                    self.width,
+                    self.height,
+                    ExtendedColorType::Rgba8,
+                )
+                .map_err(|e| AppError::encode("PNG", e))?,
+            CopyEncoding::Jpeg { quality } => JpegEncoder::new_with_quality(&mut out, quality)
+                .write_image(
+                    &imaging::flatten_onto_white(&self.pixels),
+                    self.width,
+                    self.height,
+                    ExtendedColorType::Rgb8,
+                )
+                .map_err(|e| AppError::encode("JPEG", e))?,
+        }
+        Ok(out)
+    }
+}
+
+/// An image queued for the clipboard, shared between backends.
+///
+/// Encoded bytes are produced lazily and cached, so backends that take raw
+/// RGBA (arboard) never pay for encoding and CLI fallbacks encode only once.
+/// The same goes for extra formats, which only multi-format backends publish.
+pub struct ClipboardPayload {
+    pub image: RgbaImage,
+    pub encoding: CopyEncoding,
+    pub extra_formats: Vec<ExtraFormat>,
+    /// Where `text/uri-list` files are written
+    pub temp_dir: Option<PathBuf>,
+    /// Downscaled to fit a copy budget
+    pub scaled: bool,
+    /// Byte budget the encoded bytes were fitted into
+    pub max_bytes: Option<usize>,
+    encoded: OnceLock<Vec<u8>>,
+    representations: OnceLock<Vec<Representation>>,
+    cancelled: AtomicBool,
+}
+
+impl ClipboardPayload {
+    pub fn new(image: RgbaImage, encoding: CopyEncoding) -> Self {
+        Self {
+            image,
+            encoding,
+            extra_formats: Vec::new(),
+            temp_dir: None,
+            scaled: false,
+            max_bytes: None,
+            encoded: OnceLock::new(),
+            representations: OnceLock::new(),
+            cancelled: AtomicBool::new(false),
+        }
+    }
+
+    /// A payload for an image already fitted to its budget, reusing its encoded bytes
+    pub fn from_fitted(fitted: FittedImage) -> Self {
+        let mut payload = Self::new(fitted.image, fitted.encoding);
+        payload.scaled = fitted.scaled;
+        if let Some(encoded) = fitted.encoded {
+            payload.encoded = OnceLock::from(encoded);
+        }
+        payload
+    }
+
+    pub fn with_extra_formats(
+        mut self,
+        formats: Vec<ExtraFormat>,
+        temp_dir: Option<PathBuf>,
+    ) -> Self {
+        self.extra_formats = formats;
+        self.temp_dir = temp_dir;
+        self
+    }
+
+    /// Mark this payload as superseded by a newer copy
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+
+    pub fn encoded(&self) -> Result<&[u8], AppError> {
+        if let Some(bytes) = self.encoded.get() {
+            return Ok(bytes);
+        }
+        let bytes = self.image.encode(self.encoding)?;
+        Ok(self.encoded.get_or_init(|| bytes))
+    }
+
+    /// Size of the encoded image, if it has been encoded
+    pub fn encoded_len(&self) -> Option<usize> {
+        self.encoded.get().map(Vec::len)
+    }
+
+    /// MIME types offered when every format is published, main encoding first
+    pub fn mime_types(&self) -> Vec<&'static str> {
+        std::iter::once(self.encoding.mime_type())
+            .chain(self.extra_formats.iter().map(ExtraFormat::mime_type))
+            .collect()
+    }
+
+    /// The main encoding followed by every extra format, built on first use
+    pub fn representations(&self) -> Result<&[Representation], AppError> {
+        if let Some(representations) = self.representations.get() {
+            return Ok(representations);
+        }
+        let encoded = self.encoded()?;
+        let mut representations = vec![Representation {
+            mime_type: self.encoding.mime_type(),
+            bytes: encoded.to_vec(),
+        }];
+        for format in &self.extra_formats {
+            representations.push(formats::build_extra(
+                *format,
+                &self.image,
+                self.encoding,
+                encoded,
+                self.temp_dir.as_deref(),
+            )?);
+        }
+        Ok(self.representations.get_or_init(|| representations))
+    }
+}
+
+/// User-configurable clipboard behaviour
+#[derive(Clone, serde::Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ClipboardConfig {
+    /// Backends to try, in priority order
+    pub backends: Vec<BackendKind>,
+    pub timeouts_ms: HashMap<BackendKind, u64>,
+    /// Keep serving the last copy from a helper process after the app quits (Linux)
+    pub persist_after_exit: bool,
+    /// Extra formats to publish with each copy, where the backend supports it
+    pub formats: ClipboardFormats,
+    /// Recent copies kept for re-copying; 0 disables the history
+    pub history_size: usize,
+    /// Save the history to disk so it survives restarts
+    pub persist_history: bool,
+    pub selection: SelectionMode,
+}
+
+/// Which selections a copy is written to
+#[derive(Clone, Copy, Default, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SelectionMode {
+    #[default]
+    Clipboard,
+    /// CLIPBOARD and PRIMARY, so both Ctrl+V and middle-click paste the copy
+    Both,
+    Primary,
+}
+
+impl SelectionMode {
+    /// Selections to write, in order
+    pub fn targets(&self) -> &'static [Selection] {
+        match self {
+            SelectionMode::Clipboard => &[Selection::Clipboard],
+            SelectionMode::Both => &[Selection::Clipboard, Selection::Primary],
+            SelectionMode::Primary => &[Selection::Primary],
+        }
+    }
+}
+
+impl Default for ClipboardConfig {
+    fn default() -> Self {
+        Self {
+            backends: vec![
+                BackendKind::Arboard,
+                BackendKind::WlClipboard,
+                BackendKind::WlCopy,
+                BackendKind::Xclip,
+                BackendKind::Xsel,
+            ],
+            timeouts_ms: HashMap::new(),
+            persist_after_exit: false,
+            formats: ClipboardFormats::default(),
+            history_size: history::DEFAULT_CAPACITY,
+            persist_history: false,
+            selection: SelectionMode::default(),
+        }
+    }
+}
+
+impl ClipboardConfig {
+    pub fn timeout_for(&self, kind: BackendKind) -> Duration {
+        self.timeouts_ms
+            .get(&kind)
+            .map(|ms| Duration::from_millis(*ms))
+            .unwrap_or_else(|| kind.default_timeout())
+    }
+}
+
+pub struct ClipboardState {
+    pub config: Mutex<ClipboardConfig>,
+    /// Cache directory for files behind `text/uri-list` copies
+    pub temp_dir: Option<PathBuf>,
+}
+
+impl ClipboardState {
+    /// Add the configured extra formats to `payload`
+    pub fn with_configured_formats(
+        &self,
+        payload: ClipboardPayload,
+        jpeg_quality: u8,
+    ) -> Arc<ClipboardPayload> {
+        let formats = self.config.lock().unwrap().formats;
+        let extras = formats.extras(payload.encoding, jpeg_quality);
+        Arc::new(payload.with_extra_formats(extras, self.temp_dir.clone()))
+    }
+}
+
+#[derive(Clone, serde::Serialize)]
+pub struct BackendFailure {
+    pub backend: BackendKind,
+    pub selection: Selection,
+    pub error: AppError,
+}
+
+/// What actually went on the clipboard, after any budget was applied
+#[derive(Clone, serde::Serialize)]
+pub struct CopiedImage {
+    pub width: u32,
+    pub height: u32,
+    /// Size of the published image, if it is our encoded bytes
+    pub bytes: Option<usize>,
+    pub scaled: bool,
+}
+
+impl CopiedImage {
+    fn new(payload: &ClipboardPayload, published_encoded: bool) -> Self {
+        Self {
+            width: payload.image.width,
+            height: payload.image.height,
+            bytes: payload.encoded_len().filter(|_| published_encoded),
+            scaled: payload.scaled,
+        }
+    }
+}
+
+#[derive(Clone, serde::Serialize)]
+pub struct ClipboardCopyResult {
+    pub success: bool,
+    pub error: Option<AppError>,
+    pub version: u32,
+    /// A newer copy replaced this one before it reached the clipboard
+    pub superseded: bool,
+    /// The backend that ended up owning the clipboard
+    pub backend: Option<BackendKind>,
+    /// MIME types that backend published
+    pub formats: Vec<&'static str>,
+    /// Selections the copy was written to; with [`SelectionMode::Both`] this
+    /// can be just one of them if the other failed
+    pub selections: Vec<Selection>,
+    pub image: Option<CopiedImage>,
+    /// Backends tried before `backend`, or all of them if the copy failed
+    pub failures: Vec<BackendFailure>,
+}
+
+impl ClipboardCopyResult {
+    pub fn superseded(version: u32) -> Self {
+        Self {
+            success: false,
+            error: None,
+            version,
+            superseded: true,
+            backend: None,
+            formats: Vec::new(),
+            selections: Vec::new(),
+            image: None,
+            failures: Vec::new(),
+        }
+    }
+
+    /// A copy that failed before reaching any backend
+    pub fn failed(version: u32, error: AppError) -> Self {
+        Self {
+            success: false,
+            error: Some(error),
+            version,
+            superseded: false,
+            backend: None,
+            formats: Vec::new(),
+            selections: Vec::new(),
+            image: None,
+            failures: Vec::new(),
+        }
+    }
+
+    pub fn from_outcome(version: u32, payload: &ClipboardPayload, outcome: CopyOutcome) -> Self {
+        match outcome {
+            CopyOutcome::Copied {
+                backend,
+                formats,
+                published_encoded,
+                selections,
+                failures,
+            } => Self {
+                success: true,
+                error: None,
+                version,
+                superseded: false,
+                backend: Some(backend),
+                formats,
+                selections,
+                image: Some(CopiedImage::new(payload, published_encoded)),
+                failures,
+            },
+            CopyOutcome::Failed { failures } => Self {
+                success: false,
+                // The last backend's error, so its kind reaches the frontend
+                error: Some(match failures.last() {
+                    Some(last) => last.error.clone(),
+                    None => AppError::unavailable(None, "No clipboard backends configured"),
+                }),
+                version,
+                superseded: false,
+                backend: None,
+                formats: Vec::new(),
+                selections: Vec::new(),
+                image: None,
+                failures,
+            },
+            CopyOutcome::Cancelled => Self::superseded(version),
+        }
+    }
+}
+
+pub enum CopyOutcome {
+    Copied {
+        /// Backend that wrote the first selection in `selections`
+        backend: BackendKind,
+        formats: Vec<&'static str>,
+        /// Whether that backend published the payload's encoded bytes
+        published_encoded: bool,
+        selections: Vec<Selection>,
+        failures: Vec<BackendFailure>,
+    },
+    Failed {
+        failures: Vec<BackendFailure>,
+    },
+    Cancelled,
+}
+
+/// Write the payload to each configured selection, trying backends in order
+/// until one succeeds or the payload is cancelled.
+///
+/// With a byte budget, backends that publish the encoded bytes it was fitted
+/// to are tried first; after that, when extra formats are requested, those
+/// that can publish several formats at once. Either way backends keep their
+/// relative order. The copy succeeds if at least one selection was written.
+pub fn copy_with_backends(
+    backends: &[Box<dyn ClipboardBackend>],
+    config: &ClipboardConfig,
+    payload: &Arc<ClipboardPayload>,
+) -> CopyOutcome {
+    let needs_encoded = payload.max_bytes.is_some();
+    let needs_multiple = !payload.extra_formats.is_empty();
+    let mut ordered: Vec<_> = backends.iter().map(Box::as_ref).collect();
+    ordered.sort_by_key(|backend| {
+        (
+            needs_encoded && !backend.publishes_encoded(),
+            needs_multiple && !backend.supports_multiple_formats(),
+        )
+    });
+
+    let mut failures = Vec::new();
+    let mut selections = Vec::new();
+    let mut first: Option<&dyn ClipboardBackend> = None;
+    for &selection in config.selection.targets() {
+        let written = copy_to_selection(&ordered, config, payload, selection, &mut failures);
+        if payload.is_cancelled() {
+            return CopyOutcome::Cancelled;
+        }
+        if let Some(backend) = written {
+            selections.push(selection);
+            first.get_or_insert(backend);
+        }
+    }
+
+    match first {
+        Some(backend) => CopyOutcome::Copied {
+            backend: backend.kind(),
+            formats: backend.published_formats(payload),
+            published_encoded: backend.publishes_encoded(),
+            selections,
+            failures,
+        },
+        None => CopyOutcome::Failed { failures },
+    }
+}
+
+/// The backend that wrote `selection`, if any; failures are appended to `failures`
+fn copy_to_selection<'a>(
+    backends: &[&'a dyn ClipboardBackend],
+    config: &ClipboardConfig,
+    payload: &Arc<ClipboardPayload>,
+    selection: Selection,
+    failures: &mut Vec<BackendFailure>,
+) -> Option<&'a dyn ClipboardBackend> {
+    for backend in backends {
+        if payload.is_cancelled() {
+            return None;
+        }
+        let kind = backend.kind();
+        match backend.set_image(payload, selection, config.timeout_for(kind)) {
+            Ok(()) => return Some(*backend),
+            Err(_) if payload.is_cancelled() => return None,
+            Err(error) => failures.push(BackendFailure {
+                backend: kind,
+                selection,
+                error,
+            }),
+        }
+    }
+    None
+}
+
+/// Hand the last copied image to a helper process so it survives the app exiting.
+///
+/// Call right before `std::process::exit`.
+pub fn hand_off_before_exit(app: &AppHandle) {
+    let persist = app
+        .state::<ClipboardState>()
+        .config
+        .lock()
+        .unwrap()
+        .persist_after_exit;
+    if !persist {
+        return;
+    }
+    // Both are served from threads inside this process
+    if let Some((BackendKind::Arboard | BackendKind::WlClipboard, selection, payload)) =
+        app.state::<CopyQueue>().last_copied()
+    {
+        persist::spawn_helper(&payload, selection).log_error("Failed to hand off clipboard");
+    }
+}
+
+#[tauri::command]
+pub fn configure_clipboard(
+    state: State<ClipboardState>,
+    history: State<CopyHistory>,
+    config: ClipboardConfig,
+) {
+    history.set_persist(config.persist_history);
+    history.set_capacity(config.history_size);
+    *state.config.lock().unwrap() = config;
+}
+
+/// Queue a clipboard copy of raw RGBA pixels.
+///
+/// The body is the raw pixel buffer; dimensions, version and the fallback
+/// encoding are passed as `x-*` headers so no base64 or PNG round-trip is needed.
+/// `x-max-dimension` and `x-max-bytes` optionally fit the copy into a budget
+/// on the copy thread; with a byte budget, backends that publish our encoded
+/// bytes are preferred, since arboard re-encodes the pixels itself.
+/// Results arrive later through the `clipboard-copy-result` event, including
+/// failures to fit the budget.
+#[tauri::command]
+pub async fn queue_clipboard_copy_rgba(
+    request: Request<'_>,
+    state: State<'_, ClipboardState>,
+    queue: State<'_, CopyQueue>,
+) -> Result<(), AppError> {
+    let (width, height, pixels) = rgba_body(&request)?;
+    let version: u32 = parse_header(&request, "x-version")?;
+    let jpeg_quality = parse_header(&request, "x-jpeg-quality").unwrap_or(85);
+    let encoding = match header_value(&request, "x-format").unwrap_or("png") {
+        "jpeg" => CopyEncoding::Jpeg {
+            quality: jpeg_quality,
+        },
+        _ => CopyEncoding::Png,
+    };
+
+    let document_id = header_value(&request, "x-document-id")
+        .unwrap_or_default()
+        .to_string();
+    let document_name = text_header(&request, "x-document-name");
+    // 0 or missing means no limit
+    let budget = CopyBudget {
+        max_dimension: parse_header(&request, "x-max-dimension")
+            .ok()
+            .filter(|max| *max > 0),
+        max_bytes: parse_header(&request, "x-max-bytes")
+            .ok()
+            .filter(|max| *max > 0),
+    };
+    let image = RgbaImage {
+        width,
+        height,
+        pixels: pixels.to_vec(),
+    };
+    let payload =
+        state.with_configured_formats(ClipboardPayload::new(image, encoding), jpeg_quality);
+    let config = state.config.lock().unwrap().clone();
+
+    queue.push(CopyJob {
+        document_id,
+        document_name,
+        version,
+        from_history: false,
+        payload,
+        budget,
+        config,
+    });
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn budgeted_payload(max_bytes: Option<usize>) -> Arc<ClipboardPayload> {
+        let image = RgbaImage {
+            width: 2,
+            height: 2,
+            pixels: vec![128; 16],
+        };
+        let mut payload = ClipboardPayload::new(image, CopyEncoding::Png);
+        payload.max_bytes = max_bytes;
+        payload.encoded().unwrap();
+        Arc::new(payload)
+    }
+
+    fn copy(backends: &[&MockBackend], payload: &Arc<ClipboardPayload>) -> ClipboardCopyResult {
+        let backends: Vec<Box<dyn ClipboardBackend>> = backends
+            .iter()
+            .map(|mock| Box::new((*mock).clone()) as Box<dyn ClipboardBackend>)
+            .collect();
+        let outcome = copy_with_backends(&backends, &ClipboardConfig::default(), payload);
+        ClipboardCopyResult::from_outcome(1, payload, outcome)
+    }
+
+    #[test]
+    fn falls_back_to_the_next_backend() {
+        let failing = MockBackend {
+            fail_with: Some(AppError::unavailable(Some("mock"), "no display")),
+            ..MockBackend::default()
+        };
+        let working = MockBackend::default();
+
+        let result = copy(&[&failing, &working], &budgeted_payload(None));
+        assert!(result.success);
+        assert_eq!(result.backend, Some(BackendKind::Mock));
+        assert_eq!(result.failures.len(), 1);
+        assert!(matches!(
+            result.failures[0].error,
+            AppError::BackendUnavailable { .. }
+        ));
+        assert!(failing.copies.lock().unwrap().is_empty());
+        assert_eq!(working.copies.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn byte_budget_prefers_backends_publishing_encoded_bytes() {
+        let reencoding = MockBackend {
+            reencodes: true,
+            ..MockBackend::default()
+        };
+        let encoded = MockBackend::default();
+        let payload = budgeted_payload(Some(1024));
+
+        let result = copy(&[&reencoding, &encoded], &payload);
+        assert!(result.success);
+        assert!(reencoding.copies.lock().unwrap().is_empty());
+        assert_eq!(encoded.copies.lock().unwrap().len(), 1);
+        let bytes = result.image.and_then(|image| image.bytes);
+        assert_eq!(bytes, payload.encoded_len());
+    }
+
+    #[test]
+    fn reencoded_copy_reports_no_size() {
+        let reencoding = MockBackend {
+            reencodes: true,
+            ..MockBackend::default()
+        };
+        let encoded = MockBackend::default();
+        let payload = budgeted_payload(None);
+
+        // Without a byte budget the configured order is kept
+        let result = copy(&[&reencoding, &encoded], &payload);
+        assert!(result.success);
+        assert_eq!(reencoding.copies.lock().unwrap().len(), 1);
+        assert!(encoded.copies.lock().unwrap().is_empty());
+        assert_eq!(result.image.and_then(|image| image.bytes), None);
+    }
+
+    #[test]
+    fn jpeg_copies_flatten_transparency_onto_white() {
+        let image = RgbaImage {
+            width: 8,
+            height: 8,
+            pixels: vec![0; 8 * 8 * 4],
+        };
+        let jpeg = image.encode(CopyEncoding::Jpeg { quality: 90 }).unwrap();
+        let decoded = image::load_from_memory(&jpeg).unwrap().to_rgb8();
+        assert!(decoded.pixels().all(|px| px.0.iter().all(|c| *c > 250)));
+    }
+}
@@ -0,0 +1,101 @@
+//! Keep the last copied image on the clipboard after the app exits.
+//!
+//! On X11 and Wayland the clipboard is served by whichever process set it, so
+//! the content vanishes when that process quits. Before exiting we hand the
+//! last image to a detached copy of our own binary, started with
+//! [`HELPER_ARG`] and the selection name, which serves it until another
+//! application takes over.
+
+use std::sync::Arc;
+
+use super::{ClipboardPayload, Selection};
+use crate::error::AppError;
+
+pub const HELPER_ARG: &str = "--clipboard-helper";
+
+/// Spawn the helper process and pipe it the payload.
+///
+/// Only called when the last copy was served from inside the app (arboard or
+/// wl-clipboard); CLI backends such as wl-copy and xclip already fork their
+/// own server. The helper only keeps the image itself, not extra formats,
+/// and only on the one selection that backend wrote first.
+#[cfg(target_os = "linux")]
+pub fn spawn_helper(payload: &Arc<ClipboardPayload>, selection: Selection) -> Result<(), AppError> {
+    use std::io::Write;
+    use std::os::unix::process::CommandExt;
+    use std::process::{Command, Stdio};
+
+    let exe = std::env::current_exe()?;
+    let mut child = Command::new(&exe)
+        .arg(HELPER_ARG)
+        .arg(selection.name())
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        // Own process group, so signals aimed at the app don't reach the helper
+        .process_group(0)
+        .spawn()
+        .map_err(|e| AppError::io(Some(&exe), e))?;
+
+    let image = &payload.image;
+    let mut stdin = child
+        .stdin
+        .take()
+        .ok_or_else(|| AppError::internal("Clipboard helper has no stdin"))?;
+    stdin
+        .write_all(&image.width.to_le_bytes())
+        .and_then(|_| stdin.write_all(&image.height.to_le_bytes()))
+        .and_then(|_| stdin.write_all(&image.pixels))
+        .map_err(AppError::from)
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn spawn_helper(
+    _payload: &Arc<ClipboardPayload>,
+    _selection: Selection,
+) -> Result<(), AppError> {
+    Ok(())
+}
+
+/// Entry point of the helper process. Returns the process exit code.
+#[cfg(target_os = "linux")]
+pub fn run_helper() -> i32 {
+    use arboard::{Clipboard, ImageData, LinuxClipboardKind, SetExtLinux};
+    use std::borrow::Cow;
+    use std::io::Read;
+
+    let mut input = Vec::new();
+    if std::io::stdin().read_to_end(&mut input).is_err() || input.len() < 8 {
+        return 1;
+    }
+    let width = u32::from_le_bytes([input[0], input[1], input[2], input[3]]) as usize;
+    let height = u32::from_le_bytes([input[4], input[5], input[6], input[7]]) as usize;
+    let pixels = &input[8..];
+    if pixels.len() != width * height * 4 {
+        return 1;
+    }
+
+    let kind = match std::env::args().nth(2).as_deref() {
+        Some("PRIMARY") => LinuxClipboardKind::Primary,
+        _ => LinuxClipboardKind::Clipboard,
+    };
+    let Ok(mut clipboard) = Clipboard::new() else {
+        return 1;
+    };
+    // Blocks until another application takes ownership of the selection
+    let result = clipboard.set().clipboard(kind).wait().image(ImageData {
+        width,
+        height,
+        bytes: Cow::Borrowed(pixels),
+    });
+    if result.is_ok() {
+        0
+    } else {
+        1
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn run_helper() -> i32 {
+    0
+}
@@ -0,0 +1,347 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Condvar, Mutex};
+
+use super::{
+    copy_with_backends, BackendKind, ClipboardBackend, ClipboardConfig, ClipboardCopyResult,
+    ClipboardPayload, CopyBudget, CopyHistory, CopyOutcome, Selection,
+};
+use crate::error::AppError;
+
+type BackendFactory = dyn Fn(BackendKind) -> Box<dyn ClipboardBackend> + Send + Sync;
+type ResultHandler = dyn Fn(ClipboardCopyResult) + Send + Sync;
+
+/// A single copy request waiting for (or running on) the consumer thread
+pub struct CopyJob {
+    pub document_id: String,
+    /// Shown in clipboard history labels
+    pub document_name: Option<String>,
+    pub version: u32,
+    /// Re-copy of an older version, exempt from the out-of-order check
+    pub from_history: bool,
+    pub payload: Arc<ClipboardPayload>,
+    /// Limits the payload is fitted into before it is copied
+    pub budget: CopyBudget,
+    pub config: ClipboardConfig,
+}
+
+#[derive(Default)]
+struct QueueState {
+    pending: Option<CopyJob>,
+    in_flight: Option<Arc<ClipboardPayload>>,
+    latest_versions: HashMap<String, u32>,
+    last_copied: Option<(BackendKind, Selection, Arc<ClipboardPayload>)>,
+}
+
+struct QueueInner {
+    state: Mutex<QueueState>,
+    wakeup: Condvar,
+    create_backend: Box<BackendFactory>,
+    history: CopyHistory,
+    on_result: Box<ResultHandler>,
+}
+
+/// Latest-wins clipboard copy queue with a single consumer thread.
+///
+/// At most one job waits while another runs. Pushing a new job replaces the
+/// waiting one and cancels the running one, so an older document version can
+/// never land on the clipboard after a newer one. Fitting a job into its
+/// budget happens on the consumer thread too, so it is cancelled the same way.
+#[derive(Clone)]
+pub struct CopyQueue {
+    inner: Arc<QueueInner>,
+}
+
+impl CopyQueue {
+    /// Start the consumer thread. Successful copies are recorded in `history`.
+    pub fn start<F, R>(create_backend: F, history: CopyHistory, on_result: R) -> Self
+    where
+        F: Fn(BackendKind) -> Box<dyn ClipboardBackend> + Send + Sync + 'static,
+        R: Fn(ClipboardCopyResult) + Send + Sync + 'static,
+    {
+        let inner = Arc::new(QueueInner {
+            state: Mutex::new(QueueState::default()),
+            wakeup: Condvar::new(),
+            create_backend: Box::new(create_backend),
+            history,
+            on_result: Box::new(on_result),
+        });
+
+        let consumer = Arc::clone(&inner);
+        std::thread::Builder::new()
+            .name("clipboard-copy".into())
+            .spawn(move || consumer.run())
+            .expect("failed to spawn clipboard copy thread");
+
+        Self { inner }
+    }
+
+    pub fn push(&self, job: CopyJob) {
+        let mut superseded = Vec::new();
+        {
+            let mut state = self.inner.state.lock().unwrap();
+
+            // Out-of-order arrival of an older version for the same document
+            if !job.from_history {
+                let latest = state.latest_versions.get(&job.document_id).copied();
+                if latest.is_some_and(|latest| job.version < latest) {
+                    drop(state);
+                    (self.inner.on_result)(ClipboardCopyResult::superseded(job.version));
+                    return;
+                }
+
+                state
+                    .latest_versions
+                    .insert(job.document_id.clone(), job.version);
+            }
+            if let Some(in_flight) = &state.in_flight {
+                in_flight.cancel();
+            }
+            if let Some(old) = state.pending.replace(job) {
+                superseded.push(old.version);
+            }
+        }
+        self.inner.wakeup.notify_one();
+
+        for version in superseded {
+            (self.inner.on_result)(ClipboardCopyResult::superseded(version));
+        }
+    }
+
+    /// The most recent payload that reached the clipboard, and which backend
+    /// and selection took it
+    pub fn last_copied(&self) -> Option<(BackendKind, Selection, Arc<ClipboardPayload>)> {
+        self.inner.state.lock().unwrap().last_copied.clone()
+    }
+}
+
+impl QueueInner {
+    fn run(&self) {
+        loop {
+            let mut job = {
+                let mut state = self.state.lock().unwrap();
+                let job = loop {
+                    match state.pending.take() {
+                        Some(job) => break job,
+                        None => state = self.wakeup.wait(state).unwrap(),
+                    }
+                };
+                state.in_flight = Some(Arc::clone(&job.payload));
+                job
+            };
+
+            if !job.budget.is_unlimited() {
+                match self.fit(&job) {
+                    Ok(payload) => job.payload = payload,
+                    Err(error) => {
+                        self.state.lock().unwrap().in_flight = None;
+                        (self.on_result)(match error {
+                            AppError::Cancelled => ClipboardCopyResult::superseded(job.version),
+                            error => ClipboardCopyResult::failed(job.version, error),
+                        });
+                        continue;
+                    }
+                }
+            }
+
+            let backends: Vec<_> = job
+                .config
+                .backends
+                .iter()
+                .map(|kind| (self.create_backend)(*kind))
+                .collect();
+            let outcome = copy_with_backends(&backends, &job.config, &job.payload);
+
+            {
+                let mut state = self.state.lock().unwrap();
+                state.in_flight = None;
+                if let CopyOutcome::Copied {
+                    backend,
+                    selections,
+                    ..
+                } = &outcome
+                {
+                    state.last_copied = Some((*backend, selections[0], Arc::clone(&job.payload)));
+                }
+            }
+            if matches!(outcome, CopyOutcome::Copied { .. }) {
+                self.history.record(&job);
+            }
+            (self.on_result)(ClipboardCopyResult::from_outcome(
+                job.version,
+                &job.payload,
+                outcome,
+            ));
+        }
+    }
+
+    /// Fit the job's payload into its budget. The fitted payload becomes the
+    /// one a newer job cancels.
+    fn fit(&self, job: &CopyJob) -> Result<Arc<ClipboardPayload>, AppError> {
+        let source = &job.payload;
+        let fitted = job
+            .budget
+            .fit(&source.image, source.encoding, || source.is_cancelled())?;
+        let mut payload = ClipboardPayload::from_fitted(fitted)
+            .with_extra_formats(source.extra_formats.clone(), source.temp_dir.clone());
+        payload.max_bytes = job.budget.max_bytes;
+        let payload = Arc::new(payload);
+
+        let mut state = self.state.lock().unwrap();
+        if source.is_cancelled() {
+            return Err(AppError::Cancelled);
+        }
+        state.in_flight = Some(Arc::clone(&payload));
+        Ok(payload)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+    use std::time::Duration;
+
+    use super::*;
+    use crate::clipboard::{CopyEncoding, MockBackend, RgbaImage};
+
+    const RESULT_TIMEOUT: Duration = Duration::from_secs(5);
+
+    fn start(mock: &MockBackend) -> (CopyQueue, mpsc::Receiver<ClipboardCopyResult>) {
+        let mock = mock.clone();
+        let (tx, rx) = mpsc::channel();
+        let queue = CopyQueue::start(
+            move |_| Box::new(mock.clone()),
+            CopyHistory::open(None),
+            move |result| {
+                tx.send(result).ok();
+            },
+        );
+        (queue, rx)
+    }
+
+    fn job(version: u32) -> CopyJob {
+        let image = RgbaImage {
+            width: 2,
+            height: 2,
+            pixels: vec![255; 16],
+        };
+        CopyJob {
+            document_id: "doc".into(),
+            document_name: None,
+            version,
+            from_history: false,
+            payload: Arc::new(ClipboardPayload::new(image, CopyEncoding::Png)),
+            budget: CopyBudget::default(),
+            config: ClipboardConfig {
+                backends: vec![BackendKind::Mock],
+                ..ClipboardConfig::default()
+            },
+        }
+    }
+
+    fn next(results: &mpsc::Receiver<ClipboardCopyResult>) -> ClipboardCopyResult {
+        results
+            .recv_timeout(RESULT_TIMEOUT)
+            .expect("no copy result")
+    }
+
+    #[test]
+    fn copies_through_the_backend() {
+        let mock = MockBackend::default();
+        let (queue, results) = start(&mock);
+        queue.push(job(1));
+
+        let result = next(&results);
+        assert!(result.success);
+        assert_eq!(result.version, 1);
+        assert_eq!(result.backend, Some(BackendKind::Mock));
+        assert_eq!(result.selections, [Selection::Clipboard]);
+        assert_eq!(mock.copies.lock().unwrap().len(), 1);
+        assert!(queue.last_copied().is_some());
+    }
+
+    #[test]
+    fn reports_backend_failures() {
+        let mock = MockBackend {
+            fail_with: Some(AppError::unavailable(Some("mock"), "no display")),
+            ..MockBackend::default()
+        };
+        let (queue, results) = start(&mock);
+        queue.push(job(1));
+
+        let result = next(&results);
+        assert!(!result.success);
+        assert!(!result.superseded);
+        assert!(matches!(
+            result.error,
+            Some(AppError::BackendUnavailable { .. })
+        ));
+        assert_eq!(result.failures.len(), 1);
+        assert!(queue.last_copied().is_none());
+    }
+
+    #[test]
+    fn newer_copy_cancels_older_one() {
+        let mock = MockBackend {
+            delay: Duration::from_millis(200),
+            ..MockBackend::default()
+        };
+        let (queue, results) = start(&mock);
+        queue.push(job(1));
+        queue.push(job(2));
+
+        // Version 1 is cancelled midway or replaced while waiting
+        let first = next(&results);
+        assert_eq!(first.version, 1);
+        assert!(first.superseded);
+        let second = next(&results);
+        assert_eq!(second.version, 2);
+        assert!(second.success);
+
+        let copies = mock.copies.lock().unwrap();
+        assert_eq!(copies.len(), 1);
+    }
+
+    #[test]
+    fn drops_older_version_arriving_late() {
+        let mock = MockBackend::default();
+        let (queue, results) = start(&mock);
+        queue.push(job(2));
+        assert!(next(&results).success);
+
+        queue.push(job(1));
+        let late = next(&results);
+        assert_eq!(late.version, 1);
+        assert!(late.superseded);
+        assert_eq!(mock.copies.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn fits_budget_before_copying() {
+        let mock = MockBackend::default();
+        let (queue, results) = start(&mock);
+        let mut job = job(1);
+        job.budget.max_dimension = Some(1);
+        queue.push(job);
+
+        let result = next(&results);
+        assert!(result.success);
+        let image = result.image.expect("no copied image");
+        assert_eq!((image.width, image.height), (1, 1));
+        assert!(image.scaled);
+    }
+
+    #[test]
+    fn reports_budget_that_cannot_be_met() {
+        let mock = MockBackend::default();
+        let (queue, results) = start(&mock);
+        let mut job = job(1);
+        job.budget.max_bytes = Some(1);
+        queue.push(job);
+
+        let result = next(&results);
+        assert!(!result.success);
+        assert!(!result.superseded);
+        assert!(matches!(result.error, Some(AppError::EncodeFailed { .. })));
+        assert!(mock.copies.lock().unwrap().is_empty());
+    }
+}
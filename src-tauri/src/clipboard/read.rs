@@ -0,0 +1,361 @@
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use tauri::ipc::Response;
+
+use super::backend::arboard_error;
+use crate::cli;
+use crate::error::AppError;
+use crate::headers::framed;
+
+/// Image MIME types we look for, in order of preference
+const IMAGE_MIME_TYPES: &[&str] = &[
+    "image/png",
+    "image/webp",
+    "image/jpeg",
+    "image/gif",
+    "image/bmp",
+    "image/x-bmp",
+];
+
+/// Formats the webview can display directly, so their bytes are passed through as-is
+const WEBVIEW_MIME_TYPES: &[&str] = &[
+    "image/png",
+    "image/jpeg",
+    "image/webp",
+    "image/gif",
+    "image/bmp",
+];
+
+/// What a paste should open
+#[derive(Clone, serde::Serialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum ClipboardReadResult {
+    /// A single image, whose `mime_type`-encoded bytes follow in the response
+    Image {
+        width: u32,
+        height: u32,
+        mime_type: String,
+    },
+    /// Files copied from a file manager or pasted as paths, to open as tabs
+    Files { paths: Vec<String> },
+}
+
+struct ClipboardImage {
+    width: u32,
+    height: u32,
+    mime_type: &'static str,
+    bytes: Vec<u8>,
+}
+
+/// A read result and the encoded image, empty for files
+pub type ClipboardContent = (ClipboardReadResult, Vec<u8>);
+
+impl From<ClipboardImage> for ClipboardContent {
+    fn from(image: ClipboardImage) -> Self {
+        let result = ClipboardReadResult::Image {
+            width: image.width,
+            height: image.height,
+            mime_type: image.mime_type.to_string(),
+        };
+        (result, image.bytes)
+    }
+}
+
+/// A way of reading the system clipboard
+trait ClipboardReader {
+    fn image(&mut self) -> Result<Option<ClipboardImage>, AppError>;
+    fn uri_list(&mut self) -> Result<Option<String>, AppError>;
+    fn text(&mut self) -> Result<Option<String>, AppError>;
+}
+
+type ReadList = fn(&mut dyn ClipboardReader) -> Result<Option<String>, AppError>;
+
+struct ArboardReader(arboard::Clipboard);
+
+impl ClipboardReader for ArboardReader {
+    fn image(&mut self) -> Result<Option<ClipboardImage>, AppError> {
+        use arboard::Error;
+        use image::codecs::png::PngEncoder;
+        use image::{ExtendedColorType, ImageEncoder};
+
+        let image = match self.0.get_image() {
+            Ok(image) => image,
+            Err(Error::ContentNotAvailable) => return Ok(None),
+            Err(e) => return Err(arboard_error(e)),
+        };
+        let (width, height) = (image.width as u32, image.height as u32);
+        let mut bytes = Vec::new();
+        PngEncoder::new(&mut bytes)
+            .write_image(&image.bytes, width, height, ExtendedColorType::Rgba8)
+            .map_err(|e| AppError::encode("PNG", e))?;
+        Ok(Some(ClipboardImage {
+            width,
+            height,
+            mime_type: "image/png",
+            bytes,
+        }))
+    }
+
+    fn uri_list(&mut self) -> Result<Option<String>, AppError> {
+        match self.0.get().file_list() {
+            Ok(paths) if !paths.is_empty() => Ok(Some(
+                paths
+                    .iter()
+                    .map(|p| p.to_string_lossy().into_owned())
+                    .collect::<Vec<_>>()
+                    .join("\n"),
+            )),
+            Ok(_) | Err(arboard::Error::ContentNotAvailable) => Ok(None),
+            Err(e) => Err(arboard_error(e)),
+        }
+    }
+
+    fn text(&mut self) -> Result<Option<String>, AppError> {
+        match self.0.get_text() {
+            Ok(text) => Ok(Some(text)),
+            Err(arboard::Error::ContentNotAvailable) => Ok(None),
+            Err(e) => Err(arboard_error(e)),
+        }
+    }
+}
+
+/// Wayland fallback through `wl-paste`, mirroring the `wl-copy` backend
+struct WlPasteReader {
+    types: Option<Vec<String>>,
+}
+
+impl WlPasteReader {
+    fn run(args: &[&str]) -> Result<Vec<u8>, AppError> {
+        let output = Command::new("wl-paste")
+            .args(args)
+            .stdin(Stdio::null())
+            .output()
+            .map_err(|e| {
+                AppError::unavailable(Some("wl-paste"), format!("Failed to spawn wl-paste: {}", e))
+            })?;
+        if !output.status.success() {
+            return Err(AppError::unavailable(
+                Some("wl-paste"),
+                format!(
+                    "wl-paste exited with {}: {}",
+                    output.status,
+                    String::from_utf8_lossy(&output.stderr).trim()
+                ),
+            ));
+        }
+        Ok(output.stdout)
+    }
+
+    fn types(&mut self) -> Result<&[String], AppError> {
+        if self.types.is_none() {
+            let listing = Self::run(&["--no-newline", "--list-types"])?;
+            self.types = Some(
+                String::from_utf8_lossy(&listing)
+                    .lines()
+                    .map(|line| line.trim().to_string())
+                    .collect(),
+            );
+        }
+        Ok(self.types.as_deref().unwrap_or_default())
+    }
+
+    fn read_type(&mut self, mime: &str) -> Result<Option<Vec<u8>>, AppError> {
+        if !self.types()?.iter().any(|t| t == mime) {
+            return Ok(None);
+        }
+        Self::run(&["--no-newline", "--type", mime]).map(Some)
+    }
+}
+
+impl ClipboardReader for WlPasteReader {
+    fn image(&mut self) -> Result<Option<ClipboardImage>, AppError> {
+        for mime in IMAGE_MIME_TYPES {
+            if let Some(bytes) = self.read_type(mime)? {
+                return decode_image_bytes(bytes, mime).map(Some);
+            }
+        }
+        Ok(None)
+    }
+
+    fn uri_list(&mut self) -> Result<Option<String>, AppError> {
+        self.read_type("text/uri-list")
+            .map(|bytes| bytes.map(|b| String::from_utf8_lossy(&b).into_owned()))
+    }
+
+    fn text(&mut self) -> Result<Option<String>, AppError> {
+        for mime in ["text/plain;charset=utf-8", "text/plain", "UTF8_STRING"] {
+            if let Some(bytes) = self.read_type(mime)? {
+                return Ok(Some(String::from_utf8_lossy(&bytes).into_owned()));
+            }
+        }
+        Ok(None)
+    }
+}
+
+/// Decode raw clipboard bytes, passing them through when the webview can show them
+fn decode_image_bytes(bytes: Vec<u8>, mime: &str) -> Result<ClipboardImage, AppError> {
+    use image::codecs::png::PngEncoder;
+    use image::{GenericImageView, ImageEncoder};
+
+    let image = image::load_from_memory(&bytes).map_err(|e| AppError::decode(Some(mime), e))?;
+    let (width, height) = image.dimensions();
+
+    if let Some(mime_type) = WEBVIEW_MIME_TYPES.iter().find(|m| **m == mime) {
+        return Ok(ClipboardImage {
+            width,
+            height,
+            mime_type,
+            bytes,
+        });
+    }
+
+    let rgba = image.to_rgba8();
+    let mut png = Vec::new();
+    PngEncoder::new(&mut png)
+        .write_image(&rgba, width, height, image::ExtendedColorType::Rgba8)
+        .map_err(|e| AppError::encode("PNG", e))?;
+    Ok(ClipboardImage {
+        width,
+        height,
+        mime_type: "image/png",
+        bytes: png,
+    })
+}
+
+/// Parse a `text/uri-list` or newline-separated paths into existing files
+/// the app can open, judged by their content like directory arguments
+fn parse_file_list(text: &str) -> Vec<String> {
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter_map(|line| {
+            let path = if line.starts_with("file://") {
+                url::Url::parse(line).ok()?.to_file_path().ok()?
+            } else {
+                PathBuf::from(line)
+            };
+            (path.is_absolute() && path.is_file() && cli::is_openable(&path)).then_some(path)
+        })
+        .filter_map(|path| path_to_string(&path))
+        .collect()
+}
+
+fn path_to_string(path: &Path) -> Option<String> {
+    path.canonicalize()
+        .ok()
+        .and_then(|p| p.to_str().map(String::from))
+}
+
+fn readers() -> Vec<Box<dyn ClipboardReader>> {
+    let mut readers: Vec<Box<dyn ClipboardReader>> = Vec::new();
+    if let Ok(clipboard) = arboard::Clipboard::new() {
+        readers.push(Box::new(ArboardReader(clipboard)));
+    }
+    if cfg!(target_os = "linux") && std::env::var_os("WAYLAND_DISPLAY").is_some() {
+        readers.push(Box::new(WlPasteReader { types: None }));
+    }
+    readers
+}
+
+/// Read whatever pasteable content the clipboard holds.
+///
+/// Tries image data first, then file URI lists, then plain-text paths, asking
+/// every reader for each kind before moving on to the next kind.
+pub fn read_clipboard() -> Result<ClipboardContent, AppError> {
+    let mut readers = readers();
+    if readers.is_empty() {
+        return Err(AppError::unavailable(None, "No clipboard access available"));
+    }
+    let mut errors = Vec::new();
+
+    for reader in readers.iter_mut() {
+        match reader.image() {
+            Ok(Some(image)) => return Ok(image.into()),
+            Ok(None) => {}
+            Err(e) => errors.push(e),
+        }
+    }
+
+    let list_sources: [ReadList; 2] = [|r| r.uri_list(), |r| r.text()];
+    for read_list in list_sources {
+        for reader in readers.iter_mut() {
+            match read_list(reader.as_mut()) {
+                Ok(Some(text)) => {
+                    let paths = parse_file_list(&text);
+                    if !paths.is_empty() {
+                        return Ok((ClipboardReadResult::Files { paths }, Vec::new()));
+                    }
+                }
+                Ok(None) => {}
+                Err(e) => errors.push(e),
+            }
+        }
+    }
+
+    // A reader error explains the empty result better than "nothing found"
+    Err(errors
+        .pop()
+        .unwrap_or_else(|| AppError::not_found("Clipboard holds no image or image file")))
+}
+
+/// Read the clipboard for a paste.
+///
+/// The response is framed (see `headers`): the [`ClipboardReadResult`] JSON,
+/// then the encoded image bytes if it is an image.
+#[tauri::command]
+pub async fn read_clipboard_image() -> Result<Response, AppError> {
+    let (result, bytes) = tokio::task::spawn_blocking(read_clipboard)
+        .await
+        .map_err(|e| AppError::internal(format!("Task join error: {}", e)))??;
+    Ok(Response::new(framed(&result, &bytes)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_uri_lists_and_plain_paths() {
+        let dir = std::env::temp_dir().join(format!("ursamarkup-paste-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let png = |name: &str| {
+            let path = dir.join(name);
+            image::RgbaImage::new(1, 1).save(&path).unwrap();
+            path_to_string(&path).unwrap()
+        };
+        let spaced = png("a shot.png");
+        let plain = png("plain.png");
+        std::fs::write(dir.join("notes.txt"), "not an image").unwrap();
+        let base = dir.display();
+
+        let cases: Vec<(String, Vec<&String>)> = vec![
+            // Comments and blank lines are skipped
+            (format!("# copied\n\n{}/plain.png\n", base), vec![&plain]),
+            // RFC 2483 lists end lines in CRLF
+            (
+                format!("file://{0}/plain.png\r\nfile://{0}/a%20shot.png\r\n", base),
+                vec![&plain, &spaced],
+            ),
+            (format!("{}/a shot.png", base), vec![&spaced]),
+            // Non-images, missing files and relative paths are dropped
+            (
+                format!(
+                    "{0}/notes.txt\nfile://{0}/missing.png\nplain.png\n{0}/plain.png",
+                    base
+                ),
+                vec![&plain],
+            ),
+            (String::from("https://example.com/shot.png"), vec![]),
+        ];
+        let results: Vec<_> = cases
+            .iter()
+            .map(|(text, _)| parse_file_list(text))
+            .collect();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        for ((text, expected), result) in cases.iter().zip(results) {
+            assert_eq!(&result.iter().collect::<Vec<_>>(), expected, "{:?}", text);
+        }
+    }
+}
@@ -0,0 +1,180 @@
+use std::fmt;
+use std::path::Path;
+use std::time::Duration;
+
+/// Error returned by every command and reported in copy results.
+///
+/// Serialized with a stable `kind` tag so the frontend can pick a message
+/// without parsing text; `message` carries the underlying error for logs.
+#[derive(Clone, Debug, serde::Serialize)]
+#[serde(tag = "kind")]
+pub enum AppError {
+    /// Image bytes could not be decoded
+    DecodeFailed {
+        format: Option<String>,
+        message: String,
+    },
+    /// Pixels could not be encoded to `format`
+    EncodeFailed {
+        format: String,
+        message: String,
+    },
+    /// A clipboard backend, tool or display server is missing or refused the request
+    BackendUnavailable {
+        backend: Option<String>,
+        message: String,
+    },
+    Timeout {
+        operation: String,
+        timeout_ms: u64,
+    },
+    Io {
+        path: Option<String>,
+        message: String,
+    },
+    PermissionDenied {
+        path: Option<String>,
+        message: String,
+    },
+    /// Malformed arguments or headers from the caller
+    InvalidInput {
+        message: String,
+    },
+    NotFound {
+        message: String,
+    },
+    /// Replaced by a newer request before it finished
+    Cancelled,
+    /// Window, menu or event failures inside Tauri
+    Internal {
+        message: String,
+    },
+}
+
+impl AppError {
+    pub fn decode(format: Option<&str>, error: impl fmt::Display) -> Self {
+        AppError::DecodeFailed {
+            format: format.map(String::from),
+            message: error.to_string(),
+        }
+    }
+
+    pub fn encode(format: &str, error: impl fmt::Display) -> Self {
+        AppError::EncodeFailed {
+            format: format.to_string(),
+            message: error.to_string(),
+        }
+    }
+
+    pub fn unavailable(backend: Option<&str>, message: impl Into<String>) -> Self {
+        AppError::BackendUnavailable {
+            backend: backend.map(String::from),
+            message: message.into(),
+        }
+    }
+
+    pub fn timeout(operation: impl Into<String>, timeout: Duration) -> Self {
+        AppError::Timeout {
+            operation: operation.into(),
+            timeout_ms: timeout.as_millis() as u64,
+        }
+    }
+
+    /// An I/O error on `path`, split out as `PermissionDenied` where it applies
+    pub fn io(path: Option<&Path>, error: std::io::Error) -> Self {
+        let path = path.map(|p| p.display().to_string());
+        let message = error.to_string();
+        match error.kind() {
+            std::io::ErrorKind::PermissionDenied => AppError::PermissionDenied { path, message },
+            std::io::ErrorKind::NotFound if path.is_some() => AppError::NotFound {
+                message: format!("{}: {}", path.unwrap_or_default(), message),
+            },
+            _ => AppError::Io { path, message },
+        }
+    }
+
+    pub fn invalid(message: impl Into<String>) -> Self {
+        AppError::InvalidInput {
+            message: message.into(),
+        }
+    }
+
+    pub fn not_found(message: impl Into<String>) -> Self {
+        AppError::NotFound {
+            message: message.into(),
+        }
+    }
+
+    pub fn internal(message: impl Into<String>) -> Self {
+        AppError::Internal {
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for AppError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AppError::DecodeFailed {
+                format: Some(format),
+                message,
+            } => write!(f, "Failed to decode {}: {}", format, message),
+            AppError::DecodeFailed { message, .. } => {
+                write!(f, "Failed to decode image: {}", message)
+            }
+            AppError::EncodeFailed { format, message } => {
+                write!(f, "Failed to encode {}: {}", format, message)
+            }
+            AppError::BackendUnavailable {
+                backend: Some(backend),
+                message,
+            } => write!(f, "{} unavailable: {}", backend, message),
+            AppError::BackendUnavailable { message, .. } => f.write_str(message),
+            AppError::Timeout {
+                operation,
+                timeout_ms,
+            } => write!(f, "{} timed out after {}ms", operation, timeout_ms),
+            AppError::Io {
+                path: Some(path),
+                message,
+            }
+            | AppError::PermissionDenied {
+                path: Some(path),
+                message,
+            } => write!(f, "{}: {}", path, message),
+            AppError::Io { message, .. }
+            | AppError::PermissionDenied { message, .. }
+            | AppError::InvalidInput { message }
+            | AppError::NotFound { message }
+            | AppError::Internal { message } => f.write_str(message),
+            AppError::Cancelled => f.write_str("Cancelled by a newer request"),
+        }
+    }
+}
+
+impl std::error::Error for AppError {}
+
+impl From<std::io::Error> for AppError {
+    fn from(error: std::io::Error) -> Self {
+        AppError::io(None, error)
+    }
+}
+
+impl From<tauri::Error> for AppError {
+    fn from(error: tauri::Error) -> Self {
+        AppError::internal(error.to_string())
+    }
+}
+
+/// Log failures that have no caller to report to, such as window and tray updates
+pub trait LogError {
+    fn log_error(self, context: &str);
+}
+
+impl<T, E: fmt::Display> LogError for Result<T, E> {
+    fn log_error(self, context: &str) {
+        if let Err(e) = self {
+            eprintln!("{}: {}", context, e);
+        }
+    }
+}
@@ -0,0 +1,145 @@
+//! Events the main window must not miss, such as files to open.
+//!
+//! Until the frontend has attached its listeners and called
+//! `frontend_ready`, these events are queued instead of emitted, then sent in
+//! the order they happened. A reload of the webview drops its listeners, so
+//! the queue is used again until it reports ready once more.
+
+use std::sync::Mutex;
+
+use serde::Serialize;
+use serde_json::Value;
+use tauri::{AppHandle, Emitter, Manager, State};
+
+use crate::error::LogError;
+
+#[derive(Default)]
+pub struct FrontendEvents {
+    state: Mutex<QueueState>,
+}
+
+impl FrontendEvents {
+    /// Emit `event` now if the frontend is listening, otherwise once it is
+    pub fn emit(&self, app: &AppHandle, event: &'static str, payload: impl Serialize) {
+        let payload = match serde_json::to_value(payload) {
+            Ok(payload) => payload,
+            Err(e) => {
+                eprintln!("Dropping {} event: {}", event, e);
+                return;
+            }
+        };
+        let mut state = self.state.lock().unwrap();
+        state.emit(event, payload, |event, payload| {
+            emit_now(app, event, payload)
+        });
+    }
+
+    /// The frontend is listening; send what was queued
+    fn ready(&self, app: &AppHandle) {
+        let mut state = self.state.lock().unwrap();
+        state.ready(|event, payload| emit_now(app, event, payload));
+    }
+
+    /// The webview is loading a page and has no listeners until it is ready
+    pub fn reset(&self) {
+        self.state.lock().unwrap().ready = false;
+    }
+}
+
+fn emit_now(app: &AppHandle, event: &'static str, payload: Value) {
+    app.emit(event, payload)
+        .log_error(&format!("Failed to emit {}", event));
+}
+
+/// The queue itself, which hands events to `send` once they can be delivered
+#[derive(Default)]
+struct QueueState {
+    ready: bool,
+    queued: Vec<(&'static str, Value)>,
+}
+
+impl QueueState {
+    fn emit(
+        &mut self,
+        event: &'static str,
+        payload: Value,
+        mut send: impl FnMut(&'static str, Value),
+    ) {
+        if self.ready {
+            send(event, payload);
+        } else {
+            self.queued.push((event, payload));
+        }
+    }
+
+    fn ready(&mut self, mut send: impl FnMut(&'static str, Value)) {
+        self.ready = true;
+        for (event, payload) in self.queued.drain(..) {
+            send(event, payload);
+        }
+    }
+}
+
+/// Emit `event` through the queue managed as app state
+pub fn emit_when_ready(app: &AppHandle, event: &'static str, payload: impl Serialize) {
+    app.state::<FrontendEvents>().emit(app, event, payload);
+}
+
+/// Called by the main window once its listeners for queued events are attached
+#[tauri::command]
+pub fn frontend_ready(app: AppHandle, events: State<FrontendEvents>) {
+    events.ready(&app);
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    /// Emit through `queue`, collecting what is sent
+    fn emit(queue: &mut QueueState, sent: &mut Vec<Value>, payload: Value) {
+        queue.emit("event", payload, |_, payload| sent.push(payload));
+    }
+
+    #[test]
+    fn queues_until_ready_then_flushes_in_order() {
+        let mut queue = QueueState::default();
+        let mut sent = Vec::new();
+        emit(&mut queue, &mut sent, json!(1));
+        emit(&mut queue, &mut sent, json!(2));
+        assert!(sent.is_empty());
+
+        queue.ready(|_, payload| sent.push(payload));
+        assert_eq!(sent, [json!(1), json!(2)]);
+        assert!(queue.queued.is_empty());
+    }
+
+    #[test]
+    fn passes_through_once_ready() {
+        let mut queue = QueueState::default();
+        let mut sent = Vec::new();
+        queue.ready(|_, payload| sent.push(payload));
+        emit(&mut queue, &mut sent, json!("now"));
+        assert_eq!(sent, [json!("now")]);
+    }
+
+    #[test]
+    fn buffers_again_after_reset() {
+        let events = FrontendEvents::default();
+        let mut sent = Vec::new();
+        events
+            .state
+            .lock()
+            .unwrap()
+            .ready(|_, payload| sent.push(payload));
+
+        // A reload drops the listeners until the page reports ready again
+        events.reset();
+        let mut queue = events.state.lock().unwrap();
+        emit(&mut queue, &mut sent, json!("reloaded"));
+        assert!(sent.is_empty());
+        queue.ready(|_, payload| sent.push(payload));
+        assert_eq!(sent, [json!("reloaded")]);
+    }
+}
@@ -0,0 +1,115 @@
+//! Reading `x-*` headers and raw bodies of binary IPC requests, which carry
+//! pixels as the body and everything else as headers.
+//!
+//! Bodies that need structured data next to the bytes use one framing both
+//! ways: a little-endian `u32` length, that much JSON, then the binary data.
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use tauri::ipc::{InvokeBody, Request};
+
+use crate::error::AppError;
+
+pub(crate) fn header_value<'a>(request: &'a Request<'_>, name: &str) -> Result<&'a str, AppError> {
+    request
+        .headers()
+        .get(name)
+        .and_then(|v| v.to_str().ok())
+        .ok_or_else(|| AppError::invalid(format!("Missing header: {}", name)))
+}
+
+pub(crate) fn parse_header<T: std::str::FromStr>(
+    request: &Request<'_>,
+    name: &str,
+) -> Result<T, AppError> {
+    header_value(request, name)?
+        .parse()
+        .map_err(|_| AppError::invalid(format!("Invalid header: {}", name)))
+}
+
+/// A percent-encoded text header, since header values are ASCII only.
+/// Missing and empty headers are `None`.
+pub(crate) fn text_header(request: &Request<'_>, name: &str) -> Option<String> {
+    header_value(request, name)
+        .ok()
+        .filter(|value| !value.is_empty())
+        .map(|value| {
+            percent_encoding::percent_decode_str(value)
+                .decode_utf8_lossy()
+                .into_owned()
+        })
+}
+
+/// Width, height and RGBA pixels
+pub(crate) type RgbaPixels<'a> = (u32, u32, &'a [u8]);
+
+/// The raw RGBA body, checked against the `x-width` and `x-height` headers
+pub(crate) fn rgba_body<'a>(request: &'a Request<'_>) -> Result<RgbaPixels<'a>, AppError> {
+    let ((width, height, pixels), rest) = rgba_body_with_trailer(request)?;
+    if !rest.is_empty() {
+        return Err(AppError::invalid(format!(
+            "RGBA buffer size {} does not match {}x{}",
+            pixels.len() + rest.len(),
+            width,
+            height
+        )));
+    }
+    Ok((width, height, pixels))
+}
+
+/// Like [`rgba_body`], but bytes after the pixels are returned instead of
+/// being rejected
+pub(crate) fn rgba_body_with_trailer<'a>(
+    request: &'a Request<'_>,
+) -> Result<(RgbaPixels<'a>, &'a [u8]), AppError> {
+    let InvokeBody::Raw(body) = request.body() else {
+        return Err(AppError::invalid("Expected raw RGBA body"));
+    };
+    let width: u32 = parse_header(request, "x-width")?;
+    let height: u32 = parse_header(request, "x-height")?;
+    let size = width as u64 * height as u64 * 4;
+    if (body.len() as u64) < size {
+        return Err(AppError::invalid(format!(
+            "RGBA buffer size {} does not match {}x{}",
+            body.len(),
+            width,
+            height
+        )));
+    }
+    let (pixels, rest) = body.split_at(size as usize);
+    Ok(((width, height, pixels), rest))
+}
+
+/// Split a framed raw body into its JSON part and binary data
+pub(crate) fn framed_body<'a, T: DeserializeOwned>(
+    request: &'a Request<'_>,
+) -> Result<(T, &'a [u8]), AppError> {
+    let InvokeBody::Raw(body) = request.body() else {
+        return Err(AppError::invalid("Expected raw body"));
+    };
+    unframe(body)
+}
+
+/// Split framed bytes into their JSON part and binary data
+pub(crate) fn unframe<T: DeserializeOwned>(body: &[u8]) -> Result<(T, &[u8]), AppError> {
+    let malformed = || AppError::invalid("Malformed request body");
+    let length = body
+        .get(..4)
+        .and_then(|bytes| bytes.try_into().ok())
+        .map(u32::from_le_bytes)
+        .ok_or_else(malformed)? as usize;
+    let json = body.get(4..4 + length).ok_or_else(malformed)?;
+    let value = serde_json::from_slice(json)
+        .map_err(|e| AppError::invalid(format!("Invalid request JSON: {}", e)))?;
+    Ok((value, &body[4 + length..]))
+}
+
+/// Frame `value` as JSON followed by `data`, for a binary response
+pub(crate) fn framed(value: &impl Serialize, data: &[u8]) -> Result<Vec<u8>, AppError> {
+    let json = serde_json::to_vec(value).map_err(|e| AppError::internal(e.to_string()))?;
+    let mut body = Vec::with_capacity(4 + json.len() + data.len());
+    body.extend_from_slice(&(json.len() as u32).to_le_bytes());
+    body.extend_from_slice(&json);
+    body.extend_from_slice(data);
+    Ok(body)
+}
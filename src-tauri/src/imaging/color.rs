@@ -0,0 +1,117 @@
+//! Colour management. The canvas works in sRGB, so decoded pixels are
+//! converted from their embedded ICC profile (Display P3 screenshots,
+//! Adobe RGB photos) instead of being shown with shifted colours.
+
+use std::sync::OnceLock;
+
+use moxcms::{ColorProfile, DataColorSpace, Layout, ProfileText, TransformOptions};
+
+use crate::error::AppError;
+
+/// Convert RGBA pixels from the ICC profile `icc` to sRGB, returning the
+/// profile's name.
+///
+/// Every RGB profile is converted, since a name mentioning sRGB says nothing
+/// about the primaries and curves inside. Non-RGB profiles are left alone:
+/// the decoder has already turned grey and CMYK images into plain RGB.
+pub fn convert_to_srgb(
+    rgba: &mut image::RgbaImage,
+    icc: &[u8],
+) -> Result<Option<String>, AppError> {
+    let profile =
+        ColorProfile::new_from_slice(icc).map_err(|e| AppError::decode(Some("ICC"), e))?;
+    let name = profile_name(&profile);
+    if profile.color_space != DataColorSpace::Rgb {
+        return Ok(name);
+    }
+
+    let transform = profile
+        .create_transform_8bit(
+            Layout::Rgba,
+            &ColorProfile::new_srgb(),
+            Layout::Rgba,
+            TransformOptions::default(),
+        )
+        .map_err(|e| AppError::decode(Some("ICC"), e))?;
+    let source = rgba.as_raw().clone();
+    transform
+        .transform(&source, rgba)
+        .map_err(|e| AppError::decode(Some("ICC"), e))?;
+    Ok(name)
+}
+
+/// The profile's description, e.g. `"Display P3"`
+fn profile_name(profile: &ColorProfile) -> Option<String> {
+    let name = match profile.description.as_ref()? {
+        ProfileText::PlainString(text) => text.clone(),
+        ProfileText::Localizable(strings) => strings
+            .iter()
+            .find(|s| s.language == "en")
+            .or_else(|| strings.first())?
+            .value
+            .clone(),
+        ProfileText::Description(description) if description.ascii_string.is_empty() => {
+            description.unicode_string.clone()
+        }
+        ProfileText::Description(description) => description.ascii_string.clone(),
+    };
+    let name = name.trim_matches(|c: char| c == '\0' || c.is_whitespace());
+    (!name.is_empty()).then(|| name.to_string())
+}
+
+/// An encoded sRGB ICC profile, for embedding in exports
+pub fn srgb_profile() -> Result<&'static [u8], AppError> {
+    static PROFILE: OnceLock<Vec<u8>> = OnceLock::new();
+    if let Some(profile) = PROFILE.get() {
+        return Ok(profile);
+    }
+    let encoded = ColorProfile::new_srgb()
+        .encode()
+        .map_err(|e| AppError::encode("ICC", e))?;
+    Ok(PROFILE.get_or_init(|| encoded))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use moxcms::LocalizableString;
+
+    fn convert(icc: &[u8], pixel: [u8; 4]) -> (Option<String>, [u8; 4]) {
+        let mut rgba = image::RgbaImage::from_pixel(1, 1, image::Rgba(pixel));
+        let name = convert_to_srgb(&mut rgba, icc).unwrap();
+        (name, rgba.get_pixel(0, 0).0)
+    }
+
+    fn assert_near(actual: [u8; 4], expected: [u8; 4]) {
+        let near = actual.iter().zip(expected).all(|(a, e)| a.abs_diff(e) <= 1);
+        assert!(near, "{:?} is not near {:?}", actual, expected);
+    }
+
+    #[test]
+    fn converts_display_p3_to_srgb() {
+        let p3 = ColorProfile::new_display_p3().encode().unwrap();
+        let (name, pixel) = convert(&p3, [200, 100, 50, 128]);
+        assert_eq!(name.as_deref(), Some("Display P3"));
+        // P3 is wider, so the same values are more saturated in sRGB; alpha
+        // is untouched
+        assert_near(pixel, [215, 93, 31, 128]);
+    }
+
+    #[test]
+    fn converts_profiles_by_content_not_name() {
+        let mut misnamed = ColorProfile::new_display_p3();
+        misnamed.description = Some(ProfileText::Localizable(vec![LocalizableString::new(
+            "en".into(),
+            "US".into(),
+            "sRGB (wide)".into(),
+        )]));
+        let (name, pixel) = convert(&misnamed.encode().unwrap(), [200, 100, 50, 255]);
+        assert_eq!(name.as_deref(), Some("sRGB (wide)"));
+        assert_near(pixel, [215, 93, 31, 255]);
+
+        // Converting sRGB to itself changes nothing
+        let (name, pixel) = convert(srgb_profile().unwrap(), [200, 100, 50, 255]);
+        assert_eq!(name.as_deref(), Some("sRGB IEC61966-2.1"));
+        assert_near(pixel, [200, 100, 50, 255]);
+    }
+}
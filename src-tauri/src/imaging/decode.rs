@@ -0,0 +1,346 @@
+use std::io::{BufRead, Cursor, Seek};
+use std::path::Path;
+
+use image::metadata::Orientation;
+use image::{DynamicImage, ImageDecoder, ImageFormat, ImageReader};
+use tauri::ipc::Response;
+
+use super::color;
+use crate::error::AppError;
+use crate::headers::framed;
+use crate::project;
+
+/// Formats `decode_image` accepts, by `image` format
+const SUPPORTED_FORMATS: &[ImageFormat] = &[
+    ImageFormat::Png,
+    ImageFormat::Jpeg,
+    ImageFormat::WebP,
+    ImageFormat::Gif,
+    ImageFormat::Bmp,
+    ImageFormat::Tiff,
+    ImageFormat::Ico,
+    ImageFormat::Qoi,
+];
+
+/// What the file held before conversion to 8-bit RGBA
+#[derive(Clone, serde::Serialize)]
+pub struct ImageInfo {
+    /// Lowercase format name, e.g. `"webp"`
+    pub format: &'static str,
+    /// Dimensions after applying `orientation`
+    pub width: u32,
+    pub height: u32,
+    /// Bits per channel in the file
+    pub bit_depth: u8,
+    pub has_alpha: bool,
+    /// EXIF orientation (1-8) that was applied; 1 means none
+    pub orientation: u8,
+    /// Name of the embedded ICC profile the pixels were converted from
+    pub color_profile: Option<String>,
+    /// 1 for still images; only the first frame is decoded
+    pub frame_count: u32,
+    /// The file is a PNG export carrying its editable project, which
+    /// `load_project` can open
+    pub has_project: bool,
+}
+
+/// What `probe` finds out without decoding pixels
+pub struct ImageSummary {
+    pub format: &'static str,
+    pub width: u32,
+    pub height: u32,
+}
+
+pub struct DecodedImage {
+    pub info: ImageInfo,
+    /// sRGB pixels
+    pub rgba: image::RgbaImage,
+}
+
+/// Whether `bytes` start like an image format `decode_image` accepts
+pub fn is_supported_image(bytes: &[u8]) -> bool {
+    image::guess_format(bytes).is_ok_and(|format| SUPPORTED_FORMATS.contains(&format))
+}
+
+/// Decode `bytes`, sniffing the format and falling back to the extension of `path`
+pub fn decode_bytes(bytes: &[u8], path: Option<&Path>) -> Result<DecodedImage, AppError> {
+    let format = image::guess_format(bytes)
+        .ok()
+        .or_else(|| path.and_then(|p| ImageFormat::from_path(p).ok()))
+        .filter(|format| SUPPORTED_FORMATS.contains(format))
+        .ok_or_else(|| AppError::decode(None, "Unsupported or unrecognised image format"))?;
+    let name = format_name(format);
+
+    let mut reader = ImageReader::new(Cursor::new(bytes));
+    reader.set_format(format);
+    let mut decoder = reader
+        .into_decoder()
+        .map_err(|e| AppError::decode(Some(name), e))?;
+    let orientation = decoder.orientation().unwrap_or(Orientation::NoTransforms);
+    let icc = decoder.icc_profile().ok().flatten();
+    // GIF and WebP decoders yield the first frame of an animation here
+    let mut image =
+        DynamicImage::from_decoder(decoder).map_err(|e| AppError::decode(Some(name), e))?;
+    image.apply_orientation(orientation);
+    let color = image.color();
+    let mut rgba = to_rgba(image);
+    // A broken profile should not make the image unopenable
+    let color_profile = icc.and_then(|icc| match color::convert_to_srgb(&mut rgba, &icc) {
+        Ok(name) => name,
+        Err(e) => {
+            eprintln!("Keeping unconverted colours: {}", e);
+            None
+        }
+    });
+    let info = ImageInfo {
+        format: name,
+        width: rgba.width(),
+        height: rgba.height(),
+        bit_depth: (color.bits_per_pixel() / color.channel_count() as u16) as u8,
+        has_alpha: color.has_alpha(),
+        orientation: orientation.to_exif(),
+        color_profile,
+        frame_count: frame_count(bytes, format),
+        has_project: format == ImageFormat::Png && project::has_embedded_project(bytes),
+    };
+    Ok(DecodedImage { info, rgba })
+}
+
+pub fn decode_file(path: &Path) -> Result<DecodedImage, AppError> {
+    let bytes = std::fs::read(path).map_err(|e| AppError::io(Some(path), e))?;
+    decode_bytes(&bytes, Some(path))
+}
+
+/// Format and dimensions (after EXIF orientation) of an image, from its
+/// header alone
+pub fn probe<R: BufRead + Seek>(reader: ImageReader<R>) -> Result<ImageSummary, AppError> {
+    let reader = reader.with_guessed_format()?;
+    let format = reader
+        .format()
+        .filter(|format| SUPPORTED_FORMATS.contains(format))
+        .ok_or_else(|| AppError::decode(None, "Unsupported or unrecognised image format"))?;
+    let name = format_name(format);
+    let mut decoder = reader
+        .into_decoder()
+        .map_err(|e| AppError::decode(Some(name), e))?;
+    let (width, height) = decoder.dimensions();
+    let rotated = matches!(
+        decoder.orientation().unwrap_or(Orientation::NoTransforms),
+        Orientation::Rotate90
+            | Orientation::Rotate270
+            | Orientation::Rotate90FlipH
+            | Orientation::Rotate270FlipH
+    );
+    let (width, height) = if rotated {
+        (height, width)
+    } else {
+        (width, height)
+    };
+    Ok(ImageSummary {
+        format: name,
+        width,
+        height,
+    })
+}
+
+fn to_rgba(image: DynamicImage) -> image::RgbaImage {
+    match image {
+        DynamicImage::ImageRgba8(rgba) => rgba,
+        other => other.to_rgba8(),
+    }
+}
+
+/// Frames in an animated GIF or WebP, counted from the container structure
+/// so no frame is decoded just to be counted
+fn frame_count(bytes: &[u8], format: ImageFormat) -> u32 {
+    let frames = match format {
+        ImageFormat::Gif => gif_frames(bytes),
+        ImageFormat::WebP => webp_frames(bytes),
+        _ => None,
+    };
+    frames.unwrap_or(1).max(1)
+}
+
+/// Image descriptors in a GIF, walking its blocks and skipping their data
+fn gif_frames(bytes: &[u8]) -> Option<u32> {
+    // Header and logical screen descriptor, then the global colour table
+    let flags = *bytes.get(10)?;
+    let mut pos = 13 + color_table_len(flags);
+    let mut frames = 0;
+    loop {
+        match *bytes.get(pos)? {
+            // Image descriptor, optional local colour table, LZW code size, data
+            0x2C => {
+                frames += 1;
+                let flags = *bytes.get(pos + 9)?;
+                pos = skip_sub_blocks(bytes, pos + 10 + color_table_len(flags) + 1)?;
+            }
+            // Extension: label, then data sub-blocks
+            0x21 => pos = skip_sub_blocks(bytes, pos + 2)?,
+            // Trailer
+            0x3B => return Some(frames),
+            _ => return None,
+        }
+    }
+}
+
+fn color_table_len(flags: u8) -> usize {
+    if flags & 0x80 == 0 {
+        0
+    } else {
+        3 << ((flags & 0x07) + 1)
+    }
+}
+
+/// Position after the sub-blocks starting at `pos` and their terminator
+fn skip_sub_blocks(bytes: &[u8], mut pos: usize) -> Option<usize> {
+    loop {
+        let len = *bytes.get(pos)? as usize;
+        pos += 1 + len;
+        if len == 0 {
+            return Some(pos);
+        }
+    }
+}
+
+/// `ANMF` chunks in an animated WebP; still images have none
+fn webp_frames(bytes: &[u8]) -> Option<u32> {
+    if bytes.get(..4)? != b"RIFF" || bytes.get(8..12)? != b"WEBP" {
+        return None;
+    }
+    let mut pos = 12;
+    let mut frames = 0;
+    while let Some(header) = bytes.get(pos..pos + 8) {
+        let size = u32::from_le_bytes(header[4..8].try_into().ok()?) as usize;
+        if &header[..4] == b"ANMF" {
+            frames += 1;
+        }
+        // Chunks are padded to an even size
+        pos = pos.checked_add(8 + size + (size & 1))?;
+    }
+    Some(frames)
+}
+
+fn format_name(format: ImageFormat) -> &'static str {
+    match format {
+        ImageFormat::Png => "png",
+        ImageFormat::Jpeg => "jpeg",
+        ImageFormat::WebP => "webp",
+        ImageFormat::Gif => "gif",
+        ImageFormat::Bmp => "bmp",
+        ImageFormat::Tiff => "tiff",
+        ImageFormat::Ico => "ico",
+        ImageFormat::Qoi => "qoi",
+        _ => "unknown",
+    }
+}
+
+/// Decode an image file to RGBA.
+///
+/// The response is binary to avoid base64 for large images: a little-endian
+/// `u32` length, that many bytes of [`ImageInfo`] JSON, then the RGBA pixels.
+#[tauri::command]
+pub async fn decode_image(path: String) -> Result<Response, AppError> {
+    let decoded = tokio::task::spawn_blocking(move || decode_file(Path::new(&path)))
+        .await
+        .map_err(|e| AppError::internal(format!("Task join error: {}", e)))??;
+
+    Ok(Response::new(framed(&decoded.info, decoded.rgba.as_raw())?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::codecs::gif::GifEncoder;
+    use image::{Delay, Frame, Rgba, RgbaImage};
+
+    fn gif(frames: usize) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        {
+            let mut encoder = GifEncoder::new(&mut bytes);
+            for i in 0..frames {
+                let image = RgbaImage::from_pixel(4, 4, Rgba([i as u8 * 60, 0, 0, 255]));
+                encoder
+                    .encode_frame(Frame::from_parts(
+                        image,
+                        0,
+                        0,
+                        Delay::from_numer_denom_ms(100, 1),
+                    ))
+                    .unwrap();
+            }
+        }
+        bytes
+    }
+
+    fn webp_chunk(fourcc: &[u8; 4], data: &[u8]) -> Vec<u8> {
+        let mut chunk = fourcc.to_vec();
+        chunk.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        chunk.extend_from_slice(data);
+        if data.len() % 2 == 1 {
+            chunk.push(0);
+        }
+        chunk
+    }
+
+    fn riff_webp(chunks: &[Vec<u8>]) -> Vec<u8> {
+        let body: Vec<u8> = chunks.concat();
+        let mut bytes = b"RIFF".to_vec();
+        bytes.extend_from_slice(&(4 + body.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(b"WEBP");
+        bytes.extend_from_slice(&body);
+        bytes
+    }
+
+    #[test]
+    fn counts_gif_frames_without_decoding() {
+        assert_eq!(frame_count(&gif(3), ImageFormat::Gif), 3);
+        assert_eq!(frame_count(&gif(1), ImageFormat::Gif), 1);
+    }
+
+    #[test]
+    fn counts_animated_webp_frames() {
+        let animated = riff_webp(&[
+            webp_chunk(b"VP8X", &[0x02, 0, 0, 0, 3, 0, 0, 3, 0, 0]),
+            webp_chunk(b"ANIM", &[0; 6]),
+            webp_chunk(b"ANMF", &[0; 17]),
+            webp_chunk(b"ANMF", &[0; 17]),
+        ]);
+        assert_eq!(frame_count(&animated, ImageFormat::WebP), 2);
+
+        let still = riff_webp(&[webp_chunk(b"VP8L", &[0; 5])]);
+        assert_eq!(frame_count(&still, ImageFormat::WebP), 1);
+    }
+
+    #[test]
+    fn truncated_files_count_as_one_frame() {
+        let gif = gif(3);
+        assert_eq!(frame_count(&gif[..gif.len() / 2], ImageFormat::Gif), 1);
+        assert_eq!(frame_count(b"RIFF", ImageFormat::WebP), 1);
+    }
+
+    #[test]
+    fn decodes_first_gif_frame_with_count() {
+        let decoded = decode_bytes(&gif(2), None).unwrap();
+        assert_eq!(decoded.info.format, "gif");
+        assert_eq!(decoded.info.frame_count, 2);
+        assert_eq!((decoded.info.width, decoded.info.height), (4, 4));
+    }
+
+    #[test]
+    fn converts_tagged_pngs_to_srgb() {
+        let p3 = moxcms::ColorProfile::new_display_p3().encode().unwrap();
+        let mut info = png::Info::with_size(1, 1);
+        info.icc_profile = Some(p3.into());
+        let mut bytes = Vec::new();
+        let mut encoder = png::Encoder::with_info(&mut bytes, info).unwrap();
+        encoder.set_color(png::ColorType::Rgba);
+        let mut writer = encoder.write_header().unwrap();
+        writer.write_image_data(&[200, 100, 50, 255]).unwrap();
+        writer.finish().unwrap();
+
+        let decoded = decode_bytes(&bytes, None).unwrap();
+        assert_eq!(decoded.info.color_profile.as_deref(), Some("Display P3"));
+        assert_ne!(decoded.rgba.get_pixel(0, 0).0, [200, 100, 50, 255]);
+    }
+}
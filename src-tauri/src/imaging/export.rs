@@ -0,0 +1,566 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use tauri::ipc::Request;
+
+use super::color;
+use super::metadata::{self, Metadata, MetadataMode};
+use crate::error::AppError;
+use crate::headers::{header_value, parse_header, rgba_body_with_trailer, text_header, unframe};
+use crate::project::{self, ProjectState};
+
+/// Encoder settings for an export
+#[derive(Clone, Copy)]
+pub enum ExportFormat {
+    Png {
+        /// Deflate level, 0 (stored) to 9 (smallest)
+        compression: u8,
+        /// Write an indexed PNG when the image has at most 256 colours
+        palette: bool,
+    },
+    Jpeg {
+        quality: u8,
+        subsampling: ChromaSubsampling,
+    },
+    WebP {
+        lossless: bool,
+        /// Only used for lossy WebP
+        quality: u8,
+    },
+}
+
+#[derive(Clone, Copy)]
+pub enum ChromaSubsampling {
+    /// Full colour resolution, best for text and thin lines
+    Yuv444,
+    Yuv422,
+    Yuv420,
+}
+
+const DEFAULT_PNG: ExportFormat = ExportFormat::Png {
+    compression: 6,
+    palette: false,
+};
+const DEFAULT_JPEG: ExportFormat = ExportFormat::Jpeg {
+    quality: 90,
+    subsampling: ChromaSubsampling::Yuv420,
+};
+const DEFAULT_WEBP: ExportFormat = ExportFormat::WebP {
+    lossless: true,
+    quality: 90,
+};
+
+impl ExportFormat {
+    /// Default settings for a format name or file extension
+    pub fn named(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "png" => Some(DEFAULT_PNG),
+            "jpg" | "jpeg" => Some(DEFAULT_JPEG),
+            "webp" => Some(DEFAULT_WEBP),
+            _ => None,
+        }
+    }
+
+    /// Default settings for the format matching `path`'s extension, PNG if unknown
+    pub fn for_path(path: &Path) -> Self {
+        path.extension()
+            .and_then(|ext| Self::named(&ext.to_string_lossy()))
+            .unwrap_or(DEFAULT_PNG)
+    }
+
+    /// Settings from `x-format` and the format's own headers; anything
+    /// missing falls back to [`ExportFormat::for_path`]
+    fn from_request(request: &Request<'_>, path: &Path) -> Result<Self, AppError> {
+        let format = match header_value(request, "x-format") {
+            Ok(name) => Self::named(name)
+                .ok_or_else(|| AppError::invalid(format!("Unknown export format: {}", name)))?,
+            Err(_) => Self::for_path(path),
+        };
+        Ok(match format {
+            ExportFormat::Png {
+                compression,
+                palette,
+            } => ExportFormat::Png {
+                compression: parse_header(request, "x-png-compression")
+                    .unwrap_or(compression)
+                    .min(9),
+                palette: parse_header(request, "x-png-palette").unwrap_or(palette),
+            },
+            ExportFormat::Jpeg {
+                quality,
+                subsampling,
+            } => ExportFormat::Jpeg {
+                quality: parse_header(request, "x-jpeg-quality")
+                    .unwrap_or(quality)
+                    .clamp(1, 100),
+                subsampling: match header_value(request, "x-jpeg-subsampling") {
+                    Ok("444") => ChromaSubsampling::Yuv444,
+                    Ok("422") => ChromaSubsampling::Yuv422,
+                    Ok("420") => ChromaSubsampling::Yuv420,
+                    _ => subsampling,
+                },
+            },
+            ExportFormat::WebP { lossless, quality } => ExportFormat::WebP {
+                lossless: parse_header(request, "x-webp-lossless").unwrap_or(lossless),
+                quality: parse_header(request, "x-webp-quality")
+                    .unwrap_or(quality)
+                    .min(100),
+            },
+        })
+    }
+}
+
+/// Encode RGBA pixels, embedding `metadata`. JPEG has no alpha channel, so
+/// transparent areas are flattened onto white; only PNG embeds a project.
+pub fn encode_rgba(
+    pixels: &[u8],
+    width: u32,
+    height: u32,
+    format: ExportFormat,
+    metadata: &Metadata,
+) -> Result<Vec<u8>, AppError> {
+    match format {
+        ExportFormat::Png {
+            compression,
+            palette,
+        } => encode_png(pixels, width, height, compression, palette, metadata),
+        ExportFormat::Jpeg {
+            quality,
+            subsampling,
+        } => encode_jpeg(pixels, width, height, quality, subsampling, metadata),
+        ExportFormat::WebP { lossless, quality } => {
+            let encoder = webp::Encoder::from_rgba(pixels, width, height);
+            let encoded = encoder
+                .encode_simple(lossless, quality as f32)
+                .map(|memory| memory.to_vec())
+                .map_err(|e| AppError::encode("WebP", format!("{:?}", e)))?;
+            let has_alpha = pixels.chunks_exact(4).any(|px| px[3] != 255);
+            metadata::add_to_webp(encoded, width, height, has_alpha, metadata)
+        }
+    }
+}
+
+fn encode_png(
+    pixels: &[u8],
+    width: u32,
+    height: u32,
+    compression: u8,
+    palette: bool,
+    metadata: &Metadata,
+) -> Result<Vec<u8>, AppError> {
+    let mut info = png::Info::with_size(width, height);
+    info.icc_profile = metadata.icc.as_deref().map(Cow::Borrowed);
+    info.exif_metadata = metadata.exif.as_deref().map(Cow::Borrowed);
+    if let Some(archive) = &metadata.project {
+        info.utf8_text.push(project::png_chunk(archive)?);
+    }
+
+    let mut out = Vec::new();
+    let mut encoder =
+        png::Encoder::with_info(&mut out, info).map_err(|e| AppError::encode("PNG", e))?;
+    encoder.set_deflate_compression(match compression {
+        0 => png::DeflateCompression::NoCompression,
+        level => png::DeflateCompression::Level(level),
+    });
+
+    let indexed = if palette {
+        reduce_to_palette(pixels)
+    } else {
+        None
+    };
+    let data = match indexed {
+        Some((colors, indices)) => {
+            let depth = match colors.len() {
+                0..=2 => png::BitDepth::One,
+                3..=4 => png::BitDepth::Two,
+                5..=16 => png::BitDepth::Four,
+                _ => png::BitDepth::Eight,
+            };
+            encoder.set_color(png::ColorType::Indexed);
+            encoder.set_depth(depth);
+            encoder.set_palette(
+                colors
+                    .iter()
+                    .flat_map(|c| [c[0], c[1], c[2]])
+                    .collect::<Vec<_>>(),
+            );
+            if colors.iter().any(|c| c[3] != 255) {
+                encoder.set_trns(colors.iter().map(|c| c[3]).collect::<Vec<_>>());
+            }
+            pack_indices(&indices, width as usize, depth as usize)
+        }
+        None => {
+            encoder.set_color(png::ColorType::Rgba);
+            encoder.set_depth(png::BitDepth::Eight);
+            pixels.to_vec()
+        }
+    };
+
+    let mut writer = encoder
+        .write_header()
+        .map_err(|e| AppError::encode("PNG", e))?;
+    writer
+        .write_image_data(&data)
+        .map_err(|e| AppError::encode("PNG", e))?;
+    writer.finish().map_err(|e| AppError::encode("PNG", e))?;
+    Ok(out)
+}
+
+/// The palette and per-pixel indices, if the image has at most 256 colours.
+/// Lossless: images with more colours are left as RGBA.
+fn reduce_to_palette(pixels: &[u8]) -> Option<(Vec<[u8; 4]>, Vec<u8>)> {
+    let mut lookup: HashMap<[u8; 4], u8> = HashMap::new();
+    let mut colors = Vec::new();
+    let mut indices = Vec::with_capacity(pixels.len() / 4);
+    for px in pixels.chunks_exact(4) {
+        let color = [px[0], px[1], px[2], px[3]];
+        let index = match lookup.get(&color) {
+            Some(index) => *index,
+            None if colors.len() == 256 => return None,
+            None => {
+                let index = colors.len() as u8;
+                lookup.insert(color, index);
+                colors.push(color);
+                index
+            }
+        };
+        indices.push(index);
+    }
+    Some((colors, indices))
+}
+
+/// Pack 8-bit palette indices into rows of `depth`-bit samples
+fn pack_indices(indices: &[u8], width: usize, depth: usize) -> Vec<u8> {
+    if depth == 8 || width == 0 {
+        return indices.to_vec();
+    }
+    let per_byte = 8 / depth;
+    let row_bytes = width.div_ceil(per_byte);
+    let mut out = vec![0u8; row_bytes * (indices.len() / width)];
+    for (y, row) in indices.chunks_exact(width).enumerate() {
+        for (x, index) in row.iter().enumerate() {
+            let shift = 8 - depth * (x % per_byte + 1);
+            out[y * row_bytes + x / per_byte] |= index << shift;
+        }
+    }
+    out
+}
+
+fn encode_jpeg(
+    pixels: &[u8],
+    width: u32,
+    height: u32,
+    quality: u8,
+    subsampling: ChromaSubsampling,
+    metadata: &Metadata,
+) -> Result<Vec<u8>, AppError> {
+    use jpeg_encoder::{ColorType, Encoder, SamplingFactor};
+
+    let (Ok(width), Ok(height)) = (u16::try_from(width), u16::try_from(height)) else {
+        return Err(AppError::encode(
+            "JPEG",
+            format!("{}x{} exceeds the JPEG size limit", width, height),
+        ));
+    };
+    let mut out = Vec::new();
+    let mut encoder = Encoder::new(&mut out, quality);
+    encoder.set_sampling_factor(match subsampling {
+        ChromaSubsampling::Yuv444 => SamplingFactor::R_4_4_4,
+        ChromaSubsampling::Yuv422 => SamplingFactor::R_4_2_2,
+        ChromaSubsampling::Yuv420 => SamplingFactor::R_4_2_0,
+    });
+    if let Some(icc) = &metadata.icc {
+        encoder
+            .add_icc_profile(icc)
+            .map_err(|e| AppError::encode("JPEG", e))?;
+    }
+    if let Some(exif) = &metadata.exif {
+        // APP1 holds EXIF behind an "Exif\0\0" marker
+        let mut segment = b"Exif\0\0".to_vec();
+        segment.extend_from_slice(exif);
+        encoder
+            .add_app_segment(1, &segment)
+            .map_err(|e| AppError::encode("JPEG", e))?;
+    }
+    encoder
+        .encode(&flatten_onto_white(pixels), width, height, ColorType::Rgb)
+        .map_err(|e| AppError::encode("JPEG", e))?;
+    Ok(out)
+}
+
+/// Composite RGBA pixels onto white, so transparent areas do not turn black
+/// where the alpha channel is dropped
+pub fn flatten_onto_white(pixels: &[u8]) -> Vec<u8> {
+    pixels
+        .chunks_exact(4)
+        .flat_map(|px| {
+            let alpha = px[3] as u32;
+            let blend = |c: u8| ((c as u32 * alpha + 255 * (255 - alpha) + 127) / 255) as u8;
+            [blend(px[0]), blend(px[1]), blend(px[2])]
+        })
+        .collect()
+}
+
+/// Distinguishes temp files of concurrent writes to the same path
+static TEMP_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// Write `bytes` to `path` without ever leaving a half-written file there.
+///
+/// The data goes to a temp file in the same directory (so the rename stays on
+/// one filesystem), is synced, and is then renamed over `path`. With `backup`,
+/// an existing `path` is first copied to `<path>.bak`, which is returned.
+pub fn write_atomic(path: &Path, bytes: &[u8], backup: bool) -> Result<Option<PathBuf>, AppError> {
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| AppError::invalid(format!("Not a file path: {}", path.display())))?;
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let temp = dir.join(format!(
+        ".{}.{}.{}.tmp",
+        file_name.to_string_lossy(),
+        std::process::id(),
+        TEMP_COUNTER.fetch_add(1, Ordering::Relaxed)
+    ));
+
+    let written = write_temp(&temp, path, bytes).and_then(|_| {
+        let backup_path = if backup && path.exists() {
+            let mut name = file_name.to_os_string();
+            name.push(".bak");
+            let backup_path = path.with_file_name(name);
+            std::fs::copy(path, &backup_path).map_err(|e| AppError::io(Some(&backup_path), e))?;
+            Some(backup_path)
+        } else {
+            None
+        };
+        std::fs::rename(&temp, path).map_err(|e| AppError::io(Some(path), e))?;
+        Ok(backup_path)
+    });
+    if written.is_err() {
+        let _ = std::fs::remove_file(&temp);
+    }
+    let backup_path = written?;
+
+    // Persist the rename itself; best-effort, not every platform can sync a directory
+    #[cfg(unix)]
+    if let Ok(dir) = File::open(dir) {
+        let _ = dir.sync_all();
+    }
+    Ok(backup_path)
+}
+
+fn write_temp(temp: &Path, target: &Path, bytes: &[u8]) -> Result<(), AppError> {
+    let io_error = |e| AppError::io(Some(temp), e);
+    let mut file = File::create_new(temp).map_err(io_error)?;
+    file.write_all(bytes).map_err(io_error)?;
+    file.sync_all().map_err(io_error)?;
+    // Keep the permissions of the file being replaced
+    if let Ok(metadata) = std::fs::metadata(target) {
+        std::fs::set_permissions(temp, metadata.permissions()).map_err(io_error)?;
+    }
+    Ok(())
+}
+
+/// Whether `a` and `b` name the same existing file
+fn same_file(a: &Path, b: &Path) -> bool {
+    match (a.canonicalize(), b.canonicalize()) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
+#[derive(Clone, serde::Serialize)]
+pub struct ExportResult {
+    pub path: String,
+    /// Size of the written file
+    pub bytes: usize,
+    /// Copy of the overwritten source file, if one was made
+    pub backup: Option<String>,
+}
+
+/// Encode raw RGBA pixels and write them to `x-path`.
+///
+/// Takes the same binary body and `x-width`/`x-height` headers as
+/// `queue_clipboard_copy_rgba`. `x-format` picks the encoder (default: from
+/// the extension) and `x-png-*`, `x-jpeg-*` and `x-webp-*` headers tune it.
+/// With `x-metadata: keep`, EXIF (minus orientation) of `x-source-path` is
+/// copied over; by default all metadata is stripped. `x-embed-srgb: true`
+/// tags the file with an sRGB ICC profile. PNG exports stay editable when
+/// the pixels are followed by a project, framed like `save_project`'s body.
+/// When `x-path` is the document's `x-source-path`, the original is kept as
+/// a `.bak` file next to it.
+#[tauri::command]
+pub async fn export_image(request: Request<'_>) -> Result<ExportResult, AppError> {
+    let ((width, height, pixels), trailer) = rgba_body_with_trailer(&request)?;
+    let project = if trailer.is_empty() {
+        None
+    } else {
+        let (state, image): (ProjectState, &[u8]) = unframe(trailer)?;
+        Some((state, image.to_vec()))
+    };
+    let path = PathBuf::from(
+        text_header(&request, "x-path")
+            .ok_or_else(|| AppError::invalid("Missing header: x-path"))?,
+    );
+    let source = text_header(&request, "x-source-path").map(PathBuf::from);
+    let format = ExportFormat::from_request(&request, &path)?;
+    let metadata_mode = match header_value(&request, "x-metadata") {
+        Ok("keep") => MetadataMode::Keep,
+        _ => MetadataMode::Strip,
+    };
+    let embed_srgb = parse_header(&request, "x-embed-srgb").unwrap_or(false);
+    let pixels = pixels.to_vec();
+
+    tokio::task::spawn_blocking(move || {
+        // Read before writing, since the export may replace the source
+        let mut metadata = match (&source, metadata_mode) {
+            // A moved or unreadable source loses its metadata, not the export
+            (Some(source), MetadataMode::Keep) => match std::fs::read(source) {
+                Ok(bytes) => metadata::read_metadata(&bytes),
+                Err(e) => {
+                    eprintln!("Exporting without metadata of {}: {}", source.display(), e);
+                    Metadata::default()
+                }
+            },
+            _ => Metadata::default(),
+        };
+        if embed_srgb {
+            metadata.icc = Some(color::srgb_profile()?.to_vec());
+        }
+        if let (Some((state, image)), ExportFormat::Png { .. }) = (&project, format) {
+            metadata.project = Some(project::write_project(image, state)?);
+        }
+        let bytes = encode_rgba(&pixels, width, height, format, &metadata)?;
+        let overwrites_source = source.is_some_and(|source| same_file(&path, &source));
+        let backup = write_atomic(&path, &bytes, overwrites_source)?;
+        Ok(ExportResult {
+            path: path.display().to_string(),
+            bytes: bytes.len(),
+            backup: backup.map(|p| p.display().to_string()),
+        })
+    })
+    .await
+    .map_err(|e| AppError::internal(format!("Task join error: {}", e)))?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reduces_few_colours_to_palette() {
+        let red = [255, 0, 0, 255];
+        let clear = [0, 0, 0, 0];
+        let pixels = [red, clear, red, red].concat();
+        let (colors, indices) = reduce_to_palette(&pixels).unwrap();
+        assert_eq!(colors, [red, clear]);
+        assert_eq!(indices, [0, 1, 0, 0]);
+    }
+
+    #[test]
+    fn keeps_rgba_beyond_256_colours() {
+        let pixels: Vec<u8> = (0..257u32)
+            .flat_map(|i| [i as u8, (i >> 8) as u8, 0, 255])
+            .collect();
+        assert!(reduce_to_palette(&pixels).is_none());
+        assert!(reduce_to_palette(&pixels[..256 * 4]).is_some());
+    }
+
+    #[test]
+    fn packs_indices_into_rows() {
+        // 1-bit, with each row padded to a whole byte
+        let indices = [1, 0, 1, 1, 0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 1, 1, 1];
+        assert_eq!(
+            pack_indices(&indices, 9, 1),
+            [0b1011_0000, 0b1000_0000, 0b0000_0011, 0b1000_0000]
+        );
+        // 2-bit
+        assert_eq!(
+            pack_indices(&[3, 2, 1, 0, 1], 5, 2),
+            [0b1110_0100, 0b0100_0000]
+        );
+        // 4-bit, two rows
+        assert_eq!(
+            pack_indices(&[15, 1, 2, 3, 4, 5], 3, 4),
+            [0xF1, 0x20, 0x34, 0x50]
+        );
+        // 8-bit is unchanged
+        assert_eq!(pack_indices(&[7, 200], 2, 8), [7, 200]);
+    }
+
+    #[test]
+    fn indexed_png_round_trips() {
+        let pixels = [
+            [10, 20, 30, 255],
+            [0, 0, 0, 0],
+            [10, 20, 30, 255],
+            [250, 0, 0, 128],
+        ]
+        .concat();
+        let png = encode_png(&pixels, 2, 2, 6, true, &Metadata::default()).unwrap();
+        let decoded = image::load_from_memory(&png).unwrap().to_rgba8();
+        assert_eq!(decoded.as_raw(), &pixels);
+    }
+
+    #[test]
+    fn flattens_transparency_onto_white() {
+        let pixels = [
+            [0, 0, 0, 0],
+            [0, 0, 0, 255],
+            [255, 0, 0, 128],
+            [10, 20, 30, 255],
+        ]
+        .concat();
+        assert_eq!(
+            flatten_onto_white(&pixels),
+            [255, 255, 255, 0, 0, 0, 255, 127, 127, 10, 20, 30]
+        );
+    }
+
+    #[test]
+    fn concurrent_writes_use_their_own_temp_files() {
+        let dir = std::env::temp_dir().join(format!("ursamarkup-export-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("out.png");
+
+        let writers: Vec<_> = (0..8u8)
+            .map(|i| {
+                let path = path.clone();
+                std::thread::spawn(move || write_atomic(&path, &[i; 64 * 1024], false))
+            })
+            .collect();
+        for writer in writers {
+            writer.join().unwrap().unwrap();
+        }
+
+        // One of the writes won, whole; no temp files are left behind
+        let written = std::fs::read(&path).unwrap();
+        assert_eq!(written.len(), 64 * 1024);
+        assert!(written.iter().all(|b| *b == written[0]));
+        let entries = std::fs::read_dir(&dir).unwrap().count();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(entries, 1);
+    }
+
+    #[test]
+    fn backs_up_the_overwritten_file() {
+        let dir = std::env::temp_dir().join(format!("ursamarkup-backup-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("shot.png");
+        std::fs::write(&path, b"original").unwrap();
+
+        let backup = write_atomic(&path, b"edited", true).unwrap().unwrap();
+        let contents = (
+            std::fs::read(&path).unwrap(),
+            std::fs::read(&backup).unwrap(),
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(backup, dir.join("shot.png.bak"));
+        assert_eq!(contents, (b"edited".to_vec(), b"original".to_vec()));
+    }
+}
@@ -0,0 +1,263 @@
+//! EXIF and ICC metadata carried from a source file into its export.
+//!
+//! Pixels are always exported upright, so a kept EXIF block has its
+//! orientation reset to "no transform"; otherwise viewers would rotate the
+//! image a second time. Stripping drops everything, including GPS
+//! coordinates and camera serial numbers.
+//!
+//! The source's ICC profile itself is not carried over: decoding converts
+//! the pixels to sRGB, so it no longer describes them. A kept colour-managed
+//! source is tagged sRGB instead.
+
+use std::io::Cursor;
+
+use image::metadata::Orientation;
+use image::{ImageDecoder, ImageReader};
+
+use super::color;
+use crate::error::AppError;
+
+/// What to do with the source file's metadata on export
+#[derive(Clone, Copy, Default, PartialEq, Eq)]
+pub enum MetadataMode {
+    /// Keep EXIF, minus orientation, and tag sources that had ICC as sRGB
+    Keep,
+    #[default]
+    Strip,
+}
+
+/// Metadata blocks as stored in the file, ready to be written back
+#[derive(Default)]
+pub struct Metadata {
+    /// A TIFF structure, without the `Exif\0\0` prefix JPEG puts before it
+    pub exif: Option<Vec<u8>>,
+    pub icc: Option<Vec<u8>>,
+    /// A `.ursa` archive to embed so the export stays editable; PNG only
+    pub project: Option<Vec<u8>>,
+}
+
+impl Metadata {
+    /// Whether there is EXIF or ICC data, which every export format can carry
+    pub fn is_empty(&self) -> bool {
+        self.exif.is_none() && self.icc.is_none()
+    }
+}
+
+/// Read the metadata of an encoded image that is worth keeping.
+/// Unreadable metadata is treated as absent.
+pub fn read_metadata(bytes: &[u8]) -> Metadata {
+    let Ok(reader) = ImageReader::new(Cursor::new(bytes)).with_guessed_format() else {
+        return Metadata::default();
+    };
+    let Ok(mut decoder) = reader.into_decoder() else {
+        return Metadata::default();
+    };
+    let mut exif = decoder.exif_metadata().ok().flatten();
+    if let Some(exif) = exif.as_mut() {
+        let _ = Orientation::remove_from_exif_chunk(exif);
+    }
+    let icc = match decoder.icc_profile() {
+        Ok(Some(_)) => color::srgb_profile().ok().map(<[u8]>::to_vec),
+        _ => None,
+    };
+    Metadata {
+        exif,
+        icc,
+        ..Metadata::default()
+    }
+}
+
+const WEBP_ICC_FLAG: u8 = 0x20;
+const WEBP_ALPHA_FLAG: u8 = 0x10;
+const WEBP_EXIF_FLAG: u8 = 0x08;
+
+/// Rewrite a WebP file in the extended (`VP8X`) layout with `metadata` added.
+///
+/// libwebp's simple encoder cannot write metadata, so the chunks are spliced
+/// in afterwards: `ICCP` goes before the image data and `EXIF` after it.
+pub fn add_to_webp(
+    webp: Vec<u8>,
+    width: u32,
+    height: u32,
+    has_alpha: bool,
+    metadata: &Metadata,
+) -> Result<Vec<u8>, AppError> {
+    if metadata.is_empty() {
+        return Ok(webp);
+    }
+
+    let mut flags = if has_alpha { WEBP_ALPHA_FLAG } else { 0 };
+    let mut image_chunks = Vec::new();
+    for (fourcc, data) in webp_chunks(&webp)? {
+        match &fourcc {
+            b"VP8X" => flags |= data.first().copied().unwrap_or(0),
+            b"ICCP" | b"EXIF" => {}
+            _ => image_chunks.push((fourcc, data)),
+        }
+    }
+    if metadata.icc.is_some() {
+        flags |= WEBP_ICC_FLAG;
+    }
+    if metadata.exif.is_some() {
+        flags |= WEBP_EXIF_FLAG;
+    }
+
+    let mut vp8x = [0u8; 10];
+    vp8x[0] = flags;
+    vp8x[4..7].copy_from_slice(&(width.saturating_sub(1)).to_le_bytes()[..3]);
+    vp8x[7..10].copy_from_slice(&(height.saturating_sub(1)).to_le_bytes()[..3]);
+
+    let mut body = b"WEBP".to_vec();
+    push_chunk(&mut body, b"VP8X", &vp8x);
+    if let Some(icc) = &metadata.icc {
+        push_chunk(&mut body, b"ICCP", icc);
+    }
+    for (fourcc, data) in image_chunks {
+        push_chunk(&mut body, &fourcc, data);
+    }
+    if let Some(exif) = &metadata.exif {
+        push_chunk(&mut body, b"EXIF", exif);
+    }
+
+    let mut out = b"RIFF".to_vec();
+    out.extend_from_slice(&(body.len() as u32).to_le_bytes());
+    out.extend_from_slice(&body);
+    Ok(out)
+}
+
+/// A RIFF chunk's FourCC and data
+type Chunk<'a> = ([u8; 4], &'a [u8]);
+
+/// The chunks of a RIFF WebP file, in order
+fn webp_chunks(webp: &[u8]) -> Result<Vec<Chunk<'_>>, AppError> {
+    let malformed = || AppError::encode("WebP", "malformed RIFF container");
+    if webp.len() < 12 || &webp[0..4] != b"RIFF" || &webp[8..12] != b"WEBP" {
+        return Err(malformed());
+    }
+    let mut chunks = Vec::new();
+    let mut offset = 12;
+    while offset + 8 <= webp.len() {
+        let fourcc: [u8; 4] = webp[offset..offset + 4]
+            .try_into()
+            .map_err(|_| malformed())?;
+        let size = u32::from_le_bytes(
+            webp[offset + 4..offset + 8]
+                .try_into()
+                .map_err(|_| malformed())?,
+        ) as usize;
+        let data = webp
+            .get(offset + 8..offset + 8 + size)
+            .ok_or_else(malformed)?;
+        chunks.push((fourcc, data));
+        // Chunks are padded to an even size
+        offset += 8 + size + (size & 1);
+    }
+    Ok(chunks)
+}
+
+fn push_chunk(out: &mut Vec<u8>, fourcc: &[u8; 4], data: &[u8]) {
+    out.extend_from_slice(fourcc);
+    out.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    out.extend_from_slice(data);
+    if data.len() % 2 == 1 {
+        out.push(0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::codecs::webp::{WebPDecoder, WebPEncoder};
+    use image::ExtendedColorType;
+
+    /// A little-endian TIFF block with a camera make and orientation 6
+    /// (rotate 90°)
+    fn exif_with_orientation() -> Vec<u8> {
+        let mut exif = b"II*\0".to_vec();
+        exif.extend_from_slice(&8u32.to_le_bytes());
+        exif.extend_from_slice(&2u16.to_le_bytes());
+        // Make: ASCII, 4 bytes, stored inline
+        exif.extend_from_slice(&[0x0F, 0x01, 2, 0, 4, 0, 0, 0]);
+        exif.extend_from_slice(b"Cam\0");
+        // Orientation: SHORT, 1 value
+        exif.extend_from_slice(&[0x12, 0x01, 3, 0, 1, 0, 0, 0, 6, 0, 0, 0]);
+        exif.extend_from_slice(&0u32.to_le_bytes());
+        exif
+    }
+
+    fn lossless_webp(pixels: &[u8], width: u32, height: u32) -> Vec<u8> {
+        let mut out = Vec::new();
+        WebPEncoder::new_lossless(&mut out)
+            .encode(pixels, width, height, ExtendedColorType::Rgba8)
+            .unwrap();
+        out
+    }
+
+    #[test]
+    fn keeps_exif_without_orientation() {
+        let mut png = Vec::new();
+        let mut info = png::Info::with_size(1, 1);
+        let exif = exif_with_orientation();
+        info.exif_metadata = Some(exif.as_slice().into());
+        info.icc_profile = Some(b"not sRGB".as_slice().into());
+        let mut encoder = png::Encoder::with_info(&mut png, info).unwrap();
+        encoder.set_color(png::ColorType::Rgba);
+        let mut writer = encoder.write_header().unwrap();
+        writer.write_image_data(&[1, 2, 3, 255]).unwrap();
+        writer.finish().unwrap();
+
+        let metadata = read_metadata(&png);
+        let kept = metadata.exif.unwrap();
+        assert_eq!(
+            Orientation::from_exif_chunk(&kept),
+            Some(Orientation::NoTransforms)
+        );
+        assert_eq!(kept.len(), exif.len());
+        assert!(kept.windows(4).any(|w| w == b"Cam\0"));
+        // The pixels are decoded to sRGB, so that is what gets tagged
+        assert_eq!(
+            metadata.icc.as_deref(),
+            Some(color::srgb_profile().unwrap())
+        );
+    }
+
+    #[test]
+    fn leaves_webp_without_metadata_alone() {
+        let webp = lossless_webp(&[0, 0, 0, 255], 1, 1);
+        let metadata = read_metadata(&webp);
+        assert!(metadata.is_empty());
+        assert_eq!(
+            add_to_webp(webp.clone(), 1, 1, false, &metadata).unwrap(),
+            webp
+        );
+    }
+
+    #[test]
+    fn adds_metadata_chunks_to_webp() {
+        let pixels = [[255, 0, 0, 255], [0, 0, 255, 128]].concat();
+        let metadata = Metadata {
+            exif: Some(exif_with_orientation()),
+            icc: Some(color::srgb_profile().unwrap().to_vec()),
+            project: None,
+        };
+        let webp = add_to_webp(lossless_webp(&pixels, 2, 1), 2, 1, true, &metadata).unwrap();
+
+        let chunks = webp_chunks(&webp).unwrap();
+        let order: Vec<_> = chunks.iter().map(|(fourcc, _)| fourcc).collect();
+        assert_eq!(order, [b"VP8X", b"ICCP", b"VP8L", b"EXIF"]);
+        let vp8x = chunks[0].1;
+        assert_eq!(vp8x[0], WEBP_ICC_FLAG | WEBP_ALPHA_FLAG | WEBP_EXIF_FLAG);
+        // Canvas size minus one, as 24-bit values
+        assert_eq!(&vp8x[4..10], [1, 0, 0, 0, 0, 0]);
+        assert_eq!(
+            u32::from_le_bytes(webp[4..8].try_into().unwrap()) as usize,
+            webp.len() - 8
+        );
+
+        let mut decoder = WebPDecoder::new(Cursor::new(&webp)).unwrap();
+        assert_eq!(decoder.icc_profile().unwrap(), metadata.icc);
+        assert_eq!(decoder.exif_metadata().unwrap(), metadata.exif);
+        let decoded = image::DynamicImage::from_decoder(decoder).unwrap();
+        assert_eq!(decoded.to_rgba8().as_raw(), &pixels);
+    }
+}
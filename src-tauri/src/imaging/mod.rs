@@ -0,0 +1,17 @@
+//! Image file handling in Rust, for formats and files the webview cannot
+//! decode on its own.
+
+mod color;
+mod decode;
+mod export;
+mod metadata;
+
+pub use decode::{
+    decode_bytes, decode_file, decode_image, is_supported_image, probe, DecodedImage, ImageInfo,
+    ImageSummary,
+};
+pub use export::{
+    encode_rgba, export_image, flatten_onto_white, write_atomic, ChromaSubsampling, ExportFormat,
+    ExportResult,
+};
+pub use metadata::{read_metadata, Metadata, MetadataMode};
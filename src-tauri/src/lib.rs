@@ -1,159 +1,141 @@
-use base64::Engine;
-use std::path::Path;
-use std::process::Command;
+pub mod cli;
+pub mod clipboard;
+pub mod error;
+mod events;
+mod headers;
+pub mod imaging;
+pub mod project;
+pub mod render;
+
+use std::ffi::OsString;
+use std::path::PathBuf;
 use std::sync::Mutex;
 use tauri::{
-    menu::{Menu, MenuItem, PredefinedMenuItem},
+    image::Image,
+    menu::{IconMenuItem, Menu, MenuItem, PredefinedMenuItem, Submenu},
     tray::{MouseButton, TrayIconBuilder, TrayIconEvent},
-    AppHandle, Emitter, Manager, State, Wry,
+    webview::PageLoadEvent,
+    AppHandle, Emitter, Manager, Wry,
 };
+use tauri_plugin_dialog::DialogExt;
+
+use error::{AppError, LogError};
 
 struct TrayMenuState {
     toggle_item: Mutex<Option<MenuItem<Wry>>>,
+    recent_menu: Mutex<Option<Submenu<Wry>>>,
 }
 
 fn set_tray_text(app: &AppHandle, text: &str) {
     let state = app.state::<TrayMenuState>();
     let guard = state.toggle_item.lock().unwrap();
     if let Some(item) = guard.as_ref() {
-        let _ = item.set_text(text);
+        item.set_text(text).log_error("Failed to update tray menu");
+    }
+}
+
+const RECENT_ITEM_PREFIX: &str = "recent:";
+
+// Rebuild the "Recent copies" submenu from the clipboard history
+fn set_recent_copies(app: &AppHandle, entries: &[clipboard::HistoryEntry]) -> tauri::Result<()> {
+    let state = app.state::<TrayMenuState>();
+    let guard = state.recent_menu.lock().unwrap();
+    let Some(menu) = guard.as_ref() else {
+        return Ok(());
+    };
+
+    for item in menu.items()? {
+        menu.remove(&item)?;
+    }
+    if entries.is_empty() {
+        menu.append(&MenuItem::with_id(
+            app,
+            "recent_empty",
+            "No recent copies",
+            false,
+            None::<&str>,
+        )?)?;
+        return Ok(());
     }
+    for entry in entries {
+        let icon = entry
+            .thumbnail_rgba()
+            .map(|thumb| Image::new_owned(thumb.pixels, thumb.width, thumb.height));
+        menu.append(&IconMenuItem::with_id(
+            app,
+            format!("{}{}", RECENT_ITEM_PREFIX, entry.id),
+            &entry.label,
+            true,
+            icon,
+            None::<&str>,
+        )?)?;
+    }
+    menu.append(&PredefinedMenuItem::separator(app)?)?;
+    menu.append(&MenuItem::with_id(
+        app,
+        "recent_clear",
+        "Clear History",
+        true,
+        None::<&str>,
+    )?)?;
+    Ok(())
 }
 
 #[tauri::command]
-fn minimize_to_tray(app: AppHandle) {
+fn minimize_to_tray(app: AppHandle) -> Result<(), AppError> {
     if let Some(window) = app.get_webview_window("main") {
-        let _ = window.hide();
+        window.hide()?;
         set_tray_text(&app, "Open Ursa Markup");
     }
+    Ok(())
 }
 
 #[tauri::command]
-fn restore_from_tray(app: AppHandle) {
+fn restore_from_tray(app: AppHandle) -> Result<(), AppError> {
     if let Some(window) = app.get_webview_window("main") {
-        let _ = window.show();
-        let _ = window.set_focus();
+        window.show()?;
+        window.set_focus()?;
         set_tray_text(&app, "Hide Ursa Markup");
     }
+    Ok(())
 }
 
 // Logic to toggle window visibility (used by the MENU item only)
 fn toggle_window(app: &AppHandle) {
     if let Some(window) = app.get_webview_window("main") {
         if window.is_visible().unwrap_or(true) {
-            let _ = window.hide();
+            window.hide().log_error("Failed to hide window");
             set_tray_text(app, "Open Ursa Markup");
         } else {
-            let _ = window.show();
-            let _ = window.set_focus();
+            window.show().log_error("Failed to show window");
+            window.set_focus().log_error("Failed to focus window");
                             set_tray_text(app, "Hide Ursa Markup");
         }
     }
 }
 
-fn resolve_file_path(path: &str) -> String {
-    let p = Path::new(path);
-    if p.is_absolute() {
-        p.canonicalize()
-            .ok()
-            .and_then(|pb| pb.to_str().map(String::from))
-            .unwrap_or_else(|| path.to_string())
-    } else {
-        std::env::current_dir()
-            .ok()
-            .and_then(|cwd| cwd.join(p).canonicalize().ok())
-            .and_then(|pb| pb.to_str().map(String::from))
-            .unwrap_or_else(|| path.to_string())
-    }
-}
-
-#[derive(Clone, serde::Serialize)]
-struct OpenFilesPayload {
-    file_paths: Vec<String>,
-}
-
-#[derive(Clone, serde::Serialize)]
-struct ClipboardCopyResult {
-    success: bool,
-    error: Option<String>,
-    version: u32,
-}
-
-struct PendingFiles {
-    paths: Mutex<Vec<String>>,
-}
-
-#[tauri::command]
-async fn queue_clipboard_copy_base64(
-    app: AppHandle,
-    image_base64: String,
-    version: u32,
-) -> Result<(), String> {
-    tokio::spawn(async move {
-        let result = tokio::task::spawn_blocking(move || copy_png_to_clipboard(&image_base64))
-            .await
-            .map_err(|e| format!("Task join error: {}", e))
-            .and_then(|r| r);
-        let _ = app.emit(
-            "clipboard-copy-result",
-            ClipboardCopyResult {
-                success: result.is_ok(),
-                error: result.err(),
-                version,
-            },
-        );
+/// Let the user pick a folder and open the images in it, like a directory
+/// given on the command line
+fn open_folder(app: &AppHandle) {
+    let handle = app.clone();
+    app.dialog().file().pick_folder(move |folder| {
+        let Some(dir) = folder.and_then(|folder| folder.into_path().ok()) else {
+            return;
+        };
+        // Walked on the launch worker, like directories from the command line
+        handle.state::<cli::LaunchWorker>().submit(move || Some(cli::folder_payload(&dir)));
     });
-    Ok(())
 }
 
-fn copy_png_to_clipboard(image_base64: &str) -> Result<(), String> {
-    use arboard::{Clipboard, ImageData};
-    use image::GenericImageView;
-    use std::borrow::Cow;
-
-    let png_data = base64::engine::general_purpose::STANDARD
-        .decode(image_base64)
-        .map_err(|e| format!("Failed to decode base64: {}", e))?;
-    let img =
-        image::load_from_memory(&png_data).map_err(|e| format!("Failed to decode PNG: {}", e))?;
-    let (width, height) = img.dimensions();
-    let rgba_data = img.to_rgba8().into_raw();
-
-    match Clipboard::new() {
-        Ok(mut clipboard) => {
-            let img_data = ImageData {
-                width: width as usize,
-                height: height as usize,
-                bytes: Cow::Owned(rgba_data),
-            };
-            if clipboard.set_image(img_data).is_ok() {
-                return Ok(());
-            }
-        }
-        Err(_) => {}
-    }
-
-    let mut child = Command::new("wl-copy")
-        .arg("--type")
-        .arg("image/png")
-        .stdin(std::process::Stdio::piped())
-        .spawn()
-        .map_err(|e| format!("{}", e))?;
-    if let Some(stdin) = child.stdin.as_mut() {
-        use std::io::Write;
-        stdin.write_all(&png_data).map_err(|e| format!("{}", e))?;
-    }
-    child.wait().map_err(|e| format!("{}", e))?;
-    Ok(())
-}
-
-#[tauri::command]
-fn get_pending_files(state: State<PendingFiles>) -> Vec<String> {
-    state.paths.lock().unwrap().drain(..).collect()
+/// Work that has to happen however the app exits
+fn before_exit(app: &AppHandle) {
+    clipboard::hand_off_before_exit(app);
+    app.state::<cli::Sessions>().cancel_all();
 }
 
 #[tauri::command]
-fn exit_app() {
+fn exit_app(app: AppHandle) {
+    before_exit(&app);
     std::process::exit(0);
 }
 
@@ -164,35 +146,63 @@ pub fn run() {
         .plugin(tauri_plugin_dialog::init())
         .plugin(tauri_plugin_fs::init())
         .plugin(tauri_plugin_store::Builder::new().build())
-        .plugin(tauri_plugin_cli::init())
         .plugin(tauri_plugin_shell::init())
         .plugin(tauri_plugin_clipboard_manager::init())
-        .plugin(tauri_plugin_single_instance::init(|app, argv, _cwd| {
-            let file_paths: Vec<String> = argv
-                .iter()
-                .skip(1)
-                .filter(|arg| !arg.starts_with('-'))
-                .map(|arg| resolve_file_path(arg))
-                .collect();
-            if !file_paths.is_empty() {
-                let _ = app.emit("open-files", OpenFilesPayload { file_paths });
-            }
+        .plugin(tauri_plugin_single_instance::init(|app, argv, cwd| {
+            // Paths are relative to where the second instance was started
+            let args = argv.into_iter().map(OsString::from).collect();
+            app.state::<cli::LaunchWorker>().launch(args, Some(PathBuf::from(cwd)));
         }))
+        .manage(events::FrontendEvents::default())
+        .manage(cli::Sessions::default())
+        .on_page_load(|webview, payload| {
+            // A reload drops the frontend's listeners until it is ready again
+            if webview.label() == "main" && payload.event() == PageLoadEvent::Started {
+                webview.state::<events::FrontendEvents>().reset();
+            }
+        })
         .invoke_handler(tauri::generate_handler![
             minimize_to_tray,
             restore_from_tray,
-            queue_clipboard_copy_base64,
-            get_pending_files,
+            clipboard::queue_clipboard_copy_rgba,
+            clipboard::configure_clipboard,
+            clipboard::read_clipboard_image,
+            clipboard::get_clipboard_history,
+            clipboard::recopy_clipboard_history,
+            clipboard::clear_clipboard_history,
+            imaging::decode_image,
+            imaging::export_image,
+            project::save_project,
+            project::load_project,
+            cli::read_pipe_input,
+            cli::finish_pipe_session,
+            cli::close_pipe_session,
+            cli::finish_wait_session,
+            events::frontend_ready,
             exit_app
         ])
         .setup(|app| {
             // Initial state: App is open, so menu says "Hide"
             let toggle_i = MenuItem::with_id(app, "toggle", "Hide Ursa Markup", true, None::<&str>)?;
             let open_file_i = MenuItem::with_id(app, "open_file", "Open File", true, None::<&str>)?;
+            let open_folder_i =
+                MenuItem::with_id(app, "open_folder", "Open Folder", true, None::<&str>)?;
+            // Filled from the clipboard history below
+            let recent_i = Submenu::with_id(app, "recent", "Recent Copies", true)?;
             let sep = PredefinedMenuItem::separator(app)?;
             let quit_i = MenuItem::with_id(app, "quit", "Quit", true, None::<&str>)?;
 
-            let menu = Menu::with_items(app, &[&toggle_i, &open_file_i, &sep, &quit_i])?;
+            let menu = Menu::with_items(
+                app,
+                &[
+                    &toggle_i,
+                    &open_file_i,
+                    &open_folder_i,
+                    &recent_i,
+                    &sep,
+                    &quit_i,
+                ],
+            )?;
 
             let _tray = TrayIconBuilder::with_id("ursamarkup-tray")
                 .icon(app.default_window_icon().unwrap().clone())
@@ -200,12 +210,22 @@ pub fn run() {
                 .title("Ursa Markup")
                 .tooltip("Ursa Markup")
                 .on_menu_event(|app, event| match event.id.as_ref() {
-                    "quit" => std::process::exit(0),
+                    "quit" => {
+                        before_exit(app);
+                        std::process::exit(0);
+                    }
                     "toggle" => toggle_window(app),
                     "open_file" => {
-                        let _ = app.emit("tray-open-file", ());
+                        events::emit_when_ready(app, "tray-open-file", ());
+                    }
+                    "open_folder" => open_folder(app),
+                    "recent_clear" => app.state::<clipboard::CopyHistory>().clear(),
+                    id => {
+                        if let Some(entry_id) = id.strip_prefix(RECENT_ITEM_PREFIX) {
+                            clipboard::recopy_from_history(app, entry_id)
+                                .log_error("Failed to re-copy from history");
+                        }
                     }
-                    _ => {}
                 })
                 .on_tray_icon_event(|tray, event| {
                     if let TrayIconEvent::Click {
@@ -215,8 +235,8 @@ pub fn run() {
                     {
                         let app = tray.app_handle();
                         if let Some(window) = app.get_webview_window("main") {
-                            let _ = window.show();
-                            let _ = window.set_focus();
+                            window.show().log_error("Failed to show window");
+                            window.set_focus().log_error("Failed to focus window");
             set_tray_text(app, "Hide Ursa Markup");
                         }
                     }
@@ -226,47 +246,84 @@ pub fn run() {
             // Store the MenuItem in state so we can update its text later
             app.manage(TrayMenuState {
                 toggle_item: Mutex::new(Some(toggle_i)),
+                recent_menu: Mutex::new(Some(recent_i)),
             });
 
-            let initial_paths: Vec<String> = if cfg!(not(mobile)) {
-                use tauri_plugin_cli::CliExt;
-                let mut paths = Vec::new();
-                if let Ok(matches) = app.cli().matches() {
-                    if let Some(args) = matches.args.get("file") {
-                        match &args.value {
-                            serde_json::Value::String(s) => paths.push(resolve_file_path(s)),
-                            serde_json::Value::Array(arr) => {
-                                for v in arr {
-                                    if let Some(s) = v.as_str() {
-                                        paths.push(resolve_file_path(s));
-                                    }
-                                }
-                            }
-                            _ => {}
-                        }
-                    }
+            // Files are checked off the main thread and reach the frontend
+            // through the event queue, however long that takes
+            let handle = app.handle().clone();
+            app.manage(cli::LaunchWorker::new(move |mut payload| {
+                payload.accept_sessions(&handle.state::<cli::Sessions>());
+                if !payload.is_empty() {
+                    events::emit_when_ready(&handle, "open-files", payload);
                 }
-                paths
-            } else {
-                Vec::new()
-            };
+            }));
+            if cfg!(not(mobile)) {
+                app.state::<cli::LaunchWorker>()
+                    .launch(std::env::args_os().collect(), std::env::current_dir().ok());
+            }
+            let clipboard_temp_dir = app
+                .path()
+                .app_cache_dir()
+                .ok()
+                .map(|dir| dir.join("clipboard"));
+            if let Some(dir) = &clipboard_temp_dir {
+                clipboard::clean_temp_dir(dir, None, Some(clipboard::TEMP_FILE_MAX_AGE));
+            }
+            app.manage(clipboard::ClipboardState {
+                config: Mutex::new(clipboard::ClipboardConfig::default()),
+                temp_dir: clipboard_temp_dir,
+            });
 
-            app.manage(PendingFiles {
-                paths: Mutex::new(initial_paths),
+            let history = clipboard::CopyHistory::open(
+                app.path()
+                    .app_cache_dir()
+                    .ok()
+                    .map(|dir| dir.join("clipboard-history")),
+            );
+            let handle = app.handle().clone();
+            history.set_on_change(move |entries| {
+                // Changes come from the copy thread, but menus belong to the main thread
+                let menu_handle = handle.clone();
+                let menu_entries = entries.to_vec();
+                handle
+                    .run_on_main_thread(move || {
+                        set_recent_copies(&menu_handle, &menu_entries)
+                            .log_error("Failed to update recent copies menu");
+                    })
+                    .log_error("Failed to update recent copies menu");
+                handle
+                    .emit("clipboard-history-changed", entries)
+                    .log_error("Failed to emit clipboard-history-changed");
             });
+            set_recent_copies(app.handle(), &history.entries())?;
+            app.manage(history.clone());
+
+            let handle = app.handle().clone();
+            app.manage(clipboard::CopyQueue::start(
+                clipboard::create_backend,
+                history,
+                move |result| {
+                    handle
+                        .emit("clipboard-copy-result", result)
+                        .log_error("Failed to emit clipboard-copy-result");
+                },
+            ));
             Ok(())
         })
         .build(tauri::generate_context!())
         .expect("error while running tauri application")
-        .run(|app, event| {
-            if let tauri::RunEvent::WindowEvent {
+        .run(|app, event| match event {
+            tauri::RunEvent::WindowEvent {
                 event: tauri::WindowEvent::Focused(true),
                 ..
-            } = event
-            {
+            } => {
                 if let Some(window) = app.get_webview_window("main") {
-                    let _ = window.set_focus();
+                    window.set_focus().log_error("Failed to focus window");
                 }
             }
+            // Closing the last window exits through the event loop, not exit_app
+            tauri::RunEvent::Exit => before_exit(app),
+            _ => {}
         });
 }
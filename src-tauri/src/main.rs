@@ -2,5 +2,15 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
 fn main() {
+    // Detached helper that keeps serving the clipboard after the app exits
+    if std::env::args().nth(1).as_deref() == Some(ursamarkup_lib::clipboard::HELPER_ARG) {
+        std::process::exit(ursamarkup_lib::clipboard::run_helper());
+    }
+
+    // Subcommands such as `render` run without a window
+    if let Some(code) = ursamarkup_lib::cli::run_headless(std::env::args_os()) {
+        std::process::exit(code);
+    }
+
     ursamarkup_lib::run()
 }
@@ -0,0 +1,127 @@
+//! Projects embedded in exported PNGs, so a plain PNG shared with others can
+//! be reopened with its annotations still editable.
+//!
+//! The `.ursa` archive is stored base64 encoded in a compressed `iTXt`
+//! chunk ahead of the image data. Viewers skip text chunks they do not know
+//! and show the flattened image as usual.
+
+use std::io::Cursor;
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use png::text_metadata::ITXtChunk;
+
+use crate::error::AppError;
+
+/// Keyword of the `iTXt` chunk holding the archive
+const KEYWORD: &str = "UrsaMarkupProject";
+
+/// The chunk holds a whole image, so allow more than the decoder's 64 MiB default
+const READ_LIMIT_BYTES: usize = 512 * 1024 * 1024;
+
+/// An `iTXt` chunk carrying a project archive
+pub fn png_chunk(archive: &[u8]) -> Result<ITXtChunk, AppError> {
+    let mut chunk = ITXtChunk::new(KEYWORD, STANDARD.encode(archive));
+    chunk
+        .compress_text()
+        .map_err(|e| AppError::encode("PNG", e))?;
+    Ok(chunk)
+}
+
+/// Whether a PNG file carries an embedded project, without decompressing it
+pub fn has_embedded_project(png: &[u8]) -> bool {
+    project_chunk(png, READ_LIMIT_BYTES).is_some()
+}
+
+/// The project archive embedded in a PNG file, if any
+pub fn embedded_project(png: &[u8]) -> Result<Option<Vec<u8>>, AppError> {
+    let Some(chunk) = project_chunk(png, READ_LIMIT_BYTES) else {
+        return Ok(None);
+    };
+    let text = chunk
+        .get_text()
+        .map_err(|e| AppError::decode(Some("png"), e))?;
+    STANDARD
+        .decode(text.trim())
+        .map(Some)
+        .map_err(|e| AppError::decode(Some("png"), format!("embedded project: {}", e)))
+}
+
+/// Only chunks before the image data are read, which is where exports put it.
+/// Files whose chunks exceed `limit_bytes` are treated as having none.
+fn project_chunk(png: &[u8], limit_bytes: usize) -> Option<ITXtChunk> {
+    let limits = png::Limits { bytes: limit_bytes };
+    let reader = png::Decoder::new_with_limits(Cursor::new(png), limits)
+        .read_info()
+        .ok()?;
+    reader
+        .info()
+        .utf8_text
+        .iter()
+        .find(|chunk| chunk.keyword == KEYWORD)
+        .cloned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::imaging::{encode_rgba, ExportFormat, Metadata};
+
+    /// A 1×1 PNG with `chunk` ahead of the image data
+    fn png_with(chunk: ITXtChunk) -> Vec<u8> {
+        let mut info = png::Info::with_size(1, 1);
+        info.utf8_text.push(chunk);
+        let mut out = Vec::new();
+        let mut encoder = png::Encoder::with_info(&mut out, info).unwrap();
+        encoder.set_color(png::ColorType::Rgba);
+        let mut writer = encoder.write_header().unwrap();
+        writer.write_image_data(&[0, 0, 0, 255]).unwrap();
+        writer.finish().unwrap();
+        out
+    }
+
+    #[test]
+    fn exported_png_carries_the_archive() {
+        let archive = b"PK\x03\x04 not really a zip".to_vec();
+        let metadata = Metadata {
+            project: Some(archive.clone()),
+            ..Metadata::default()
+        };
+        let format = ExportFormat::Png {
+            compression: 6,
+            palette: false,
+        };
+        let png = encode_rgba(&[10, 20, 30, 255], 1, 1, format, &metadata).unwrap();
+
+        assert!(has_embedded_project(&png));
+        assert_eq!(embedded_project(&png).unwrap(), Some(archive));
+        // Viewers still see the image
+        let image = image::load_from_memory(&png).unwrap().to_rgba8();
+        assert_eq!(image.as_raw(), &[10, 20, 30, 255]);
+    }
+
+    #[test]
+    fn plain_png_has_no_project() {
+        let png = png_with(ITXtChunk::new("Comment", "hello"));
+        assert!(!has_embedded_project(&png));
+        assert_eq!(embedded_project(&png).unwrap(), None);
+        assert!(!has_embedded_project(b"not a png"));
+    }
+
+    #[test]
+    fn reads_archives_past_the_decoder_default_limit() {
+        // Stored uncompressed, the chunk is larger than the 64 MiB the PNG
+        // decoder allows by default
+        let archive = vec![0x5a; 50 * 1024 * 1024];
+        let png = png_with(ITXtChunk::new(KEYWORD, STANDARD.encode(&archive)));
+        assert!(png.len() > 64 * 1024 * 1024);
+        assert_eq!(embedded_project(&png).unwrap(), Some(archive));
+    }
+
+    #[test]
+    fn ignores_chunks_past_the_read_limit() {
+        let png = png_with(ITXtChunk::new(KEYWORD, "A".repeat(256 * 1024)));
+        assert!(project_chunk(&png, 1024 * 1024).is_some());
+        assert!(project_chunk(&png, 128 * 1024).is_none());
+    }
+}
@@ -0,0 +1,125 @@
+//! Upgrading project state written with an older schema.
+//!
+//! Every schema change bumps [`SCHEMA_VERSION`] and appends a step to
+//! [`MIGRATIONS`] that rewrites the previous version's JSON in place, so a
+//! project of any age is upgraded one version at a time.
+
+use super::ProjectState;
+use crate::error::AppError;
+
+/// Schema version written by this build
+pub const SCHEMA_VERSION: u32 = 1;
+
+type Migration = fn(&mut ProjectState) -> Result<(), AppError>;
+
+/// `MIGRATIONS[n]` upgrades schema `n + 1` to `n + 2`
+const MIGRATIONS: &[Migration] = &[];
+
+const _: () = assert!(MIGRATIONS.len() == SCHEMA_VERSION as usize - 1);
+
+/// Upgrade `state` from schema `from` to [`SCHEMA_VERSION`]
+pub fn migrate(state: &mut ProjectState, from: u32) -> Result<(), AppError> {
+    run(state, from, MIGRATIONS)
+}
+
+/// Fail unless a project written with schema `from` can be migrated
+pub fn check_version(from: u32) -> Result<(), AppError> {
+    check(from, SCHEMA_VERSION)
+}
+
+/// Apply the steps of `migrations` from schema `from` on, up to the schema
+/// after the last step
+fn run(state: &mut ProjectState, from: u32, migrations: &[Migration]) -> Result<(), AppError> {
+    check(from, migrations.len() as u32 + 1)?;
+    for step in &migrations[(from - 1) as usize..] {
+        step(state)?;
+    }
+    Ok(())
+}
+
+fn check(from: u32, current: u32) -> Result<(), AppError> {
+    if from == 0 {
+        return Err(AppError::decode(Some("ursa"), "invalid schema version 0"));
+    }
+    if from > current {
+        return Err(AppError::decode(
+            Some("ursa"),
+            format!(
+                "schema version {} is newer than this version of Ursa Markup supports ({})",
+                from, current
+            ),
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn state() -> ProjectState {
+        ProjectState {
+            history: json!({ "groups": [], "currentIndex": -1 }),
+            view: json!({ "zoom": 1.0 }),
+        }
+    }
+
+    /// Stand-ins for two schema changes: a renamed view field, then a new one
+    fn rename_zoom(state: &mut ProjectState) -> Result<(), AppError> {
+        let zoom = state.view["zoom"].take();
+        state.view["scale"] = zoom;
+        state.view.as_object_mut().unwrap().remove("zoom");
+        Ok(())
+    }
+
+    fn add_rotation(state: &mut ProjectState) -> Result<(), AppError> {
+        state.view["rotation"] = json!(0);
+        Ok(())
+    }
+
+    const STUB_MIGRATIONS: &[Migration] = &[rename_zoom, add_rotation];
+
+    #[test]
+    fn applies_every_step_after_the_file_schema() {
+        let mut state = state();
+        run(&mut state, 1, STUB_MIGRATIONS).unwrap();
+        assert_eq!(state.view, json!({ "scale": 1.0, "rotation": 0 }));
+    }
+
+    #[test]
+    fn skips_steps_the_file_already_has() {
+        let mut state = state();
+        run(&mut state, 2, STUB_MIGRATIONS).unwrap();
+        assert_eq!(state.view, json!({ "zoom": 1.0, "rotation": 0 }));
+
+        let mut state = self::state();
+        run(&mut state, 3, STUB_MIGRATIONS).unwrap();
+        assert_eq!(state.view, json!({ "zoom": 1.0 }));
+    }
+
+    #[test]
+    fn stops_at_a_failing_step() {
+        fn fail(_: &mut ProjectState) -> Result<(), AppError> {
+            Err(AppError::decode(Some("ursa"), "broken history"))
+        }
+        let mut state = state();
+        let error = run(&mut state, 1, &[fail, add_rotation]).unwrap_err();
+        assert!(matches!(error, AppError::DecodeFailed { .. }));
+        assert!(state.view.get("rotation").is_none());
+    }
+
+    #[test]
+    fn rejects_future_and_zero_versions() {
+        for version in [0, SCHEMA_VERSION + 1] {
+            let error = migrate(&mut state(), version).unwrap_err();
+            assert!(
+                matches!(error, AppError::DecodeFailed { .. }),
+                "{}",
+                version
+            );
+        }
+        assert!(migrate(&mut state(), SCHEMA_VERSION).is_ok());
+    }
+}
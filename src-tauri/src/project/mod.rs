@@ -0,0 +1,465 @@
+//! `.ursa` project files, which keep a document editable after saving
+//! instead of flattening the annotations into the image.
+//!
+//! A project is a zip archive with:
+//! - `manifest.json`: the schema version and the image entry's name
+//! - `image.<ext>`: the image the annotations were drawn on
+//! - `history.json`: the stroke history, including the undo position
+//! - `view.json`: zoom, pan and ruler state
+//!
+//! History and view are stored as the frontend serialises them. The backend
+//! only looks inside them to migrate projects written with an older schema.
+//!
+//! The same archive can also travel inside an exported PNG; see [`embed`].
+
+mod embed;
+mod migrate;
+
+use std::fs::File;
+use std::io::{BufReader, Cursor, ErrorKind, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use image::ImageReader;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tauri::ipc::{Request, Response};
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipArchive, ZipWriter};
+
+pub use embed::{embedded_project, has_embedded_project, png_chunk};
+pub use migrate::SCHEMA_VERSION;
+
+use crate::error::AppError;
+use crate::headers::{framed, framed_body, text_header};
+use crate::imaging::{self, write_atomic, ExportResult, ImageSummary};
+
+pub const PROJECT_EXTENSION: &str = "ursa";
+
+const MANIFEST_ENTRY: &str = "manifest.json";
+const HISTORY_ENTRY: &str = "history.json";
+const VIEW_ENTRY: &str = "view.json";
+
+/// Largest uncompressed entry read from an archive. The sizes in the zip
+/// header are not trusted; entries are cut off when they exceed this.
+const MAX_ENTRY_BYTES: u64 = 256 * 1024 * 1024;
+/// Largest uncompressed size of all entries read from one archive
+const MAX_ARCHIVE_BYTES: u64 = 512 * 1024 * 1024;
+
+#[derive(Serialize, Deserialize)]
+struct Manifest {
+    schema_version: u32,
+    /// Name of the image entry
+    image: String,
+    /// App and version that wrote the file
+    #[serde(default)]
+    generator: String,
+}
+
+/// The editable state of a project, in the current schema
+#[derive(Serialize, Deserialize)]
+pub struct ProjectState {
+    /// `{ groups, currentIndex }`
+    pub history: Value,
+    /// `{ zoom, viewOffset, ruler }`
+    pub view: Value,
+}
+
+pub struct Project {
+    pub state: ProjectState,
+    /// Encoded image bytes
+    pub image: Vec<u8>,
+    /// Schema the file was written with, before migration
+    pub schema_version: u32,
+}
+
+/// Whether `path` has the project extension
+pub fn is_project_path(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case(PROJECT_EXTENSION))
+}
+
+/// Pack an encoded image and its editable state into a project archive
+pub fn write_project(image: &[u8], state: &ProjectState) -> Result<Vec<u8>, AppError> {
+    let format = image::guess_format(image)
+        .map_err(|e| AppError::invalid(format!("Unrecognised project image: {}", e)))?;
+    let extension = format.extensions_str().first().copied().unwrap_or("img");
+    let manifest = Manifest {
+        schema_version: SCHEMA_VERSION,
+        image: format!("image.{}", extension),
+        generator: format!("Ursa Markup {}", env!("CARGO_PKG_VERSION")),
+    };
+
+    let encode_error = |e: zip::result::ZipError| AppError::encode("Ursa project", e);
+    let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+    write_json(&mut zip, MANIFEST_ENTRY, &manifest)?;
+    // Image formats are compressed already
+    zip.start_file(
+        manifest.image.as_str(),
+        SimpleFileOptions::default().compression_method(CompressionMethod::Stored),
+    )
+    .map_err(encode_error)?;
+    zip.write_all(image)
+        .map_err(|e| AppError::encode("Ursa project", e))?;
+    write_json(&mut zip, HISTORY_ENTRY, &state.history)?;
+    write_json(&mut zip, VIEW_ENTRY, &state.view)?;
+    Ok(zip.finish().map_err(encode_error)?.into_inner())
+}
+
+fn write_json(
+    zip: &mut ZipWriter<Cursor<Vec<u8>>>,
+    name: &str,
+    value: &impl Serialize,
+) -> Result<(), AppError> {
+    let json = serde_json::to_vec_pretty(value).map_err(|e| AppError::internal(e.to_string()))?;
+    zip.start_file(
+        name,
+        SimpleFileOptions::default().compression_method(CompressionMethod::Deflated),
+    )
+    .map_err(|e| AppError::encode("Ursa project", e))?;
+    zip.write_all(&json)
+        .map_err(|e| AppError::encode("Ursa project", e))
+}
+
+/// Unpack a project archive, migrating its state to the current schema
+pub fn read_project(bytes: &[u8]) -> Result<Project, AppError> {
+    read_project_within(bytes, ReadLimits::default())
+}
+
+fn read_project_within(bytes: &[u8], mut limits: ReadLimits) -> Result<Project, AppError> {
+    let mut zip = ZipArchive::new(Cursor::new(bytes)).map_err(decode_error)?;
+    let manifest: Manifest = read_json(&mut zip, MANIFEST_ENTRY, &mut limits)?;
+    let mut state = ProjectState {
+        history: read_json(&mut zip, HISTORY_ENTRY, &mut limits)?,
+        view: read_json(&mut zip, VIEW_ENTRY, &mut limits)?,
+    };
+    migrate::migrate(&mut state, manifest.schema_version)?;
+    if !state.history.get("groups").is_some_and(Value::is_array) {
+        return Err(AppError::decode(
+            Some("ursa"),
+            "stroke history has no groups",
+        ));
+    }
+    let image = read_entry(&mut zip, &manifest.image, &mut limits)?;
+    Ok(Project {
+        state,
+        image,
+        schema_version: manifest.schema_version,
+    })
+}
+
+/// Uncompressed bytes still allowed to be read from an archive
+struct ReadLimits {
+    entry: u64,
+    total: u64,
+    remaining: u64,
+}
+
+impl Default for ReadLimits {
+    fn default() -> Self {
+        Self {
+            entry: MAX_ENTRY_BYTES,
+            total: MAX_ARCHIVE_BYTES,
+            remaining: MAX_ARCHIVE_BYTES,
+        }
+    }
+}
+
+fn read_entry<R: Read + Seek>(
+    zip: &mut ZipArchive<R>,
+    name: &str,
+    limits: &mut ReadLimits,
+) -> Result<Vec<u8>, AppError> {
+    let entry = zip.by_name(name).map_err(decode_error)?;
+    let limit = limits.entry.min(limits.remaining);
+    let mut bytes = Vec::new();
+    entry
+        .take(limit + 1)
+        .read_to_end(&mut bytes)
+        .map_err(|e| AppError::decode(Some("ursa"), format!("{}: {}", name, e)))?;
+    if bytes.len() as u64 > limit {
+        return Err(AppError::invalid(if limit == limits.entry {
+            format!("{} in project is over {} bytes uncompressed", name, limit)
+        } else {
+            format!("Project is over {} bytes uncompressed", limits.total)
+        }));
+    }
+    limits.remaining -= bytes.len() as u64;
+    Ok(bytes)
+}
+
+fn read_json<T: DeserializeOwned, R: Read + Seek>(
+    zip: &mut ZipArchive<R>,
+    name: &str,
+    limits: &mut ReadLimits,
+) -> Result<T, AppError> {
+    serde_json::from_slice(&read_entry(zip, name, limits)?)
+        .map_err(|e| AppError::decode(Some("ursa"), format!("{}: {}", name, e)))
+}
+
+fn decode_error(e: zip::result::ZipError) -> AppError {
+    AppError::decode(Some("ursa"), e)
+}
+
+/// Read a `.ursa` file, or the project embedded in an exported PNG
+pub fn read_project_file(path: &Path) -> Result<Project, AppError> {
+    let bytes = std::fs::read(path).map_err(|e| AppError::io(Some(path), e))?;
+    if is_project_path(path) {
+        return read_project(&bytes);
+    }
+    let archive = embedded_project(&bytes)?.ok_or_else(|| {
+        AppError::not_found(format!("No editable annotations in {}", path.display()))
+    })?;
+    read_project(&archive)
+}
+
+/// The image of a `.ursa` file as [`ImageSummary`] with the project format,
+/// from the manifest and the image header. The history and the rest of the
+/// image are not read, so this stays cheap for large projects.
+pub fn probe_project_file(path: &Path) -> Result<ImageSummary, AppError> {
+    let file = File::open(path).map_err(|e| AppError::io(Some(path), e))?;
+    let mut zip = ZipArchive::new(BufReader::new(file)).map_err(decode_error)?;
+    let mut limits = ReadLimits::default();
+    let manifest: Manifest = read_json(&mut zip, MANIFEST_ENTRY, &mut limits)?;
+    migrate::check_version(manifest.schema_version)?;
+
+    let entry = zip.by_name(&manifest.image).map_err(decode_error)?;
+    let stored = (entry.compression() == CompressionMethod::Stored)
+        .then(|| (entry.data_start(), entry.size()));
+    drop(entry);
+    let image = match stored {
+        // This app writes images stored, so they can be read in place
+        Some((start, len)) => {
+            let window = StoredEntry::new(zip.into_inner(), start, len)
+                .map_err(|e| AppError::io(Some(path), e))?;
+            imaging::probe(ImageReader::new(BufReader::new(window)))?
+        }
+        None => {
+            let bytes = read_entry(&mut zip, &manifest.image, &mut limits)?;
+            imaging::probe(ImageReader::new(Cursor::new(bytes)))?
+        }
+    };
+    Ok(ImageSummary {
+        format: PROJECT_EXTENSION,
+        ..image
+    })
+}
+
+/// The bytes of an uncompressed entry, read straight from the archive
+struct StoredEntry<R> {
+    archive: R,
+    start: u64,
+    len: u64,
+    pos: u64,
+}
+
+impl<R: Read + Seek> StoredEntry<R> {
+    fn new(mut archive: R, start: u64, len: u64) -> std::io::Result<Self> {
+        archive.seek(SeekFrom::Start(start))?;
+        Ok(Self {
+            archive,
+            start,
+            len,
+            pos: 0,
+        })
+    }
+}
+
+impl<R: Read> Read for StoredEntry<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let remaining = self.len.saturating_sub(self.pos);
+        let max = buf.len().min(remaining.try_into().unwrap_or(usize::MAX));
+        let read = self.archive.read(&mut buf[..max])?;
+        self.pos += read as u64;
+        Ok(read)
+    }
+}
+
+impl<R: Seek> Seek for StoredEntry<R> {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        let target = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(offset) => self.len.checked_add_signed(offset),
+            SeekFrom::Current(offset) => self.pos.checked_add_signed(offset),
+        }
+        .ok_or_else(|| std::io::Error::new(ErrorKind::InvalidInput, "seek before the entry"))?;
+        self.archive
+            .seek(SeekFrom::Start(self.start + target.min(self.len)))?;
+        self.pos = target;
+        Ok(target)
+    }
+}
+
+/// Save a project to `x-path`.
+///
+/// The body is a little-endian `u32` length, that many bytes of
+/// [`ProjectState`] JSON, then the encoded image the strokes were drawn on.
+#[tauri::command]
+pub async fn save_project(request: Request<'_>) -> Result<ExportResult, AppError> {
+    let (state, image): (ProjectState, &[u8]) = framed_body(&request)?;
+    let path = PathBuf::from(
+        text_header(&request, "x-path")
+            .ok_or_else(|| AppError::invalid("Missing header: x-path"))?,
+    );
+    let image = image.to_vec();
+
+    tokio::task::spawn_blocking(move || {
+        let bytes = write_project(&image, &state)?;
+        write_atomic(&path, &bytes, false)?;
+        Ok(ExportResult {
+            path: path.display().to_string(),
+            bytes: bytes.len(),
+            backup: None,
+        })
+    })
+    .await
+    .map_err(|e| AppError::internal(format!("Task join error: {}", e)))?
+}
+
+#[derive(Serialize)]
+struct LoadedProject {
+    /// Schema the file was written with; the state is already migrated
+    schema_version: u32,
+    history: Value,
+    view: Value,
+}
+
+/// Open a project file, or a PNG exported with its project embedded.
+///
+/// The response uses the same framing as `save_project`'s body: the
+/// migrated state as JSON, then the encoded image.
+#[tauri::command]
+pub async fn load_project(path: String) -> Result<Response, AppError> {
+    let project = tokio::task::spawn_blocking(move || read_project_file(Path::new(&path)))
+        .await
+        .map_err(|e| AppError::internal(format!("Task join error: {}", e)))??;
+
+    let loaded = LoadedProject {
+        schema_version: project.schema_version,
+        history: project.state.history,
+        view: project.state.view,
+    };
+    Ok(Response::new(framed(&loaded, &project.image)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn png() -> Vec<u8> {
+        let mut bytes = Vec::new();
+        image::RgbaImage::from_pixel(3, 2, image::Rgba([200, 10, 10, 255]))
+            .write_to(&mut Cursor::new(&mut bytes), image::ImageFormat::Png)
+            .unwrap();
+        bytes
+    }
+
+    fn state() -> ProjectState {
+        ProjectState {
+            history: json!({ "groups": [{ "strokes": [] }], "currentIndex": 0 }),
+            view: json!({ "zoom": 2.0, "viewOffset": { "x": 4, "y": -8 } }),
+        }
+    }
+
+    /// An archive like `write_project` writes, with its manifest replaced
+    fn archive_with(manifest: &Manifest, image: &[u8]) -> Vec<u8> {
+        let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+        write_json(&mut zip, MANIFEST_ENTRY, manifest).unwrap();
+        zip.start_file(manifest.image.as_str(), SimpleFileOptions::default())
+            .unwrap();
+        zip.write_all(image).unwrap();
+        write_json(&mut zip, HISTORY_ENTRY, &state().history).unwrap();
+        write_json(&mut zip, VIEW_ENTRY, &state().view).unwrap();
+        zip.finish().unwrap().into_inner()
+    }
+
+    #[test]
+    fn round_trips_state_and_image() {
+        let image = png();
+        let project = read_project(&write_project(&image, &state()).unwrap()).unwrap();
+        assert_eq!(project.image, image);
+        assert_eq!(project.state.history, state().history);
+        assert_eq!(project.state.view, state().view);
+        assert_eq!(project.schema_version, SCHEMA_VERSION);
+    }
+
+    #[test]
+    fn probes_stored_and_compressed_images() {
+        let dir = std::env::temp_dir().join(format!("ursamarkup-probe-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let manifest = Manifest {
+            schema_version: SCHEMA_VERSION,
+            image: "image.png".into(),
+            generator: String::new(),
+        };
+        // `archive_with` deflates the image, `write_project` stores it
+        for (name, archive) in [
+            ("stored.ursa", write_project(&png(), &state()).unwrap()),
+            ("deflated.ursa", archive_with(&manifest, &png())),
+        ] {
+            let path = dir.join(name);
+            std::fs::write(&path, archive).unwrap();
+            let summary = probe_project_file(&path).unwrap();
+            assert_eq!(
+                (summary.format, summary.width, summary.height),
+                (PROJECT_EXTENSION, 3, 2),
+                "{}",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_projects_from_a_newer_version() {
+        let manifest = Manifest {
+            schema_version: SCHEMA_VERSION + 1,
+            image: "image.png".into(),
+            generator: String::new(),
+        };
+        let error = read_project(&archive_with(&manifest, &png()))
+            .err()
+            .unwrap();
+        assert!(matches!(error, AppError::DecodeFailed { .. }));
+    }
+
+    #[test]
+    fn rejects_entries_over_the_limit() {
+        let manifest = Manifest {
+            schema_version: SCHEMA_VERSION,
+            image: "image.png".into(),
+            generator: String::new(),
+        };
+        // Compresses far below the limit, as a zip bomb would
+        let archive = archive_with(&manifest, &vec![0; 64 * 1024]);
+        let limits = ReadLimits {
+            entry: 16 * 1024,
+            total: MAX_ARCHIVE_BYTES,
+            remaining: MAX_ARCHIVE_BYTES,
+        };
+        let error = read_project_within(&archive, limits).err().unwrap();
+        assert!(matches!(error, AppError::InvalidInput { .. }));
+    }
+
+    #[test]
+    fn rejects_archives_over_the_total_limit() {
+        let archive = write_project(&png(), &state()).unwrap();
+        let entries_size = {
+            let mut zip = ZipArchive::new(Cursor::new(&archive)).unwrap();
+            (0..zip.len())
+                .map(|i| zip.by_index(i).unwrap().size())
+                .sum::<u64>()
+        };
+        let limits = |total| ReadLimits {
+            entry: MAX_ENTRY_BYTES,
+            total,
+            remaining: total,
+        };
+        assert!(read_project_within(&archive, limits(entries_size)).is_ok());
+        let error = read_project_within(&archive, limits(entries_size - 1))
+            .err()
+            .unwrap();
+        assert!(matches!(error, AppError::InvalidInput { .. }));
+    }
+}
@@ -0,0 +1,165 @@
+//! Tool drawing, following `BrushEngine.ts` path for path.
+
+use tiny_skia::{FillRule, LineCap, LineJoin, Paint, Path, PathBuilder, Pixmap, Shader, Transform};
+
+use super::{color, BlendMode, Point, Stroke, ToolConfig};
+use crate::error::AppError;
+
+/// Control point distance for a quarter circle drawn as a cubic
+const KAPPA: f32 = 0.552_284_8;
+
+/// Draw `stroke` in image coordinates mapped through `transform`
+pub fn draw_stroke(
+    pixmap: &mut Pixmap,
+    stroke: &Stroke,
+    transform: Transform,
+) -> Result<(), AppError> {
+    match stroke.tool_config {
+        ToolConfig::Pen {
+            size,
+            opacity,
+            blend_mode,
+        } => {
+            let paint = paint(&stroke.color, opacity, blend_mode)?;
+            if let Some(path) = smoothed_path(&stroke.points, true) {
+                let style = tiny_skia::Stroke {
+                    width: size as f32,
+                    line_cap: LineCap::Round,
+                    line_join: LineJoin::Round,
+                    ..Default::default()
+                };
+                pixmap.stroke_path(&path, &paint, &style, transform, None);
+            }
+        }
+        ToolConfig::Highlighter {
+            size,
+            opacity,
+            blend_mode,
+        } => {
+            let paint = paint(&stroke.color, opacity, blend_mode)?;
+            if let Some(path) = smoothed_path(&stroke.points, false) {
+                let style = tiny_skia::Stroke {
+                    width: size as f32,
+                    line_cap: LineCap::Square,
+                    line_join: LineJoin::Bevel,
+                    ..Default::default()
+                };
+                pixmap.stroke_path(&path, &paint, &style, transform, None);
+            }
+        }
+        ToolConfig::Area {
+            opacity,
+            blend_mode,
+            border_radius,
+        } => {
+            let [start, .., end] = stroke.points[..] else {
+                return Ok(());
+            };
+            let paint = paint(&stroke.color, opacity, blend_mode)?;
+            if let Some(path) = area_path(start, end, border_radius) {
+                pixmap.fill_path(&path, &paint, FillRule::Winding, transform, None);
+            }
+        }
+        ToolConfig::Eraser { .. } => {}
+    }
+    Ok(())
+}
+
+fn paint(css_color: &str, opacity: f64, blend_mode: BlendMode) -> Result<Paint<'static>, AppError> {
+    let mut color = color::parse(css_color)?;
+    color.apply_opacity((opacity / 100.0) as f32);
+    Ok(Paint {
+        shader: Shader::SolidColor(color),
+        blend_mode: match blend_mode {
+            BlendMode::Normal => tiny_skia::BlendMode::SourceOver,
+            BlendMode::Multiply => tiny_skia::BlendMode::Multiply,
+        },
+        anti_alias: true,
+        ..Default::default()
+    })
+}
+
+/// Quadratic curves through the midpoints between samples, ending with a
+/// line to the last one. With `dot`, a single point becomes a tiny segment
+/// so round caps draw it as a dot.
+fn smoothed_path(points: &[Point], dot: bool) -> Option<Path> {
+    let first = points.first()?;
+    let mut path = PathBuilder::new();
+    path.move_to(first.x as f32, first.y as f32);
+
+    match points {
+        [_] | [_, _] if dot && points.iter().all(|p| p == first) => {
+            path.line_to(first.x as f32, first.y as f32 + 0.1);
+        }
+        [_] | [_, _] => {
+            for p in &points[1..] {
+                path.line_to(p.x as f32, p.y as f32);
+            }
+        }
+        _ => {
+            for pair in points[1..].windows(2) {
+                let (curr, next) = (pair[0], pair[1]);
+                path.quad_to(
+                    curr.x as f32,
+                    curr.y as f32,
+                    ((curr.x + next.x) / 2.0) as f32,
+                    ((curr.y + next.y) / 2.0) as f32,
+                );
+            }
+            let last = points[points.len() - 1];
+            path.line_to(last.x as f32, last.y as f32);
+        }
+    }
+    path.finish()
+}
+
+/// The rectangle spanned by the first and last points, with its corner
+/// radius capped at half the shorter side like `CanvasRenderingContext2D.roundRect`
+fn area_path(start: Point, end: Point, border_radius: f64) -> Option<Path> {
+    let x = start.x.min(end.x) as f32;
+    let y = start.y.min(end.y) as f32;
+    let width = (end.x - start.x).abs() as f32;
+    let height = (end.y - start.y).abs() as f32;
+    let radius = (border_radius.max(0.0) as f32).min(width.min(height) / 2.0);
+
+    let mut path = PathBuilder::new();
+    if radius <= 0.0 {
+        path.push_rect(tiny_skia::Rect::from_xywh(x, y, width, height)?);
+        return path.finish();
+    }
+
+    let (right, bottom) = (x + width, y + height);
+    let k = radius * KAPPA;
+    path.move_to(x + radius, y);
+    path.line_to(right - radius, y);
+    path.cubic_to(
+        right - radius + k,
+        y,
+        right,
+        y + radius - k,
+        right,
+        y + radius,
+    );
+    path.line_to(right, bottom - radius);
+    path.cubic_to(
+        right,
+        bottom - radius + k,
+        right - radius + k,
+        bottom,
+        right - radius,
+        bottom,
+    );
+    path.line_to(x + radius, bottom);
+    path.cubic_to(
+        x + radius - k,
+        bottom,
+        x,
+        bottom - radius + k,
+        x,
+        bottom - radius,
+    );
+    path.line_to(x, y + radius);
+    path.cubic_to(x, y + radius - k, x + radius - k, y, x + radius, y);
+    path.close();
+    path.finish()
+}
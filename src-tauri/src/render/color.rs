@@ -0,0 +1,68 @@
+//! CSS colours as stored on strokes.
+//!
+//! The palette and colour picker produce hex strings, and older documents may
+//! carry `rgb()`/`rgba()`; named colours are not used by the app.
+
+use tiny_skia::Color;
+
+use crate::error::AppError;
+
+/// Parse `#rgb`, `#rgba`, `#rrggbb`, `#rrggbbaa`, `rgb()` or `rgba()`
+pub fn parse(css: &str) -> Result<Color, AppError> {
+    let css = css.trim();
+    let parsed = match css.strip_prefix('#') {
+        Some(hex) => parse_hex(hex),
+        None => parse_function(css),
+    };
+    parsed.ok_or_else(|| AppError::invalid(format!("Unsupported stroke colour: {:?}", css)))
+}
+
+fn parse_hex(hex: &str) -> Option<Color> {
+    if !hex.is_ascii() {
+        return None;
+    }
+    let digit = |i: usize| u8::from_str_radix(&hex[i..=i], 16).ok().map(|d| d * 17);
+    let pair = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok();
+    let [r, g, b, a] = match hex.len() {
+        3 => [digit(0)?, digit(1)?, digit(2)?, 255],
+        4 => [digit(0)?, digit(1)?, digit(2)?, digit(3)?],
+        6 => [pair(0)?, pair(2)?, pair(4)?, 255],
+        8 => [pair(0)?, pair(2)?, pair(4)?, pair(6)?],
+        _ => return None,
+    };
+    Some(Color::from_rgba8(r, g, b, a))
+}
+
+/// `rgb(255, 107, 107)`, `rgba(255, 107, 107, 0.5)` or the space-separated
+/// `rgb(255 107 107 / 50%)`
+fn parse_function(css: &str) -> Option<Color> {
+    let lower = css.to_ascii_lowercase();
+    let args = lower
+        .strip_prefix("rgba(")
+        .or_else(|| lower.strip_prefix("rgb("))?
+        .strip_suffix(')')?;
+    let parts: Vec<&str> = args
+        .split([',', ' ', '/'])
+        .filter(|part| !part.is_empty())
+        .collect();
+
+    let channel = |part: &str| -> Option<f32> {
+        match part.strip_suffix('%') {
+            Some(percent) => Some(percent.parse::<f32>().ok()? / 100.0),
+            None => Some(part.parse::<f32>().ok()? / 255.0),
+        }
+    };
+    let alpha = |part: &str| -> Option<f32> {
+        match part.strip_suffix('%') {
+            Some(percent) => Some(percent.parse::<f32>().ok()? / 100.0),
+            None => part.parse().ok(),
+        }
+    };
+    let (r, g, b, a) = match parts[..] {
+        [r, g, b] => (channel(r)?, channel(g)?, channel(b)?, 1.0),
+        [r, g, b, a] => (channel(r)?, channel(g)?, channel(b)?, alpha(a)?),
+        _ => return None,
+    };
+    let clamp = |v: f32| v.clamp(0.0, 1.0);
+    Color::from_rgba(clamp(r), clamp(g), clamp(b), clamp(a))
+}
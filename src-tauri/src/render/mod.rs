@@ -0,0 +1,426 @@
+//! Flattening annotations onto their image without a webview, for exports
+//! from the command line and batch jobs.
+//!
+//! This mirrors `CanvasEngine.ts` and `BrushEngine.ts`: the history is
+//! replayed up to its undo position, eraser groups remove the strokes they
+//! touch, and what is left is drawn in order over the base image. Changes to
+//! how a tool looks on the canvas need the same change here.
+
+mod brush;
+mod color;
+
+use image::imageops::FilterType;
+use image::RgbaImage;
+use serde::Deserialize;
+use tiny_skia::{ColorU8, IntSize, Pixmap, Transform};
+
+use crate::error::AppError;
+
+/// `StrokeHistoryState` as the frontend serialises it
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StrokeHistory {
+    pub groups: Vec<StrokeGroup>,
+    /// Index of the last applied group; `-1` when everything is undone
+    pub current_index: i64,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct StrokeGroup {
+    pub strokes: Vec<Stroke>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Stroke {
+    /// CSS colour, e.g. `"#FF6B6B"`
+    #[serde(default)]
+    pub color: String,
+    pub tool_config: ToolConfig,
+    pub points: Vec<Point>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+pub struct Point {
+    pub x: f64,
+    pub y: f64,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(
+    tag = "tool",
+    rename_all = "lowercase",
+    rename_all_fields = "camelCase"
+)]
+pub enum ToolConfig {
+    Pen {
+        size: f64,
+        /// Percent
+        opacity: f64,
+        #[serde(default)]
+        blend_mode: BlendMode,
+    },
+    Highlighter {
+        size: f64,
+        opacity: f64,
+        #[serde(default)]
+        blend_mode: BlendMode,
+    },
+    Area {
+        opacity: f64,
+        #[serde(default)]
+        blend_mode: BlendMode,
+        #[serde(default)]
+        border_radius: f64,
+    },
+    Eraser {
+        size: f64,
+        #[serde(default)]
+        eraser_mode: EraseMode,
+    },
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub enum BlendMode {
+    #[default]
+    #[serde(rename = "source-over")]
+    Normal,
+    #[serde(rename = "multiply")]
+    Multiply,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub enum EraseMode {
+    #[default]
+    #[serde(rename = "contained")]
+    Pixel,
+    #[serde(rename = "full-stroke")]
+    FullStroke,
+}
+
+/// Draw the visible strokes of `history` over `base`, with the image and the
+/// strokes scaled by `scale`
+pub fn render(
+    base: &RgbaImage,
+    history: &StrokeHistory,
+    scale: f32,
+) -> Result<RgbaImage, AppError> {
+    if !(scale.is_finite() && scale > 0.0) {
+        return Err(AppError::invalid(format!("Invalid scale: {}", scale)));
+    }
+    if base.width() == 0 || base.height() == 0 {
+        return Err(AppError::invalid("Cannot render an empty image"));
+    }
+    let width = (base.width() as f32 * scale).round().max(1.0) as u32;
+    let height = (base.height() as f32 * scale).round().max(1.0) as u32;
+    let size = IntSize::from_wh(width, height)
+        .ok_or_else(|| AppError::invalid(format!("Cannot render a {}x{} image", width, height)))?;
+
+    let mut premultiplied = if (width, height) == base.dimensions() {
+        base.as_raw().clone()
+    } else {
+        image::imageops::resize(base, width, height, FilterType::Lanczos3).into_raw()
+    };
+    for pixel in premultiplied.chunks_exact_mut(4) {
+        let color = ColorU8::from_rgba(pixel[0], pixel[1], pixel[2], pixel[3]).premultiply();
+        pixel.copy_from_slice(&[color.red(), color.green(), color.blue(), color.alpha()]);
+    }
+    let mut pixmap = Pixmap::from_vec(premultiplied, size)
+        .ok_or_else(|| AppError::internal("Pixel buffer does not match the image size"))?;
+
+    let transform = Transform::from_scale(
+        width as f32 / base.width() as f32,
+        height as f32 / base.height() as f32,
+    );
+    for stroke in visible_strokes(history) {
+        brush::draw_stroke(&mut pixmap, stroke, transform)?;
+    }
+
+    let mut rgba = Vec::with_capacity(pixmap.data().len());
+    for pixel in pixmap.pixels() {
+        let color = pixel.demultiply();
+        rgba.extend_from_slice(&[color.red(), color.green(), color.blue(), color.alpha()]);
+    }
+    RgbaImage::from_raw(width, height, rgba)
+        .ok_or_else(|| AppError::internal("Pixel buffer does not match the image size"))
+}
+
+/// Strokes left after replaying the history up to its current index.
+///
+/// Both eraser modes remove whole strokes, as the canvas does today.
+fn visible_strokes(history: &StrokeHistory) -> Vec<&Stroke> {
+    let applied = usize::try_from(history.current_index.saturating_add(1)).unwrap_or(0);
+    let mut visible: Vec<&Stroke> = Vec::new();
+    for group in history.groups.iter().take(applied) {
+        let Some(first) = group.strokes.first() else {
+            continue;
+        };
+        if !matches!(first.tool_config, ToolConfig::Eraser { .. }) {
+            visible.extend(&group.strokes);
+            continue;
+        }
+        for eraser in &group.strokes {
+            let ToolConfig::Eraser { size, .. } = eraser.tool_config else {
+                continue;
+            };
+            visible.retain(|target| !is_hit_by_eraser(target, &eraser.points, size));
+        }
+    }
+    visible
+}
+
+/// `CanvasEngine.isStrokeHitByEraser`
+fn is_hit_by_eraser(target: &Stroke, eraser: &[Point], eraser_size: f64) -> bool {
+    let radius = eraser_size / 2.0;
+    let threshold = radius + 2.0;
+    let threshold_sq = threshold * threshold;
+
+    if let (ToolConfig::Area { .. }, [start, .., end]) = (&target.tool_config, &target.points[..]) {
+        let (left, right) = (start.x.min(end.x), start.x.max(end.x));
+        let (top, bottom) = (start.y.min(end.y), start.y.max(end.y));
+        return eraser.iter().any(|p| {
+            p.x >= left - radius
+                && p.x <= right + radius
+                && p.y >= top - radius
+                && p.y <= bottom + radius
+        });
+    }
+
+    let (Some(target_bounds), Some(eraser_bounds)) = (bounds(&target.points), bounds(eraser))
+    else {
+        return false;
+    };
+    let padding = match target.tool_config {
+        ToolConfig::Pen { size, .. }
+        | ToolConfig::Highlighter { size, .. }
+        | ToolConfig::Eraser { size, .. } => size,
+        ToolConfig::Area { .. } => 5.0 / 2.0 + radius,
+    };
+    if target_bounds.2 + padding < eraser_bounds.0
+        || target_bounds.0 - padding > eraser_bounds.2
+        || target_bounds.3 + padding < eraser_bounds.1
+        || target_bounds.1 - padding > eraser_bounds.3
+    {
+        return false;
+    }
+
+    if let [point] = target.points[..] {
+        return eraser
+            .iter()
+            .any(|e| distance_sq(e, &point, &point) <= threshold_sq);
+    }
+    target.points.windows(2).any(|segment| {
+        eraser
+            .iter()
+            .any(|e| distance_sq(e, &segment[0], &segment[1]) <= threshold_sq)
+    })
+}
+
+/// `(min_x, min_y, max_x, max_y)`
+fn bounds(points: &[Point]) -> Option<(f64, f64, f64, f64)> {
+    let first = points.first()?;
+    Some(points.iter().fold(
+        (first.x, first.y, first.x, first.y),
+        |(min_x, min_y, max_x, max_y), p| {
+            (
+                min_x.min(p.x),
+                min_y.min(p.y),
+                max_x.max(p.x),
+                max_y.max(p.y),
+            )
+        },
+    ))
+}
+
+/// Squared distance from `p` to the segment `v`–`w`
+fn distance_sq(p: &Point, v: &Point, w: &Point) -> f64 {
+    let l2 = (w.x - v.x).powi(2) + (w.y - v.y).powi(2);
+    if l2 == 0.0 {
+        return (p.x - v.x).powi(2) + (p.y - v.y).powi(2);
+    }
+    let t = (((p.x - v.x) * (w.x - v.x) + (p.y - v.y) * (w.y - v.y)) / l2).clamp(0.0, 1.0);
+    (p.x - (v.x + t * (w.x - v.x))).powi(2) + (p.y - (v.y + t * (w.y - v.y))).powi(2)
+}
+
+#[cfg(test)]
+mod tests {
+    //! Each tool is checked against a reference image in `testdata/`, drawn
+    //! from the same case file by the frontend's own code: `record.mjs`
+    //! replays the history through `BrushEngine.ts` the way
+    //! `CanvasEngine.drawStrokeToContext` does and records the Canvas2D
+    //! calls, and `rasterise.py` draws those calls with Cairo. To regenerate
+    //! them after a change to how a tool draws, from the repository root:
+    //!
+    //! ```text
+    //! node src-tauri/src/render/testdata/record.mjs | python3 src-tauri/src/render/testdata/rasterise.py
+    //! ```
+    //!
+    //! Cairo and tiny-skia anti-alias edges differently, so the comparison
+    //! allows a small difference per channel and a few pixels past it where
+    //! curves are flattened differently.
+
+    use std::path::PathBuf;
+
+    use image::Rgba;
+    use serde_json::{json, Value};
+
+    use super::*;
+
+    /// Largest difference allowed in most channels: tiny-skia samples edges
+    /// 4×4 per pixel, so its coverage moves in steps of 1/16
+    const CHANNEL_TOLERANCE: u8 = 16;
+
+    /// Share of pixels allowed past `CHANNEL_TOLERANCE`, on stroke edges
+    const EDGE_PIXELS: f64 = 0.01;
+
+    /// Largest difference allowed in any channel of those edge pixels
+    const EDGE_TOLERANCE: u8 = 48;
+
+    fn history(strokes: Value) -> StrokeHistory {
+        let groups: Vec<Value> = strokes
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|stroke| json!({ "strokes": [stroke] }))
+            .collect();
+        let current_index = groups.len() as i64 - 1;
+        serde_json::from_value(json!({ "groups": groups, "currentIndex": current_index })).unwrap()
+    }
+
+    fn white() -> RgbaImage {
+        RgbaImage::from_pixel(48, 32, Rgba([255, 255, 255, 255]))
+    }
+
+    /// White with a grey band across the middle, to show blending
+    fn banded() -> RgbaImage {
+        RgbaImage::from_fn(48, 32, |_, y| match y {
+            12..20 => Rgba([128, 128, 128, 255]),
+            _ => Rgba([255, 255, 255, 255]),
+        })
+    }
+
+    fn testdata(file: &str) -> PathBuf {
+        [
+            env!("CARGO_MANIFEST_DIR"),
+            "src",
+            "render",
+            "testdata",
+            file,
+        ]
+        .iter()
+        .collect()
+    }
+
+    /// Render `testdata/{name}.json` and compare it with `{name}.png`
+    fn assert_matches_reference(name: &str) {
+        let path = testdata(&format!("{}.json", name));
+        let case: Value = serde_json::from_slice(&std::fs::read(&path).unwrap()).unwrap();
+        let base = match case["base"].as_str() {
+            Some("white") => white(),
+            Some("banded") => banded(),
+            other => panic!("{}: unknown base {:?}", path.display(), other),
+        };
+        let scale = case["scale"].as_f64().unwrap() as f32;
+        let history: StrokeHistory = serde_json::from_value(case["history"].clone()).unwrap();
+        let actual = render(&base, &history, scale).unwrap();
+
+        let path = testdata(&format!("{}.png", name));
+        let expected = image::open(&path)
+            .unwrap_or_else(|e| panic!("{}: {}", path.display(), e))
+            .into_rgba8();
+        assert_eq!(expected.dimensions(), actual.dimensions(), "{}", name);
+        let mut edge_pixels = 0;
+        for (x, y, pixel) in actual.enumerate_pixels() {
+            let wanted = expected.get_pixel(x, y);
+            let off = pixel
+                .0
+                .iter()
+                .zip(wanted.0)
+                .map(|(a, b)| a.abs_diff(b))
+                .max()
+                .unwrap();
+            assert!(
+                off <= EDGE_TOLERANCE,
+                "{}: pixel ({}, {}) is {:?}, expected {:?}",
+                name,
+                x,
+                y,
+                pixel.0,
+                wanted.0
+            );
+            if off > CHANNEL_TOLERANCE {
+                edge_pixels += 1;
+            }
+        }
+        let allowed = (actual.len() / 4) as f64 * EDGE_PIXELS;
+        assert!(
+            edge_pixels as f64 <= allowed,
+            "{}: {} pixels differ by more than {}",
+            name,
+            edge_pixels,
+            CHANNEL_TOLERANCE
+        );
+    }
+
+    #[test]
+    fn pen() {
+        assert_matches_reference("pen");
+    }
+
+    #[test]
+    fn highlighter() {
+        assert_matches_reference("highlighter");
+    }
+
+    #[test]
+    fn area() {
+        assert_matches_reference("area");
+    }
+
+    #[test]
+    fn scaled() {
+        assert_matches_reference("scaled");
+    }
+
+    #[test]
+    fn erased_and_undone_strokes_are_not_drawn() {
+        let pen = |y: u32| {
+            json!({
+                "color": "#000",
+                "toolConfig": { "tool": "pen", "size": 4, "opacity": 100 },
+                "points": [{ "x": 4, "y": y }, { "x": 44, "y": y }]
+            })
+        };
+        let eraser = json!({
+            "toolConfig": { "tool": "eraser", "size": 8 },
+            "points": [{ "x": 24, "y": 8 }]
+        });
+        let mut history = history(json!([pen(8), eraser, pen(24)]));
+        let base = white();
+
+        // The eraser takes the first line, undo hides the last
+        history.current_index = 1;
+        assert_eq!(render(&base, &history, 1.0).unwrap(), base);
+
+        history.current_index = 2;
+        let rendered = render(&base, &history, 1.0).unwrap();
+        assert_eq!(rendered.get_pixel(24, 8), &Rgba([255, 255, 255, 255]));
+        assert_eq!(rendered.get_pixel(24, 24), &Rgba([0, 0, 0, 255]));
+    }
+
+    #[test]
+    fn rejects_bad_scale_and_colour() {
+        let history = history(json!([
+            {
+                "color": "papayawhip",
+                "toolConfig": { "tool": "pen", "size": 4, "opacity": 100 },
+                "points": [{ "x": 4, "y": 4 }]
+            }
+        ]));
+        for scale in [0.0, -1.0, f32::NAN] {
+            assert!(render(&white(), &history, scale).is_err());
+        }
+        let error = render(&white(), &history, 1.0).unwrap_err();
+        assert!(matches!(error, AppError::InvalidInput { .. }));
+    }
+}
@@ -0,0 +1,162 @@
+"""Draws the Canvas2D calls printed by `record.mjs` with Cairo and writes one
+reference PNG per case next to this file.
+
+Cairo stands in for the browser canvas: both follow the Canvas2D model for
+caps, joins, global alpha and the multiply blend, and neither is tiny-skia.
+"""
+
+import ctypes
+import ctypes.util
+import json
+import math
+import os
+import re
+import sys
+
+WIDTH, HEIGHT = 48, 32
+
+FORMAT_ARGB32 = 0
+OPERATORS = {"source-over": 2, "multiply": 14}
+LINE_CAPS = {"butt": 0, "round": 1, "square": 2}
+LINE_JOINS = {"miter": 0, "round": 1, "bevel": 2}
+
+cairo = ctypes.CDLL(ctypes.util.find_library("cairo") or "libcairo.so.2")
+cairo.cairo_image_surface_create.restype = ctypes.c_void_p
+cairo.cairo_create.restype = ctypes.c_void_p
+cairo.cairo_create.argtypes = [ctypes.c_void_p]
+cairo.cairo_surface_write_to_png.argtypes = [ctypes.c_void_p, ctypes.c_char_p]
+cairo.cairo_surface_destroy.argtypes = [ctypes.c_void_p]
+cairo.cairo_destroy.argtypes = [ctypes.c_void_p]
+for name, doubles in {
+    "cairo_set_source_rgba": 4,
+    "cairo_rectangle": 4,
+    "cairo_move_to": 2,
+    "cairo_line_to": 2,
+    "cairo_curve_to": 6,
+    "cairo_arc": 5,
+    "cairo_scale": 2,
+    "cairo_set_line_width": 1,
+}.items():
+    getattr(cairo, name).argtypes = [ctypes.c_void_p] + [ctypes.c_double] * doubles
+for name in ["cairo_set_operator", "cairo_set_line_cap", "cairo_set_line_join"]:
+    getattr(cairo, name).argtypes = [ctypes.c_void_p, ctypes.c_int]
+for name in ["cairo_new_path", "cairo_new_sub_path", "cairo_close_path", "cairo_stroke", "cairo_fill", "cairo_paint"]:
+    getattr(cairo, name).argtypes = [ctypes.c_void_p]
+cairo.cairo_get_current_point.argtypes = [
+    ctypes.c_void_p,
+    ctypes.POINTER(ctypes.c_double),
+    ctypes.POINTER(ctypes.c_double),
+]
+
+
+def parse_color(css):
+    """`#rgb`, `#rrggbb`, `rgb()` and `rgba()`, as (r, g, b, a) in 0..1"""
+    css = css.strip()
+    if css.startswith("#"):
+        digits = css[1:]
+        if len(digits) == 3:
+            digits = "".join(c * 2 for c in digits)
+        return tuple(int(digits[i : i + 2], 16) / 255 for i in (0, 2, 4)) + (1.0,)
+    match = re.fullmatch(r"rgba?\(([^)]*)\)", css)
+    if not match:
+        raise ValueError(f"Unsupported colour: {css}")
+    parts = [float(p) for p in re.split(r"[\s,/]+", match.group(1).strip())]
+    alpha = parts[3] if len(parts) > 3 else 1.0
+    return tuple(p / 255 for p in parts[:3]) + (alpha,)
+
+
+def paint_base(cr, base, scale):
+    """The bases of `render::tests`: white, or white with a grey band"""
+    cairo.cairo_set_source_rgba(cr, 1, 1, 1, 1)
+    cairo.cairo_paint(cr)
+    if base == "banded":
+        grey = 128 / 255
+        cairo.cairo_set_source_rgba(cr, grey, grey, grey, 1)
+        cairo.cairo_rectangle(cr, 0, 12 * scale, WIDTH * scale, 8 * scale)
+        cairo.cairo_fill(cr)
+    elif base != "white":
+        raise ValueError(f"Unknown base: {base}")
+
+
+def round_rect(cr, x, y, width, height, radius):
+    if radius <= 0:
+        cairo.cairo_rectangle(cr, x, y, width, height)
+        return
+    cairo.cairo_new_sub_path(cr)
+    cairo.cairo_arc(cr, x + width - radius, y + radius, radius, -math.pi / 2, 0)
+    cairo.cairo_arc(cr, x + width - radius, y + height - radius, radius, 0, math.pi / 2)
+    cairo.cairo_arc(cr, x + radius, y + height - radius, radius, math.pi / 2, math.pi)
+    cairo.cairo_arc(cr, x + radius, y + radius, radius, math.pi, 3 * math.pi / 2)
+    cairo.cairo_close_path(cr)
+
+
+def replay(cr, ops):
+    state = {"globalAlpha": 1.0, "globalCompositeOperation": "source-over"}
+
+    def set_source(style):
+        r, g, b, a = parse_color(state[style])
+        cairo.cairo_set_source_rgba(cr, r, g, b, a * state["globalAlpha"])
+        cairo.cairo_set_operator(cr, OPERATORS[state["globalCompositeOperation"]])
+
+    for op in ops:
+        if "set" in op:
+            state[op["set"]] = op["value"]
+            continue
+        call, args = op["call"], op["args"]
+        if call == "beginPath":
+            cairo.cairo_new_path(cr)
+        elif call == "moveTo":
+            cairo.cairo_move_to(cr, *args)
+        elif call == "lineTo":
+            cairo.cairo_line_to(cr, *args)
+        elif call == "quadraticCurveTo":
+            x0, y0 = ctypes.c_double(), ctypes.c_double()
+            cairo.cairo_get_current_point(cr, ctypes.byref(x0), ctypes.byref(y0))
+            cx, cy, x, y = args
+            cairo.cairo_curve_to(
+                cr,
+                x0.value + 2 / 3 * (cx - x0.value),
+                y0.value + 2 / 3 * (cy - y0.value),
+                x + 2 / 3 * (cx - x),
+                y + 2 / 3 * (cy - y),
+                x,
+                y,
+            )
+        elif call == "rect":
+            cairo.cairo_rectangle(cr, *args)
+        elif call == "roundRect":
+            round_rect(cr, *args)
+        elif call == "scale":
+            cairo.cairo_scale(cr, *args)
+        elif call == "stroke":
+            set_source("strokeStyle")
+            cairo.cairo_set_line_width(cr, state["lineWidth"])
+            cairo.cairo_set_line_cap(cr, LINE_CAPS[state["lineCap"]])
+            cairo.cairo_set_line_join(cr, LINE_JOINS[state["lineJoin"]])
+            cairo.cairo_stroke(cr)
+        elif call == "fill":
+            set_source("fillStyle")
+            cairo.cairo_fill(cr)
+        else:
+            raise ValueError(f"Unsupported call: {call}")
+
+
+def main():
+    here = os.path.dirname(os.path.abspath(__file__))
+    for name, case in json.load(sys.stdin).items():
+        scale = case["scale"]
+        surface = cairo.cairo_image_surface_create(
+            FORMAT_ARGB32, round(WIDTH * scale), round(HEIGHT * scale)
+        )
+        cr = cairo.cairo_create(surface)
+        paint_base(cr, case["base"], scale)
+        replay(cr, case["ops"])
+        path = os.path.join(here, f"{name}.png")
+        cairo.cairo_surface_write_to_png(surface, path.encode())
+        cairo.cairo_destroy(cr)
+        cairo.cairo_surface_destroy(surface)
+        print(path)
+
+
+if __name__ == "__main__":
+    main()
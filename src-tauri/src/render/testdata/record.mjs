@@ -0,0 +1,86 @@
+// Records the Canvas2D calls `BrushEngine.ts` makes for each case in this
+// directory, as `CanvasEngine.drawStrokeToContext` would issue them, and
+// prints them as JSON for `rasterise.py`:
+//
+//   node src-tauri/src/render/testdata/record.mjs | python3 src-tauri/src/render/testdata/rasterise.py
+//
+// Needs Node 22.13 or later for `module.stripTypeScriptTypes`.
+
+import { readdirSync, readFileSync } from "node:fs";
+import module from "node:module";
+import process from "node:process";
+
+const here = new URL(".", import.meta.url);
+const root = new URL("../../../../", here);
+
+async function loadBrushEngine() {
+  const source = readFileSync(new URL("src/core/BrushEngine.ts", root), "utf8");
+  // Its imports are types only, and `~/` does not resolve outside Vite
+  const code = module
+    .stripTypeScriptTypes(source)
+    .replace(/^import[^;]*;$/gms, "");
+  const url = "data:text/javascript," + encodeURIComponent(code);
+  return (await import(url)).BrushEngine;
+}
+
+/** A 2D context that keeps a list of what is done to it */
+function recordingContext(ops) {
+  const methods = [
+    "beginPath",
+    "moveTo",
+    "lineTo",
+    "quadraticCurveTo",
+    "rect",
+    "roundRect",
+    "stroke",
+    "fill",
+    "scale",
+  ];
+  const ctx = {};
+  for (const name of methods) {
+    ctx[name] = (...args) => ops.push({ call: name, args });
+  }
+  return new Proxy(ctx, {
+    set(target, name, value) {
+      ops.push({ set: name, value });
+      return true;
+    },
+  });
+}
+
+/** `CanvasEngine.drawStrokeToContext` */
+function drawStroke(brushEngine, ctx, stroke) {
+  ctx.globalCompositeOperation =
+    "blendMode" in stroke.toolConfig ? stroke.toolConfig.blendMode : "source-over";
+  switch (stroke.tool) {
+    case "pen":
+      brushEngine.drawPenStroke(ctx, stroke.points, stroke.toolConfig, stroke.color);
+      break;
+    case "highlighter":
+      brushEngine.drawHighlighterStroke(ctx, stroke.points, stroke.toolConfig, stroke.color);
+      break;
+    case "area":
+      if (stroke.points.length >= 2) {
+        const start = stroke.points[0];
+        const end = stroke.points[stroke.points.length - 1];
+        brushEngine.drawArea(ctx, start, end, stroke.toolConfig, stroke.color);
+      }
+      break;
+  }
+}
+
+const BrushEngine = await loadBrushEngine();
+const brushEngine = new BrushEngine();
+const cases = {};
+for (const file of readdirSync(here).filter((f) => f.endsWith(".json")).sort()) {
+  const { base, scale, history } = JSON.parse(readFileSync(new URL(file, here), "utf8"));
+  const ops = [];
+  const ctx = recordingContext(ops);
+  ctx.scale(scale, scale);
+  // These cases have no erasers, so every applied stroke is visible
+  for (const group of history.groups.slice(0, history.currentIndex + 1)) {
+    for (const stroke of group.strokes) drawStroke(brushEngine, ctx, stroke);
+  }
+  cases[file.replace(/\.json$/, "")] = { base, scale, ops };
+}
+process.stdout.write(JSON.stringify(cases));
@@ -465,8 +465,12 @@ export function CanvasContainer({
               services.ioService
                 .copyToClipboard(canvas, document.version, {
                   isAutoCopy: true,
+                  documentId: document.id,
+                  documentName: document.fileName ?? undefined,
                   format: settings.copySettings.autoCopyFormat,
                   jpegQuality: settings.copySettings.autoCopyJpegQuality,
+                  maxDimension: settings.copySettings.autoCopyMaxDimension,
+                  maxBytes: settings.copySettings.autoCopyMaxBytes,
                 })
                 .catch(console.error);
             }
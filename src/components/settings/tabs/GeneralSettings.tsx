@@ -1,15 +1,22 @@
-import { Clipboard, Eye, FolderClosed } from "lucide-react";
+import { Clipboard, Eye, FolderClosed, Save } from "lucide-react";
 import { Slider } from "~/components/ui/slider";
 import { Switch } from "~/components/ui/switch";
 import { DeepPartial } from "~/types";
 import {
   AutoCopyFormats,
+  ChromaSubsamplings,
+  ClipboardSelections,
   CloseTabBehaviors,
   CloseWindowBehaviors,
+  ExportMetadataModes,
   ImageOpenBehaviors,
   type AppSettings,
   type AutoCopyFormat,
+  type ChromaSubsampling,
+  type ClipboardFormatSettings,
+  type ClipboardSelection,
   type CloseTabBehavior,
+  type ExportMetadataMode,
 } from "~/types/settings";
 import {
   SettingsRow,
@@ -27,7 +34,7 @@ export function GeneralSettings({
   settings,
   updateDraft,
 }: GeneralSettingsProps) {
-  const { copySettings, miscSettings } = settings;
+  const { copySettings, exportSettings, miscSettings } = settings;
 
   const closeTabOptions: { value: CloseTabBehavior; label: string }[] = [
     { value: CloseTabBehaviors.PROMPT, label: "Ask me" },
@@ -45,11 +52,78 @@ export function GeneralSettings({
     { value: ImageOpenBehaviors.CENTER, label: "Center" },
   ];
 
+  const extraFormatRows: {
+    key: keyof ClipboardFormatSettings;
+    label: string;
+    description: string;
+  }[] = [
+    {
+      key: "png",
+      label: "Also copy as PNG",
+      description: "Offer a lossless PNG when copying as JPEG",
+    },
+    {
+      key: "jpeg",
+      label: "Also copy as JPEG",
+      description: "Offer a smaller JPEG when copying as PNG",
+    },
+    {
+      key: "fileUri",
+      label: "Also copy as file",
+      description: "Offer a temporary file for apps that only accept pasted files",
+    },
+    {
+      key: "html",
+      label: "Also copy as HTML",
+      description: "Offer the image inlined in HTML for rich text editors",
+    },
+  ];
+
+  const selectionOptions = [
+    { value: ClipboardSelections.CLIPBOARD, label: "Clipboard" },
+    { value: ClipboardSelections.BOTH, label: "Both" },
+    { value: ClipboardSelections.PRIMARY, label: "Primary" },
+  ];
+
+  const subsamplingOptions = [
+    { value: ChromaSubsamplings.YUV444, label: "4:4:4" },
+    { value: ChromaSubsamplings.YUV422, label: "4:2:2" },
+    { value: ChromaSubsamplings.YUV420, label: "4:2:0" },
+  ];
+
+  const metadataOptions = [
+    { value: ExportMetadataModes.STRIP, label: "Strip" },
+    { value: ExportMetadataModes.KEEP, label: "Keep" },
+  ];
+
   const copyFormatOptions = [
     { value: AutoCopyFormats.JPEG, label: "JPEG" },
     { value: AutoCopyFormats.PNG, label: "PNG" },
   ];
 
+  // Tabs opened at once from the command line, as strings for the toggle group
+  const cliTabLimitOptions = [
+    { value: "10", label: "10" },
+    { value: "20", label: "20" },
+    { value: "50", label: "50" },
+    { value: "100", label: "100" },
+  ];
+
+  // Values are strings for the toggle group; "0" means no limit
+  const maxDimensionOptions = [
+    { value: "0", label: "Full" },
+    { value: "1920", label: "1920" },
+    { value: "2560", label: "2560" },
+    { value: "4096", label: "4096" },
+  ];
+
+  const maxBytesOptions = [
+    { value: "0", label: "Any" },
+    { value: String(1024 * 1024), label: "1 MB" },
+    { value: String(5 * 1024 * 1024), label: "5 MB" },
+    { value: String(8 * 1024 * 1024), label: "8 MB" },
+  ];
+
   return (
     <div className="space-y-5">
       {/* ---------------------------------------------------------------------
@@ -118,6 +192,21 @@ export function GeneralSettings({
             }
           />
         </SettingsRow>
+
+        <SettingsRow
+          label="Tabs opened at once"
+          description="Opening more images from the command line or a folder asks before opening the rest"
+        >
+          <ToggleButtonGroup
+            options={cliTabLimitOptions}
+            value={String(miscSettings.cliTabLimit)}
+            onChange={(value) =>
+              updateDraft({
+                miscSettings: { cliTabLimit: Number(value) },
+              })
+            }
+          />
+        </SettingsRow>
       </SettingsSection>
 
       {/* ---------------------------------------------------------------------
@@ -194,6 +283,36 @@ export function GeneralSettings({
                 />
               </SettingsSliderRow>
             )}
+
+            <SettingsRow
+              label="Auto-copy max size"
+              description="Downscale so the longest side fits"
+            >
+              <ToggleButtonGroup
+                options={maxDimensionOptions}
+                value={String(copySettings.autoCopyMaxDimension)}
+                onChange={(value) =>
+                  updateDraft({
+                    copySettings: { autoCopyMaxDimension: Number(value) },
+                  })
+                }
+              />
+            </SettingsRow>
+
+            <SettingsRow
+              label="Auto-copy file size limit"
+              description="Downscale and lower JPEG quality until the image fits"
+            >
+              <ToggleButtonGroup
+                options={maxBytesOptions}
+                value={String(copySettings.autoCopyMaxBytes)}
+                onChange={(value) =>
+                  updateDraft({
+                    copySettings: { autoCopyMaxBytes: Number(value) },
+                  })
+                }
+              />
+            </SettingsRow>
           </>
         )}
 
@@ -232,6 +351,263 @@ export function GeneralSettings({
             />
           </SettingsSliderRow>
         )}
+
+        <SettingsRow
+          label="Copy max size"
+          description="Downscale manual copies so the longest side fits"
+        >
+          <ToggleButtonGroup
+            options={maxDimensionOptions}
+            value={String(copySettings.manualCopyMaxDimension)}
+            onChange={(value) =>
+              updateDraft({
+                copySettings: { manualCopyMaxDimension: Number(value) },
+              })
+            }
+          />
+        </SettingsRow>
+
+        <SettingsRow
+          label="Copy file size limit"
+          description="Fit manual copies under a size, e.g. for chat apps"
+        >
+          <ToggleButtonGroup
+            options={maxBytesOptions}
+            value={String(copySettings.manualCopyMaxBytes)}
+            onChange={(value) =>
+              updateDraft({
+                copySettings: { manualCopyMaxBytes: Number(value) },
+              })
+            }
+          />
+        </SettingsRow>
+
+        {extraFormatRows.map(({ key, label, description }) => (
+          <SettingsRow key={key} label={label} description={description}>
+            <Switch
+              checked={copySettings.extraFormats[key]}
+              onCheckedChange={(checked) =>
+                updateDraft({
+                  copySettings: { extraFormats: { [key]: checked } },
+                })
+              }
+            />
+          </SettingsRow>
+        ))}
+
+        <SettingsSliderRow
+          label="Recent copies"
+          value={copySettings.historySize}
+        >
+          <Slider
+            value={[copySettings.historySize]}
+            onValueChange={([value]) =>
+              updateDraft({
+                copySettings: { historySize: value },
+              })
+            }
+            min={0}
+            max={30}
+            step={1}
+          />
+        </SettingsSliderRow>
+
+        <SettingsRow
+          label="Keep recent copies after restart"
+          description="Save recent copies to the cache folder; turning this off deletes them"
+        >
+          <Switch
+            checked={copySettings.saveHistoryToDisk}
+            onCheckedChange={(checked) =>
+              updateDraft({
+                copySettings: { saveHistoryToDisk: checked },
+              })
+            }
+          />
+        </SettingsRow>
+
+        <SettingsRow
+          label="Copy to selection"
+          description="Also or only fill the primary selection for middle-click paste (Linux)"
+        >
+          <ToggleButtonGroup
+            options={selectionOptions}
+            value={copySettings.clipboardSelection}
+            onChange={(value) =>
+              updateDraft({
+                copySettings: {
+                  clipboardSelection: value as ClipboardSelection,
+                },
+              })
+            }
+          />
+        </SettingsRow>
+
+        <SettingsRow
+          label="Keep clipboard after exit"
+          description="Keep the last copied image available after quitting (Linux)"
+        >
+          <Switch
+            checked={copySettings.keepClipboardAfterExit}
+            onCheckedChange={(checked) =>
+              updateDraft({
+                copySettings: { keepClipboardAfterExit: checked },
+              })
+            }
+          />
+        </SettingsRow>
+      </SettingsSection>
+
+      {/* ---------------------------------------------------------------------
+          EXPORT
+      ---------------------------------------------------------------------- */}
+      <SettingsSection
+        title="Export"
+        description="Encoder options used when saving images"
+        icon={<Save className="size-4" />}
+      >
+        <SettingsRow
+          label="Metadata"
+          description="Strip removes EXIF data such as GPS location and camera serials; Keep preserves EXIF and colour profiles"
+        >
+          <ToggleButtonGroup
+            options={metadataOptions}
+            value={exportSettings.metadata}
+            onChange={(value) =>
+              updateDraft({
+                exportSettings: { metadata: value as ExportMetadataMode },
+              })
+            }
+          />
+        </SettingsRow>
+
+        <SettingsSliderRow
+          label="PNG compression"
+          value={exportSettings.pngCompression}
+        >
+          <Slider
+            value={[exportSettings.pngCompression]}
+            onValueChange={([value]) =>
+              updateDraft({
+                exportSettings: { pngCompression: value },
+              })
+            }
+            min={0}
+            max={9}
+            step={1}
+          />
+        </SettingsSliderRow>
+
+        <SettingsRow
+          label="Reduce PNG palette"
+          description="Save images with 256 colours or fewer as smaller indexed PNGs"
+        >
+          <Switch
+            checked={exportSettings.pngPalette}
+            onCheckedChange={(checked) =>
+              updateDraft({
+                exportSettings: { pngPalette: checked },
+              })
+            }
+          />
+        </SettingsRow>
+
+        <SettingsSliderRow
+          label="JPEG quality"
+          value={Math.round(exportSettings.jpegQuality * 100)}
+          unit="%"
+        >
+          <Slider
+            value={[Math.round(exportSettings.jpegQuality * 100)]}
+            onValueChange={([value]) =>
+              updateDraft({
+                exportSettings: { jpegQuality: value / 100 },
+              })
+            }
+            min={30}
+            max={100}
+            step={5}
+          />
+        </SettingsSliderRow>
+
+        <SettingsRow
+          label="JPEG chroma subsampling"
+          description="4:4:4 keeps coloured text and thin lines sharp"
+        >
+          <ToggleButtonGroup
+            options={subsamplingOptions}
+            value={exportSettings.jpegSubsampling}
+            onChange={(value) =>
+              updateDraft({
+                exportSettings: {
+                  jpegSubsampling: value as ChromaSubsampling,
+                },
+              })
+            }
+          />
+        </SettingsRow>
+
+        <SettingsRow
+          label="Lossless WebP"
+          description="Larger files, but no compression artifacts"
+        >
+          <Switch
+            checked={exportSettings.webpLossless}
+            onCheckedChange={(checked) =>
+              updateDraft({
+                exportSettings: { webpLossless: checked },
+              })
+            }
+          />
+        </SettingsRow>
+
+        {!exportSettings.webpLossless && (
+          <SettingsSliderRow
+            label="WebP quality"
+            value={Math.round(exportSettings.webpQuality * 100)}
+            unit="%"
+          >
+            <Slider
+              value={[Math.round(exportSettings.webpQuality * 100)]}
+              onValueChange={([value]) =>
+                updateDraft({
+                  exportSettings: { webpQuality: value / 100 },
+                })
+              }
+              min={30}
+              max={100}
+              step={5}
+            />
+          </SettingsSliderRow>
+        )}
+
+        <SettingsRow
+          label="Embed sRGB profile"
+          description="Tags exports as sRGB so other apps show the same colours"
+        >
+          <Switch
+            checked={exportSettings.embedSrgb}
+            onCheckedChange={(checked) =>
+              updateDraft({
+                exportSettings: { embedSrgb: checked },
+              })
+            }
+          />
+        </SettingsRow>
+
+        <SettingsRow
+          label="Keep PNGs editable"
+          description="Embeds the original image and annotations in exported PNGs, so reopening them restores editable strokes"
+        >
+          <Switch
+            checked={exportSettings.embedProject}
+            onCheckedChange={(checked) =>
+              updateDraft({
+                exportSettings: { embedProject: checked },
+              })
+            }
+          />
+        </SettingsRow>
       </SettingsSection>
 
       {/* ---------------------------------------------------------------------
@@ -57,9 +57,11 @@ export function TabBar() {
     try {
       const result = await services.ioService.openFile();
       if (result && targetDoc) {
-        const blob = new Blob([result.fileData]);
-        const url = URL.createObjectURL(blob);
-        targetDoc.loadImage(result.filePath, url);
+        const { imageSrc, project } = await services.ioService.loadFile(
+          result.filePath,
+          result.fileData,
+        );
+        targetDoc.loadImage(result.filePath, imageSrc, undefined, project);
       }
     } catch {
       toast.error("Failed to open file");
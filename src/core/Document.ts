@@ -1,4 +1,4 @@
-import type { DocumentState, Point, Size } from "~/types";
+import type { DocumentState, Point, ProjectState, Size } from "~/types";
 import { Ruler } from "./Ruler";
 import { StrokeHistory } from "./StrokeHistory";
 
@@ -62,12 +62,14 @@ export class Document {
   }
 
   /**
-   * Load an image into the document
+   * Load an image into the document, restoring annotations and view from
+   * `project` when opening a project file
    */
   loadImage(
     filePath: string | null,
     imageSrc: string,
     fileName?: string,
+    project?: ProjectState,
   ): void {
     this.filePath = filePath;
     this.imageSrc = imageSrc;
@@ -90,9 +92,41 @@ export class Document {
     // Clear stroke history for new image
     this.strokeHistory.clear();
 
+    if (project) {
+      this.strokeHistory.groups = project.strokeHistory.groups;
+      this.strokeHistory.currentIndex = project.strokeHistory.currentIndex;
+      this.zoom = project.view.zoom;
+      this.viewOffset = project.view.viewOffset;
+      this.ruler.visible = project.view.ruler.visible;
+      this.ruler.x = project.view.ruler.x;
+      this.ruler.y = project.view.ruler.y;
+      this.ruler.angle = project.view.ruler.angle;
+      // Keep the saved view instead of fitting the image
+      this.hasAppliedInitialFit = true;
+    }
+
     this.notifyChange();
   }
 
+  /**
+   * The state to save in a project file
+   */
+  toProjectState(): ProjectState {
+    return {
+      strokeHistory: this.strokeHistory.serialize(),
+      view: {
+        zoom: this.zoom,
+        viewOffset: this.viewOffset,
+        ruler: {
+          visible: this.ruler.visible,
+          x: this.ruler.x,
+          y: this.ruler.y,
+          angle: this.ruler.angle,
+        },
+      },
+    };
+  }
+
   /**
    * Clear the document (remove image and reset state)
    */
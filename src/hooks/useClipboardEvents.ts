@@ -9,24 +9,62 @@ import { listen, type UnlistenFn } from "@tauri-apps/api/event";
 import { useEffect } from "react";
 import { toast } from "sonner";
 import { settingsManager } from "~/services";
+import type { AppError } from "~/types";
+import type { ClipboardBackend } from "~/types/settings";
+import { describeError } from "~/utils/errors";
 
 // -----------------------------------------------------------------------------
 // Types & Interfaces
 // -----------------------------------------------------------------------------
 
-// -----------------------------------------------------------------------------
-// Types & Interfaces
-// -----------------------------------------------------------------------------
+/**
+ * X11/Wayland selection a copy was written to.
+ */
+type Selection = "clipboard" | "primary";
+
+/**
+ * A clipboard backend that was tried and failed during a copy.
+ */
+type BackendFailure = {
+  backend: ClipboardBackend;
+  selection: Selection;
+  error: AppError;
+};
+
+/**
+ * The image that went on the clipboard, after any size budget was applied.
+ */
+type CopiedImage = {
+  width: number;
+  height: number;
+  /** Encoded size, when the backend used our encoded bytes */
+  bytes: number | null;
+  scaled: boolean;
+};
 
 /**
  * Payload received from the backend `clipboard-copy-result` event.
  */
 type ClipboardCopyResultPayload = {
   success: boolean;
-  error: string | null;
+  error: AppError | null;
   version: number;
+  superseded: boolean;
+  backend: ClipboardBackend | null;
+  /** MIME types the backend published */
+  formats: string[];
+  /** Selections written; may be only one of them when both were requested */
+  selections: Selection[];
+  image: CopiedImage | null;
+  failures: BackendFailure[];
 };
 
+function describeCopiedImage(image: CopiedImage): string {
+  const size = `${image.width}×${image.height}`;
+  if (image.bytes === null) return `Scaled to ${size}`;
+  return `Scaled to ${size}, ${Math.ceil(image.bytes / 1024)} KB`;
+}
+
 // -----------------------------------------------------------------------------
 // Module State
 // -----------------------------------------------------------------------------
@@ -90,7 +128,11 @@ async function setupGlobalListener(): Promise<void> {
     globalUnlisten = await listen<ClipboardCopyResultPayload>(
       "clipboard-copy-result",
       (event) => {
-        const { success, error } = event.payload;
+        const { success, error, superseded, failures, image } =
+          event.payload;
+
+        // A newer copy replaced this one; its own result will follow
+        if (superseded) return;
 
         // Validate if the result correlates to a recent manual action
         const isRecentManualCopy =
@@ -108,12 +150,18 @@ async function setupGlobalListener(): Promise<void> {
         if (success) {
           // Toast policy: Always show for manual actions, conditionally for auto-copy
           if (isRecentManualCopy || shouldShowToastForAutoCopy) {
-            toast.success("Copied to clipboard", { duration: 2000 });
+            toast.success("Copied to clipboard", {
+              description: image?.scaled
+                ? describeCopiedImage(image)
+                : undefined,
+              duration: 2000,
+            });
           }
         } else {
           // Error policy: Always notify on failure
+          console.error("Clipboard backends failed:", failures);
           toast.error("Copy failed", {
-            description: error || "Unknown error",
+            description: error ? describeError(error) : "Unknown error",
             duration: 5000,
           });
         }
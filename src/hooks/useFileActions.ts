@@ -4,13 +4,13 @@
  * user feedback and canvas integration for image annotation workflows.
  */
 
-import { readImage } from "@tauri-apps/plugin-clipboard-manager";
 import { useCallback, useEffect, useMemo, useRef } from "react";
 import { toast } from "sonner";
 import { useCanvasEngine } from "~/contexts/CanvasEngineContext";
 import { services } from "~/services";
+import { describeError } from "~/utils/errors";
+import { isProjectFile } from "~/utils/file";
 import { registerPendingCopy } from "./useClipboardEvents";
-import { convertClipboardImageToDataUrl } from "~/utils/imageProcessing";
 
 export function useFileActions() {
   const { engine } = useCanvasEngine();
@@ -24,9 +24,23 @@ export function useFileActions() {
   const handleOpen = useCallback(async () => {
     const result = await services.ioService.openFile();
     if (result) {
-      const blob = new Blob([result.fileData]);
-      const url = URL.createObjectURL(blob);
-      services.tabManager.createDocument(result.filePath, undefined, url);
+      try {
+        const { imageSrc, project } = await services.ioService.loadFile(
+          result.filePath,
+          result.fileData,
+        );
+        services.tabManager.createDocument(
+          result.filePath,
+          undefined,
+          imageSrc,
+          project,
+        );
+      } catch (error) {
+        console.error("Failed to open file:", result.filePath, error);
+        toast.error(`Could not open file: ${result.filePath}`, {
+          description: describeError(error),
+        });
+      }
     }
   }, []);
 
@@ -45,12 +59,53 @@ export function useFileActions() {
       return false;
     }
 
+    // Piped images go back to `ursamarkup --pipe` instead of to a file
+    if (services.ioService.getPipeSession(activeDoc.id)) {
+      try {
+        await services.ioService.finishPipeSession(activeDoc.id, canvas);
+      } catch (error) {
+        console.error("Failed to finish pipe session:", error);
+        toast.error("Failed to send image", {
+          description: describeError(error),
+        });
+        return false;
+      }
+      activeDoc.markAsChanged(false);
+      toast.success("Image sent", { duration: 2000 });
+      services.tabManager.closeDocument(activeDoc.id);
+      return true;
+    }
+
     const defaultPath = activeDoc.filePath || "annotated-image.png";
-    const savedFilePath = await services.ioService.saveImage(canvas, defaultPath);
+    let savedFilePath: string | null;
+    try {
+      savedFilePath = await services.ioService.saveImage(
+        canvas,
+        services.settingsManager.settings.exportSettings,
+        defaultPath,
+        activeDoc.filePath ?? undefined,
+        activeDoc.imageSrc
+          ? { imageSrc: activeDoc.imageSrc, state: activeDoc.toProjectState() }
+          : undefined,
+      );
+    } catch (error) {
+      console.error("Failed to save image:", error);
+      toast.error("Failed to save file", {
+        description: describeError(error),
+      });
+      return false;
+    }
 
     if (savedFilePath) {
-      // Update file info for unnamed documents (clipboard pastes)
-      if (activeDoc.fileName === "Pasted Image") {
+      services.ioService.recordSave(activeDoc.id, savedFilePath);
+      // A saved project is what the document reopens as from now on
+      if (isProjectFile(savedFilePath)) {
+        activeDoc.setFileInfo(
+          savedFilePath,
+          savedFilePath.split("/").pop() || undefined,
+        );
+      } else if (activeDoc.fileName === "Pasted Image") {
+        // Update file info for unnamed documents (clipboard pastes)
         // Extract filename without extension
         const fileNameWithExt = savedFilePath.split("/").pop() || "";
         const lastDot = fileNameWithExt.lastIndexOf(".");
@@ -62,10 +117,9 @@ export function useFileActions() {
       activeDoc.markAsChanged(false);
       toast.success("File saved successfully", { duration: 2000 });
       return true;
-    } else {
-      toast.error("Failed to save file");
-      return false;
     }
+    // The save dialog was cancelled
+    return false;
   }, []);
 
   const handleCopy = useCallback(async () => {
@@ -83,25 +137,54 @@ export function useFileActions() {
       await services.ioService.copyToClipboard(canvas, version, {
         force: true,
         isAutoCopy: false,
+        documentId: activeDoc.id,
+        documentName: activeDoc.fileName ?? undefined,
         format: copySettings.manualCopyFormat,
         jpegQuality: copySettings.manualCopyJpegQuality,
+        maxDimension: copySettings.manualCopyMaxDimension,
+        maxBytes: copySettings.manualCopyMaxBytes,
       });
     }
   }, []);
 
   const handlePaste = useCallback(async () => {
     try {
-      const image = await readImage();
-      const rgbaBuffer = await image.rgba();
-      const { width, height } = await image.size();
-
-      const dataUrl = convertClipboardImageToDataUrl(rgbaBuffer, width, height);
+      const content = await services.ioService.readClipboard();
+
+      if (content.kind === "image") {
+        services.tabManager.createDocument(
+          undefined,
+          "Pasted Image",
+          content.imageSrc,
+        );
+        return;
+      }
 
-      // Create document with the data URL
-      services.tabManager.createDocument(undefined, "Pasted Image", dataUrl);
+      // Files copied from a file manager open as tabs
+      for (const filePath of content.paths) {
+        try {
+          const fileData = await services.ioService.readFile(filePath);
+          const { imageSrc, project } = await services.ioService.loadFile(
+            filePath,
+            fileData,
+          );
+          services.tabManager.createDocument(
+            filePath,
+            undefined,
+            imageSrc,
+            project,
+          );
+        } catch (error) {
+          console.error("Failed to open pasted file:", filePath, error);
+          toast.error(`Could not open file: ${filePath}`);
+        }
+      }
     } catch (error) {
       console.error("Failed to paste image from clipboard:", error);
-      toast.error("Failed to paste image from clipboard", { duration: 2000 });
+      toast.error("Failed to paste image from clipboard", {
+        description: describeError(error),
+        duration: 2000,
+      });
     }
   }, []);
 
@@ -2,27 +2,117 @@ import { useEffect } from "react";
 import { toast } from "sonner";
 import { invoke } from "@tauri-apps/api/core";
 import { services } from "~/services";
-import { isImageFile } from "~/utils/file";
+import type { OpenFilesPayload } from "~/services/IOService";
+import { describeRejection } from "~/utils/errors";
+
+/** Rejected files named in the error toast; the rest are only counted */
+const MAX_LISTED_REJECTIONS = 5;
 
 /**
  * Hook to handle file operations (CLI files, single-instance file listening)
  */
 export function useFileHandling(): void {
   useEffect(() => {
-    const openFilesFromCLI = async (filePaths: string[]) => {
-      const hasImages = filePaths.some(isImageFile);
-
+    /** Open each file in a tab, returning the ids of those that opened */
+    const openFiles = async (filePaths: string[]) => {
+      const documentIds: string[] = [];
       for (const filePath of filePaths) {
         try {
           const fileData = await services.ioService.readFile(filePath);
-          const blob = new Blob([fileData]);
-          const url = URL.createObjectURL(blob);
-          services.tabManager.createDocument(filePath, undefined, url);
+          const { imageSrc, project } = await services.ioService.loadFile(
+            filePath,
+            fileData,
+          );
+          const id = services.tabManager.createDocument(
+            filePath,
+            undefined,
+            imageSrc,
+            project,
+          );
+          documentIds.push(id);
         } catch (error) {
           console.error("Failed to open CLI file:", filePath, error);
           toast.error(`Could not open file: ${filePath}`);
         }
       }
+      return documentIds;
+    };
+
+    const openFilesFromCLI = async ({
+      accepted,
+      rejected,
+      flags,
+    }: OpenFilesPayload) => {
+      if (rejected.length > 0) {
+        console.error("Rejected CLI arguments:", rejected);
+      }
+      if (rejected.length === 1) {
+        const [{ argument, reason }] = rejected;
+        toast.error(`Could not open ${argument}`, {
+          description: describeRejection(reason),
+        });
+      } else if (rejected.length > 1) {
+        // One message for all of them rather than a toast per file
+        const listed = rejected
+          .slice(0, MAX_LISTED_REJECTIONS)
+          .map(
+            ({ argument, reason }) =>
+              `${argument}: ${describeRejection(reason)}`,
+          );
+        if (rejected.length > MAX_LISTED_REJECTIONS) {
+          listed.push(`and ${rejected.length - MAX_LISTED_REJECTIONS} more`);
+        }
+        toast.error(`Could not open ${rejected.length} files`, {
+          description: listed.join("\n"),
+        });
+      }
+
+      const filePaths = accepted.map((file) => file.path);
+
+      // Accepted files were recognised by content, whatever their extension
+      const hasImages = flags.pipe_session !== null || filePaths.length > 0;
+
+      if (flags.pipe_session) {
+        const session = flags.pipe_session;
+        try {
+          const imageSrc = await services.ioService.openPipeSession(session);
+          const id = services.tabManager.createDocument(
+            undefined,
+            "Piped Image",
+            imageSrc,
+          );
+          services.ioService.trackPipeSession(id, session);
+        } catch (error) {
+          console.error("Failed to open piped image:", error);
+          toast.error("Could not open piped image");
+        }
+      }
+
+      // A script waiting on the files gets all of them
+      const limit = flags.wait_session
+        ? Infinity
+        : services.settingsManager.settings.miscSettings.cliTabLimit;
+      const documentIds = await openFiles(filePaths.slice(0, limit));
+
+      const rest = filePaths.slice(limit);
+      if (rest.length > 0) {
+        toast.warning(`Opened ${limit} of ${filePaths.length} images`, {
+          description: "The tab limit can be changed in Settings → General",
+          duration: Infinity,
+          action: {
+            label: `Open ${rest.length} more`,
+            onClick: () => openFiles(rest),
+          },
+        });
+      }
+
+      if (flags.wait_session) {
+        services.ioService
+          .trackWaitSession(flags.wait_session, documentIds)
+          .catch((error) => {
+            console.error("Failed to finish wait session:", error);
+          });
+      }
 
       // Restore from tray if images were received via CLI and setting is enabled
       if (hasImages && services.settingsManager.settings.miscSettings.restoreFromTrayOnCliImage) {
@@ -34,23 +124,35 @@ export function useFileHandling(): void {
       }
     };
 
+    // Open payloads one after another so tabs keep the order they came in
+    let opening = Promise.resolve();
     const setupListener = async () => {
-      return await services.ioService.listenForFiles((filePaths) => {
-        openFilesFromCLI(filePaths);
+      return await services.ioService.listenForFiles((payload) => {
+        opening = opening
+          .then(() => openFilesFromCLI(payload))
+          .catch((error) => {
+            console.error("Failed to open CLI files:", error);
+          });
       });
     };
 
-    const checkPendingFiles = async () => {
-      const pendingFiles = await services.ioService.getPendingFiles();
-      if (pendingFiles.length > 0) {
-        await openFilesFromCLI(pendingFiles);
-      }
-    };
+    // Answer a waiting `ursamarkup --pipe` or `--wait` once its tabs are gone
+    const unsubscribeClosed = services.tabManager.on(
+      "documentClosed",
+      ({ id, discarded }) => {
+        services.ioService.closePipeSession(id, discarded).catch((error) => {
+          console.error("Failed to close pipe session:", error);
+        });
+        services.ioService.closeWaitDocument(id, discarded).catch((error) => {
+          console.error("Failed to finish wait session:", error);
+        });
+      },
+    );
 
     const unlistenPromise = setupListener();
-    checkPendingFiles();
 
     return () => {
+      unsubscribeClosed();
       unlistenPromise.then((unlisten) => unlisten());
     };
   }, []);
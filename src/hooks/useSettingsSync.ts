@@ -40,6 +40,15 @@ export function useSettingsSync(
     };
   }, [setSettings]);
 
+  // Keep the backend clipboard configuration in sync
+  useEffect(() => {
+    services.ioService
+      .configureClipboard(settings.copySettings)
+      .catch((error) =>
+        console.error("Failed to configure clipboard backends:", error),
+      );
+  }, [settings.copySettings]);
+
   // Apply theme when theme changes
   useEffect(() => {
     services.themeManager.setTheme(settings.activeTheme);
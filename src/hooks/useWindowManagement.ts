@@ -1,5 +1,4 @@
 import { invoke } from "@tauri-apps/api/core";
-import { listen } from "@tauri-apps/api/event";
 import { getCurrentWindow } from "@tauri-apps/api/window";
 import { useEffect } from "react";
 import { services } from "~/services";
@@ -34,14 +33,23 @@ export function useWindowManagement(settings: AppSettings): void {
   // Listen for tray right-click to open file dialog
   useEffect(() => {
     const setupListener = async () => {
-      const unlisten = await listen("tray-open-file", () => {
+      const unlisten = await services.ioService.listenForTrayOpenFile(() => {
         services.ioService.openFile().then(async (result) => {
           if (result) {
             await invoke("restore_from_tray");
-            const blob = new Blob([result.fileData]);
-            const url = URL.createObjectURL(blob);
-            services.tabManager.createDocument(result.filePath, undefined, url);
+            const { imageSrc, project } = await services.ioService.loadFile(
+              result.filePath,
+              result.fileData,
+            );
+            services.tabManager.createDocument(
+              result.filePath,
+              undefined,
+              imageSrc,
+              project,
+            );
           }
+        }).catch((error) => {
+          console.error("Failed to open file from tray:", error);
         });
       });
       return unlisten;
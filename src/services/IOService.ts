@@ -1,10 +1,16 @@
 import { invoke } from "@tauri-apps/api/core";
 import { listen, type UnlistenFn } from "@tauri-apps/api/event";
 import { open, save } from "@tauri-apps/plugin-dialog";
-import { readFile, writeFile } from "@tauri-apps/plugin-fs";
-
-// Import the worker
-import CopyWorker from "~/workers/copyWorker?worker";
+import { readFile } from "@tauri-apps/plugin-fs";
+import type { AppError, ProjectState } from "~/types";
+import type { CopySettings, ExportSettings } from "~/types/settings";
+import {
+  isPng,
+  isProjectFile,
+  needsBackendDecode,
+  OPENABLE_IMAGE_EXTENSIONS,
+  PROJECT_EXTENSION,
+} from "~/utils/file";
 
 /**
  * Options for clipboard copy operation
@@ -14,10 +20,18 @@ export type CopyOptions = {
   force?: boolean;
   /** Whether this is an auto-copy (affects toast behavior) */
   isAutoCopy?: boolean;
-  /** Image format for clipboard copy */
+  /** Document the version belongs to; newer versions supersede older ones */
+  documentId?: string;
+  /** Shown in the clipboard history */
+  documentName?: string;
+  /** Encoding used when the clipboard backend needs encoded bytes */
   format?: "png" | "jpeg";
   /** JPEG quality (0.0 - 1.0), only used when format is "jpeg" */
   jpegQuality?: number;
+  /** Downscale so the longest side fits, in pixels (0 = no limit) */
+  maxDimension?: number;
+  /** Downscale and lower JPEG quality until the encoded image fits (0 = no limit) */
+  maxBytes?: number;
 };
 
 /**
@@ -30,6 +44,159 @@ export type CopyResult = {
   version: number;
 };
 
+/**
+ * Content read from the clipboard by the Rust backend
+ */
+export type ClipboardContent =
+  | {
+      kind: "image";
+      width: number;
+      height: number;
+      mime_type: string;
+      /** Object URL of the encoded image bytes */
+      imageSrc: string;
+    }
+  | { kind: "files"; paths: string[] };
+
+/**
+ * A recent copy kept by the Rust backend for re-copying
+ */
+export type ClipboardHistoryEntry = {
+  id: string;
+  document_id: string;
+  version: number;
+  /** Milliseconds since the Unix epoch */
+  timestamp: number;
+  width: number;
+  height: number;
+  label: string;
+  /** Base64 PNG thumbnail */
+  thumbnail: string;
+  jpeg_quality: number | null;
+};
+
+/**
+ * Where an export was written, as reported by the Rust backend
+ */
+export type ExportResult = {
+  path: string;
+  /** Size of the written file */
+  bytes: number;
+  /** Copy of the overwritten source file, if one was made */
+  backup: string | null;
+};
+
+/**
+ * A file opened as a document: the URL the canvas loads, plus the saved
+ * annotations and view when the file is a project
+ */
+export type LoadedFile = {
+  imageSrc: string;
+  project?: ProjectState;
+};
+
+/**
+ * What a document needs to be saved as a `.ursa` project
+ */
+export type ProjectSnapshot = {
+  /** URL of the image the annotations are drawn on */
+  imageSrc: string;
+  state: ProjectState;
+};
+
+/**
+ * Metadata of an image decoded by the Rust backend
+ */
+export type DecodedImageInfo = {
+  format: string;
+  width: number;
+  height: number;
+  /** Bits per channel in the original file */
+  bit_depth: number;
+  has_alpha: boolean;
+  /** EXIF orientation that was applied (1 = none) */
+  orientation: number;
+  /** Embedded ICC profile the pixels were converted to sRGB from */
+  color_profile: string | null;
+  /** Frames in an animated GIF or WebP; only the first is decoded */
+  frame_count: number;
+  /** A PNG export with its editable project embedded */
+  has_project: boolean;
+};
+
+/**
+ * Options given on the command line alongside the files to open
+ */
+export type LaunchFlags = {
+  /** Session of an image piped in with `ursamarkup --pipe` */
+  pipe_session: string | null;
+  /** Session of `ursamarkup --wait`, waiting for the files' tabs to close */
+  wait_session: string | null;
+};
+
+/**
+ * Tabs an `ursamarkup --wait` is waiting on
+ */
+type WaitSession = {
+  documentIds: Set<string>;
+  /** Paths the tabs were saved to */
+  saved: string[];
+  /** Whether unsaved changes were thrown away in any tab */
+  discarded: boolean;
+};
+
+/**
+ * A command line file that exists and looks like an image or project
+ */
+export type AcceptedFile = {
+  /** Absolute path */
+  path: string;
+  /** Lowercase image format, or "ursa" for projects */
+  format: string;
+  /** Dimensions after EXIF orientation; of the image inside for projects */
+  width: number;
+  height: number;
+};
+
+/**
+ * Why a command line argument cannot be opened
+ */
+export type RejectReason =
+  | { kind: "InvalidArgument"; message: string }
+  | { kind: "NotFound" }
+  | { kind: "Unreadable"; message: string }
+  | { kind: "NoImages"; recursive: boolean }
+  | { kind: "TooManyImages"; opened: number }
+  | { kind: "NotAnImage" }
+  | { kind: "TooLarge"; size: number; limit: number }
+  | { kind: "Corrupt"; message: string };
+
+export type RejectedFile = {
+  /** The argument as given, or the file's path if it came from a directory */
+  argument: string;
+  reason: RejectReason;
+};
+
+/**
+ * Files from the command line, of this launch or forwarded from a second
+ * instance
+ */
+export type OpenFilesPayload = {
+  /** Files that passed validation, in the order they were given */
+  accepted: AcceptedFile[];
+  rejected: RejectedFile[];
+  /** Working directory of the launching instance */
+  cwd: string | null;
+  flags: LaunchFlags;
+};
+
+/**
+ * Backend events held back until the frontend calls `frontend_ready`, so
+ * nothing sent while the page is loading is lost
+ */
+const QUEUED_EVENTS = ["open-files", "tray-open-file"] as const;
+type QueuedEvent = (typeof QUEUED_EVENTS)[number];
+
 /**
  * IOService handles all file and clipboard operations
  * Provides a clean interface for file I/O and clipboard access
@@ -37,19 +204,14 @@ export type CopyResult = {
 export class IOService {
   /** Track the last version that was successfully queued for copy */
   private lastCopiedVersion: number = -1;
-
-  /** Web Worker for PNG encoding */
-  private copyWorker: Worker | null = null;
-
-  /**
-   * Get or create the copy worker (lazy initialization)
-   */
-  private getCopyWorker(): Worker {
-    if (!this.copyWorker) {
-      this.copyWorker = new CopyWorker();
-    }
-    return this.copyWorker;
-  }
+  /** Pipe session of each document opened from `ursamarkup --pipe` */
+  private pipeSessions = new Map<string, string>();
+  /** `ursamarkup --wait` sessions by id */
+  private waitSessions = new Map<string, WaitSession>();
+  /** Attached listeners per queued backend event */
+  private queuedListeners = new Map<QueuedEvent, number>();
+  /** Whether `frontend_ready` was sent for this page load */
+  private readySent = false;
 
   /**
    * Open a file dialog and read the selected file
@@ -60,8 +222,9 @@ export class IOService {
         filters: [
           {
             name: "Images",
-            extensions: ["png", "jpg", "jpeg", "webp", "bmp", "gif"],
+            extensions: OPENABLE_IMAGE_EXTENSIONS,
           },
+          { name: "Ursa Markup Projects", extensions: [PROJECT_EXTENSION] },
           { name: "All Files", extensions: ["*"] },
         ],
         multiple: false,
@@ -88,55 +251,241 @@ export class IOService {
     return readFile(filePath);
   }
 
+  /**
+   * Decode an image file to RGBA in the Rust backend
+   *
+   * The response is a little-endian u32 length, that many bytes of
+   * metadata JSON, then the RGBA pixels.
+   */
+  async decodeImage(
+    filePath: string,
+  ): Promise<{ info: DecodedImageInfo; pixels: ImageData }> {
+    const buffer = await invoke<ArrayBuffer>("decode_image", {
+      path: filePath,
+    });
+    return this.parseDecoded(buffer);
+  }
+
+  private parseDecoded(buffer: ArrayBuffer): {
+    info: DecodedImageInfo;
+    pixels: ImageData;
+  } {
+    const infoLength = new DataView(buffer).getUint32(0, true);
+    const info: DecodedImageInfo = JSON.parse(
+      new TextDecoder().decode(new Uint8Array(buffer, 4, infoLength)),
+    );
+    const pixels = new ImageData(
+      new Uint8ClampedArray(buffer, 4 + infoLength),
+      info.width,
+      info.height,
+    );
+    return { info, pixels };
+  }
+
+  /**
+   * Get a URL the canvas can load for an image file
+   *
+   * Formats the webview cannot display, rotated photos and images with an
+   * ICC profile are decoded in Rust (converting colours to sRGB) and
+   * re-encoded as PNG; everything else is served from the file bytes as-is.
+   */
+  async createImageUrl(
+    filePath: string,
+    fileData: Uint8Array,
+  ): Promise<string> {
+    if (!needsBackendDecode(filePath, fileData)) {
+      return URL.createObjectURL(new Blob([fileData]));
+    }
+
+    const { pixels } = await this.decodeImage(filePath);
+    return this.createPixelsUrl(pixels);
+  }
+
+  /**
+   * Get a URL the canvas can load for decoded pixels, encoded as PNG
+   */
+  private async createPixelsUrl(pixels: ImageData): Promise<string> {
+    const canvas = document.createElement("canvas");
+    canvas.width = pixels.width;
+    canvas.height = pixels.height;
+    const ctx = canvas.getContext("2d");
+    if (!ctx) throw new Error("Failed to get canvas context");
+    ctx.putImageData(pixels, 0, 0);
+
+    const blob = await new Promise<Blob | null>((resolve) => {
+      canvas.toBlob(resolve, "image/png");
+    });
+    if (!blob) throw new Error("Failed to create blob from canvas");
+    return URL.createObjectURL(blob);
+  }
+
+  /**
+   * Load a file for a document: images as a canvas URL, projects and PNGs
+   * exported with an embedded project with their annotations and view
+   */
+  async loadFile(filePath: string, fileData: Uint8Array): Promise<LoadedFile> {
+    if (isProjectFile(filePath)) {
+      return this.loadProject(filePath);
+    }
+    if (!isPng(fileData)) {
+      return { imageSrc: await this.createImageUrl(filePath, fileData) };
+    }
+
+    // The backend reports whether the PNG was exported with its project
+    const { info, pixels } = await this.decodeImage(filePath);
+    if (info.has_project) {
+      return this.loadProject(filePath);
+    }
+    return { imageSrc: await this.createPixelsUrl(pixels) };
+  }
+
+  /**
+   * Open a `.ursa` project, or the project embedded in an exported PNG, in
+   * the Rust backend, which migrates older schema versions
+   *
+   * The response is framed like decode_image's: a little-endian u32 length,
+   * that many bytes of state JSON, then the encoded image.
+   */
+  async loadProject(filePath: string): Promise<Required<LoadedFile>> {
+    const buffer = await invoke<ArrayBuffer>("load_project", {
+      path: filePath,
+    });
+    const stateLength = new DataView(buffer).getUint32(0, true);
+    const state: {
+      history: ProjectState["strokeHistory"];
+      view: ProjectState["view"];
+    } = JSON.parse(
+      new TextDecoder().decode(new Uint8Array(buffer, 4, stateLength)),
+    );
+    const image = new Blob([new Uint8Array(buffer, 4 + stateLength)]);
+    return {
+      imageSrc: URL.createObjectURL(image),
+      project: { strokeHistory: state.history, view: state.view },
+    };
+  }
+
+  /**
+   * Write a document's image and annotations to a `.ursa` project
+   */
+  async saveProject(
+    filePath: string,
+    project: ProjectSnapshot,
+  ): Promise<ExportResult> {
+    return invoke<ExportResult>(
+      "save_project",
+      await this.encodeProject(project),
+      { headers: { "x-path": encodeURIComponent(filePath) } },
+    );
+  }
+
+  /**
+   * Frame a project for the backend: a little-endian u32 length, that many
+   * bytes of state JSON, then the encoded image
+   */
+  private async encodeProject(project: ProjectSnapshot): Promise<Uint8Array> {
+    const image = new Uint8Array(
+      await (await fetch(project.imageSrc)).arrayBuffer(),
+    );
+    const state = new TextEncoder().encode(
+      JSON.stringify({
+        history: project.state.strokeHistory,
+        view: project.state.view,
+      }),
+    );
+    const body = new Uint8Array(4 + state.length + image.length);
+    new DataView(body.buffer).setUint32(0, state.length, true);
+    body.set(state, 4);
+    body.set(image, 4 + state.length);
+    return body;
+  }
+
   /**
    * Save an image canvas to a file
+   *
+   * Encoding and the write itself happen in Rust: the file is written to a
+   * temp file and renamed into place, and overwriting `sourcePath` keeps a
+   * `.bak` copy of the original. Metadata of `sourcePath` is kept or
+   * stripped according to `exportSettings.metadata`.
+   *
+   * Choosing a `.ursa` path saves `project` instead, keeping the
+   * annotations editable. With `exportSettings.embedProject`, PNG exports
+   * carry the project too.
+   *
+   * @returns The saved path, or null if the user cancelled the dialog
    */
   async saveImage(
     canvas: HTMLCanvasElement,
+    exportSettings: ExportSettings,
     defaultPath?: string,
+    sourcePath?: string,
+    project?: ProjectSnapshot,
   ): Promise<string | null> {
-    try {
-      const filePath = await save({
-        filters: [
-          { name: "PNG Image", extensions: ["png"] },
-          { name: "JPEG Image", extensions: ["jpg", "jpeg"] },
-          { name: "WebP Image", extensions: ["webp"] },
-        ],
-        defaultPath: defaultPath || "annotated-image.png",
-      });
-
-      if (!filePath) return null;
-
-      const mimeType =
-        filePath.endsWith(".jpg") || filePath.endsWith(".jpeg")
-          ? "image/jpeg"
-          : filePath.endsWith(".webp")
-            ? "image/webp"
-            : "image/png";
-
-      const blob = await new Promise<Blob | null>((resolve) => {
-        canvas.toBlob(resolve, mimeType, 0.95);
-      });
+    const filePath = await save({
+      filters: [
+        { name: "PNG Image", extensions: ["png"] },
+        { name: "JPEG Image", extensions: ["jpg", "jpeg"] },
+        { name: "WebP Image", extensions: ["webp"] },
+        ...(project
+          ? [{ name: "Ursa Markup Project", extensions: [PROJECT_EXTENSION] }]
+          : []),
+      ],
+      defaultPath: defaultPath || "annotated-image.png",
+    });
 
-      if (!blob) {
-        console.error("Failed to create blob from canvas");
-        return null;
-      }
+    if (!filePath) return null;
 
-      const arrayBuffer = await blob.arrayBuffer();
-      const uint8Array = new Uint8Array(arrayBuffer);
+    if (project && isProjectFile(filePath)) {
+      const result = await this.saveProject(filePath, project);
+      return result.path;
+    }
 
-      await writeFile(filePath, uint8Array);
-      return filePath;
-    } catch (error) {
-      console.error("Failed to save image:", error);
-      return null;
+    const lowerPath = filePath.toLowerCase();
+    const format =
+      lowerPath.endsWith(".jpg") || lowerPath.endsWith(".jpeg")
+        ? "jpeg"
+        : lowerPath.endsWith(".webp")
+          ? "webp"
+          : "png";
+
+    const ctx = canvas.getContext("2d");
+    if (!ctx) throw new Error("Failed to get canvas context");
+    const imageData = ctx.getImageData(0, 0, canvas.width, canvas.height);
+    const pixels = new Uint8Array(imageData.data.buffer);
+
+    // The project follows the pixels in the same body
+    let body = pixels;
+    if (format === "png" && exportSettings.embedProject && project) {
+      const encoded = await this.encodeProject(project);
+      body = new Uint8Array(pixels.length + encoded.length);
+      body.set(pixels);
+      body.set(encoded, pixels.length);
     }
+
+    const result = await invoke<ExportResult>("export_image", body, {
+      headers: {
+        "x-width": String(imageData.width),
+        "x-height": String(imageData.height),
+        "x-path": encodeURIComponent(filePath),
+        "x-source-path": encodeURIComponent(sourcePath ?? ""),
+        "x-format": format,
+        "x-png-compression": String(exportSettings.pngCompression),
+        "x-png-palette": String(exportSettings.pngPalette),
+        "x-jpeg-quality": String(Math.round(exportSettings.jpegQuality * 100)),
+        "x-jpeg-subsampling": exportSettings.jpegSubsampling,
+        "x-webp-lossless": String(exportSettings.webpLossless),
+        "x-webp-quality": String(Math.round(exportSettings.webpQuality * 100)),
+        "x-metadata": exportSettings.metadata,
+        "x-embed-srgb": String(exportSettings.embedSrgb),
+      },
+    });
+    return result.path;
   }
 
   /**
-   * Copy canvas image to clipboard using Web Worker + Rust backend
+   * Copy canvas image to clipboard via the Rust backend
    *
+   * Sends the raw RGBA pixels as a binary IPC body; any encoding the
+   * clipboard backend needs happens at most once, in Rust.
    *
    * @param canvas The canvas to copy
    * @param version The document version (for deduplication)
@@ -154,27 +503,31 @@ export class IOService {
     }
 
     try {
-      let base64Data: string;
-      const format = options?.format ?? "png";
+      const ctx = canvas.getContext("2d");
+      if (!ctx) throw new Error("Failed to get canvas context");
 
-      if (format === "jpeg") {
-        // JPEG path: Fast encoding (hardware accelerated)
-        const quality = options?.jpegQuality ?? 0.85;
-        base64Data = await this.encodeAsJpeg(canvas, quality);
-      } else {
-        // PNG path: High quality via worker (slower)
-        const ctx = canvas.getContext("2d");
-        if (!ctx) throw new Error("Failed to get canvas context");
-
-        const imageData = ctx.getImageData(0, 0, canvas.width, canvas.height);
-        base64Data = await this.encodeInWorker(imageData);
-      }
+      const imageData = ctx.getImageData(0, 0, canvas.width, canvas.height);
+      const format = options?.format ?? "png";
+      const jpegQuality = Math.round((options?.jpegQuality ?? 0.85) * 100);
 
       // Queue copy in Rust backend (fire-and-forget)
-      await invoke("queue_clipboard_copy_base64", {
-        imageBase64: base64Data,
-        version,
-      });
+      await invoke(
+        "queue_clipboard_copy_rgba",
+        new Uint8Array(imageData.data.buffer),
+        {
+          headers: {
+            "x-width": String(imageData.width),
+            "x-height": String(imageData.height),
+            "x-version": String(version),
+            "x-document-id": options?.documentId ?? "",
+            "x-document-name": encodeURIComponent(options?.documentName ?? ""),
+            "x-format": format,
+            "x-jpeg-quality": String(jpegQuality),
+            "x-max-dimension": String(options?.maxDimension ?? 0),
+            "x-max-bytes": String(options?.maxBytes ?? 0),
+          },
+        },
+      );
 
       // Update last copied version
       this.lastCopiedVersion = version;
@@ -187,122 +540,256 @@ export class IOService {
   }
 
   /**
-   * Fast JPEG encoding for auto-copy (much faster than PNG)
-   * Uses canvas.toBlob which is hardware accelerated
+   * Read an image (or copied image files) from the clipboard
+   *
+   * The response is framed like decode_image's: a little-endian u32 length,
+   * that many bytes of JSON, then the encoded image if there is one.
    */
-  private async encodeAsJpeg(
-    canvas: HTMLCanvasElement,
-    quality: number = 0.92,
-  ): Promise<string> {
-    const blob = await new Promise<Blob | null>((resolve) => {
-      canvas.toBlob(resolve, "image/jpeg", quality);
+  async readClipboard(): Promise<ClipboardContent> {
+    const buffer = await invoke<ArrayBuffer>("read_clipboard_image");
+    const contentLength = new DataView(buffer).getUint32(0, true);
+    const content:
+      | Omit<Extract<ClipboardContent, { kind: "image" }>, "imageSrc">
+      | Extract<ClipboardContent, { kind: "files" }> = JSON.parse(
+      new TextDecoder().decode(new Uint8Array(buffer, 4, contentLength)),
+    );
+    if (content.kind === "files") {
+      return content;
+    }
+    const image = new Blob([new Uint8Array(buffer, 4 + contentLength)], {
+      type: content.mime_type,
     });
+    return { ...content, imageSrc: URL.createObjectURL(image) };
+  }
 
-    if (!blob) {
-      throw new Error("Failed to create JPEG blob from canvas");
-    }
+  /**
+   * Recent copies, newest first
+   */
+  async getClipboardHistory(): Promise<ClipboardHistoryEntry[]> {
+    return invoke<ClipboardHistoryEntry[]>("get_clipboard_history");
+  }
+
+  /**
+   * Put a recent copy back on the clipboard
+   */
+  async recopyFromHistory(id: string): Promise<void> {
+    await invoke("recopy_clipboard_history", { id });
+  }
 
-    // Convert blob to base64
-    const arrayBuffer = await blob.arrayBuffer();
-    const uint8Array = new Uint8Array(arrayBuffer);
-    return this.uint8ArrayToBase64(uint8Array);
+  async clearClipboardHistory(): Promise<void> {
+    await invoke("clear_clipboard_history");
   }
 
   /**
-   * Convert Uint8Array to base64 string efficiently
-   * Uses chunked processing to avoid call stack issues
+   * Push clipboard backend settings to the Rust backend
    */
-  private uint8ArrayToBase64(bytes: Uint8Array): string {
-    // Process in chunks to avoid call stack issues with large arrays
-    const CHUNK_SIZE = 0x8000; // 32KB chunks
-    const chunks: string[] = [];
-
-    for (let i = 0; i < bytes.length; i += CHUNK_SIZE) {
-      const chunk = bytes.subarray(i, Math.min(i + CHUNK_SIZE, bytes.length));
-      chunks.push(
-        String.fromCharCode.apply(null, chunk as unknown as number[]),
-      );
-    }
+  async configureClipboard(copySettings: CopySettings): Promise<void> {
+    await invoke("configure_clipboard", {
+      config: {
+        backends: copySettings.backendOrder,
+        timeoutsMs: copySettings.backendTimeoutsMs,
+        persistAfterExit: copySettings.keepClipboardAfterExit,
+        formats: copySettings.extraFormats,
+        historySize: copySettings.historySize,
+        persistHistory: copySettings.saveHistoryToDisk,
+        selection: copySettings.clipboardSelection,
+      },
+    });
+  }
 
-    return btoa(chunks.join(""));
+  /**
+   * Get the last copied version (for UI feedback)
+   */
+  getLastCopiedVersion(): number {
+    return this.lastCopiedVersion;
   }
 
   /**
-   * Encode ImageData to base64 PNG in a Web Worker
+   * Get a URL the canvas can load for the image of a pipe session
    */
-  private encodeInWorker(imageData: ImageData): Promise<string> {
-    return new Promise((resolve, reject) => {
-      const worker = this.getCopyWorker();
-
-      const handleMessage = (event: MessageEvent) => {
-        worker.removeEventListener("message", handleMessage);
-        worker.removeEventListener("error", handleError);
-
-        if (event.data.type === "success") {
-          resolve(event.data.base64Data);
-        } else {
-          reject(new Error(event.data.error || "Worker failed"));
-        }
-      };
+  async openPipeSession(session: string): Promise<string> {
+    const buffer = await invoke<ArrayBuffer>("read_pipe_input", { session });
+    const { pixels } = this.parseDecoded(buffer);
+    return this.createPixelsUrl(pixels);
+  }
 
-      const handleError = (error: ErrorEvent) => {
-        worker.removeEventListener("message", handleMessage);
-        worker.removeEventListener("error", handleError);
-        reject(new Error(error.message));
-      };
+  /**
+   * Remember that closing or confirming `documentId` answers `session`
+   */
+  trackPipeSession(documentId: string, session: string): void {
+    this.pipeSessions.set(documentId, session);
+  }
 
-      worker.addEventListener("message", handleMessage);
-      worker.addEventListener("error", handleError);
+  /**
+   * The pipe session a document answers, if it was opened from one
+   */
+  getPipeSession(documentId: string): string | null {
+    return this.pipeSessions.get(documentId) ?? null;
+  }
 
-      // Transfer the underlying ArrayBuffer to the worker (zero-copy)
-      worker.postMessage(
-        {
-          type: "copy",
-          imageData: imageData.data,
-          width: imageData.width,
-          height: imageData.height,
+  /**
+   * Send the annotated canvas to the waiting `ursamarkup --pipe` as PNG
+   */
+  async finishPipeSession(
+    documentId: string,
+    canvas: HTMLCanvasElement,
+  ): Promise<void> {
+    const session = this.pipeSessions.get(documentId);
+    if (!session) throw new Error("Document has no pipe session");
+
+    const ctx = canvas.getContext("2d");
+    if (!ctx) throw new Error("Failed to get canvas context");
+    const imageData = ctx.getImageData(0, 0, canvas.width, canvas.height);
+
+    await invoke(
+      "finish_pipe_session",
+      new Uint8Array(imageData.data.buffer),
+      {
+        headers: {
+          "x-width": String(imageData.width),
+          "x-height": String(imageData.height),
+          "x-session": session,
         },
-        [imageData.data.buffer],
-      );
-    });
+      },
+    );
+    this.pipeSessions.delete(documentId);
   }
 
   /**
-   * Get the last copied version (for UI feedback)
+   * End the pipe session of a closed document. A session that was already
+   * finished is left alone; otherwise the waiting process gets the
+   * unchanged image, or is told it was cancelled if `discarded`.
    */
-  getLastCopiedVersion(): number {
-    return this.lastCopiedVersion;
+  async closePipeSession(
+    documentId: string,
+    discarded: boolean,
+  ): Promise<void> {
+    const session = this.pipeSessions.get(documentId);
+    if (!session) return;
+
+    this.pipeSessions.delete(documentId);
+    await invoke("close_pipe_session", { session, discard: discarded });
+  }
+
+  /**
+   * Wait for `documentIds` to close before answering `session`. Without any
+   * documents (every file failed to open) the session ends right away,
+   * reported as discarded.
+   */
+  async trackWaitSession(
+    session: string,
+    documentIds: string[],
+  ): Promise<void> {
+    const waitSession: WaitSession = {
+      documentIds: new Set(documentIds),
+      saved: [],
+      discarded: documentIds.length === 0,
+    };
+    this.waitSessions.set(session, waitSession);
+    if (documentIds.length === 0) {
+      await this.finishWaitSession(session, waitSession);
+    }
+  }
+
+  /**
+   * Note a save for the `ursamarkup --wait` session of a document, if any
+   */
+  recordSave(documentId: string, filePath: string): void {
+    for (const waitSession of this.waitSessions.values()) {
+      if (waitSession.documentIds.has(documentId)) {
+        waitSession.saved.push(filePath);
+      }
+    }
+  }
+
+  /**
+   * Note a closed document, answering its `ursamarkup --wait` session once
+   * the last of its documents is closed
+   */
+  async closeWaitDocument(
+    documentId: string,
+    discarded: boolean,
+  ): Promise<void> {
+    for (const [session, waitSession] of this.waitSessions) {
+      if (!waitSession.documentIds.delete(documentId)) continue;
+
+      waitSession.discarded ||= discarded;
+      if (waitSession.documentIds.size === 0) {
+        await this.finishWaitSession(session, waitSession);
+      }
+    }
+  }
+
+  private async finishWaitSession(
+    session: string,
+    { saved, discarded }: WaitSession,
+  ): Promise<void> {
+    this.waitSessions.delete(session);
+    await invoke("finish_wait_session", {
+      session,
+      result: { saved, discarded },
+    });
   }
 
   /**
    * Listen for files opened via CLI (single-instance)
    */
   async listenForFiles(
-    callback: (filePaths: string[]) => void,
+    callback: (payload: OpenFilesPayload) => void,
   ): Promise<UnlistenFn> {
-    const unlisten = await listen("open-files", (event) => {
-      const payload = event.payload as { file_paths: string[] };
-      if (payload?.file_paths && payload.file_paths.length > 0) {
-        callback(payload.file_paths);
-      }
-    });
+    return this.listenQueued<OpenFilesPayload>("open-files", callback);
+  }
 
-    return unlisten;
+  /**
+   * Listen for "Open File" in the tray menu
+   */
+  async listenForTrayOpenFile(callback: () => void): Promise<UnlistenFn> {
+    return this.listenQueued("tray-open-file", callback);
   }
 
   /**
-   * Get any pending files from initial launch
+   * Listen for an event the backend holds back until the frontend is ready
    */
-  async getPendingFiles(): Promise<string[]> {
-    try {
-      // Small delay to ensure backend is ready
-      await new Promise((resolve) => setTimeout(resolve, 150));
-      const pendingFiles = await invoke<string[]>("get_pending_files");
-      return pendingFiles || [];
-    } catch {
-      // No pending files or backend not ready
-      return [];
-    }
+  private async listenQueued<T>(
+    event: QueuedEvent,
+    callback: (payload: T) => void,
+  ): Promise<UnlistenFn> {
+    const unlisten = await listen<T>(event, (e) => callback(e.payload));
+    this.countListeners(event, 1);
+    this.scheduleReadyCheck();
+
+    return () => {
+      unlisten();
+      this.countListeners(event, -1);
+    };
+  }
+
+  private countListeners(event: QueuedEvent, change: number): void {
+    this.queuedListeners.set(
+      event,
+      (this.queuedListeners.get(event) ?? 0) + change,
+    );
+  }
+
+  /**
+   * Tell the backend to send its queued events once every queued event has
+   * a listener. Checked a tick later, since React's development double
+   * mount removes listeners right after adding them.
+   */
+  private scheduleReadyCheck(): void {
+    setTimeout(() => {
+      if (this.readySent) return;
+      const listening = QUEUED_EVENTS.every(
+        (event) => (this.queuedListeners.get(event) ?? 0) > 0,
+      );
+      if (!listening) return;
+
+      this.readySent = true;
+      invoke("frontend_ready").catch((error) => {
+        this.readySent = false;
+        console.error("Failed to signal frontend ready:", error);
+      });
+    }, 0);
   }
 }
 
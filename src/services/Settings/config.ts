@@ -11,8 +11,12 @@ import {
 
 import {
   AutoCopyFormats,
+  ChromaSubsamplings,
+  ClipboardBackends,
+  ClipboardSelections,
   CloseTabBehaviors,
   CloseWindowBehaviors,
+  ExportMetadataModes,
   ImageOpenBehaviors,
   type AppSettings,
   type HotkeySettings,
@@ -153,8 +157,48 @@ export const DEFAULT_SETTINGS: AppSettings = {
     autoCopyJpegQuality: 0.7,
     autoCopyOnChange: true,
     autoCopyShowToast: false,
+    autoCopyMaxDimension: 0,
+    autoCopyMaxBytes: 0,
     manualCopyFormat: AutoCopyFormats.JPEG,
     manualCopyJpegQuality: 0.9,
+    manualCopyMaxDimension: 0,
+    manualCopyMaxBytes: 0,
+    backendOrder: [
+      ClipboardBackends.ARBOARD,
+      ClipboardBackends.WL_CLIPBOARD,
+      ClipboardBackends.WL_COPY,
+      ClipboardBackends.XCLIP,
+      ClipboardBackends.XSEL,
+    ],
+    backendTimeoutsMs: {
+      [ClipboardBackends.ARBOARD]: 2000,
+      [ClipboardBackends.WL_CLIPBOARD]: 2000,
+      [ClipboardBackends.WL_COPY]: 5000,
+      [ClipboardBackends.XCLIP]: 5000,
+      [ClipboardBackends.XSEL]: 5000,
+    },
+    keepClipboardAfterExit: false,
+    extraFormats: {
+      png: false,
+      jpeg: false,
+      fileUri: false,
+      html: false,
+    },
+    historySize: 10,
+    saveHistoryToDisk: false,
+    clipboardSelection: ClipboardSelections.CLIPBOARD,
+  },
+
+  exportSettings: {
+    pngCompression: 6,
+    pngPalette: false,
+    jpegQuality: 0.95,
+    jpegSubsampling: ChromaSubsamplings.YUV420,
+    webpLossless: false,
+    webpQuality: 0.95,
+    metadata: ExportMetadataModes.STRIP,
+    embedSrgb: false,
+    embedProject: false,
   },
 
   miscSettings: {
@@ -163,5 +207,6 @@ export const DEFAULT_SETTINGS: AppSettings = {
     closeWindowBehavior: CloseWindowBehaviors.EXIT,
     showDebugInfo: false,
     restoreFromTrayOnCliImage: true,
+    cliTabLimit: 20,
   },
 };
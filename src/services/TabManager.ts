@@ -1,4 +1,5 @@
 import { Document } from "~/core/Document";
+import type { ProjectState } from "~/types";
 import type { CloseTabBehavior, ServiceEvents } from "~/types/settings";
 
 type EventCallback<T> = (payload: T) => void;
@@ -99,11 +100,13 @@ export class TabManager {
   /**
    * Create a new document
    * Reuses empty tab if the active document is empty and no image is being loaded
+   * Annotations and view are restored from `project` when opening a project file
    */
   createDocument(
     filePath?: string,
     fileName?: string,
     imageSrc?: string,
+    project?: ProjectState,
   ): string {
     const activeDoc = this.getActiveDocument();
 
@@ -112,7 +115,7 @@ export class TabManager {
     if (activeDoc && activeDoc.isEmpty() && !activeDoc.hasChanges) {
       if (imageSrc) {
         // Load image into the empty document
-        activeDoc.loadImage(filePath || null, imageSrc, fileName);
+        activeDoc.loadImage(filePath || null, imageSrc, fileName, project);
         this.emit("documentChanged", { id: activeDoc.id });
         return activeDoc.id;
       } else if (!filePath && !imageSrc) {
@@ -128,7 +131,7 @@ export class TabManager {
 
     // Load image if provided
     if (imageSrc) {
-      newDoc.loadImage(filePath || null, imageSrc, fileName);
+      newDoc.loadImage(filePath || null, imageSrc, fileName, project);
     }
 
     this.emit("documentAdded", { id: newDoc.id });
@@ -156,7 +159,7 @@ export class TabManager {
     // Handle based on close behavior setting
     switch (this.closeTabBehavior) {
       case "discard":
-        this.doCloseDocument(id);
+        this.doCloseDocument(id, true);
         break;
       case "auto-save":
         // Set up pending close - caller should handle save then call confirmCloseWithSave
@@ -174,8 +177,9 @@ export class TabManager {
 
   /**
    * Actually close the document (internal)
+   * @param discarded Whether unsaved changes were thrown away
    */
-  private doCloseDocument(id: string): void {
+  private doCloseDocument(id: string, discarded = false): void {
     const doc = this.documents.get(id);
     if (!doc) return;
 
@@ -200,7 +204,7 @@ export class TabManager {
       }
     }
 
-    this.emit("documentClosed", { id });
+    this.emit("documentClosed", { id, discarded });
     this.emit("activeDocumentChanged", { id: this.activeId });
 
     // If no documents left, create a new empty one
@@ -235,7 +239,7 @@ export class TabManager {
 
     const { id } = this.pendingClose;
     this.pendingClose = null;
-    this.doCloseDocument(id);
+    this.doCloseDocument(id, true);
   }
 
   /**
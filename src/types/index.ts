@@ -42,6 +42,23 @@ export type StrokeHistoryState = {
   currentIndex: number;
 };
 
+/**
+ * Zoom, pan and ruler of a document, as stored in a project file
+ */
+export type ProjectViewState = {
+  zoom: number;
+  viewOffset: Point;
+  ruler: { visible: boolean; x: number; y: number; angle: number };
+};
+
+/**
+ * The editable state of a document that a `.ursa` project keeps
+ */
+export type ProjectState = {
+  strokeHistory: StrokeHistoryState;
+  view: ProjectViewState;
+};
+
 export type Tab = {
   id: string;
   filePath: string | null;
@@ -114,3 +131,18 @@ export interface PreviewState<T extends Exclude<Tool, "eraser">> {
 export type AnyPreviewState = {
   [K in Exclude<Tool, "eraser">]: PreviewState<K>;
 }[Exclude<Tool, "eraser">];
+
+/**
+ * Error returned by Rust commands, tagged by a stable `kind`
+ */
+export type AppError =
+  | { kind: "DecodeFailed"; format: string | null; message: string }
+  | { kind: "EncodeFailed"; format: string; message: string }
+  | { kind: "BackendUnavailable"; backend: string | null; message: string }
+  | { kind: "Timeout"; operation: string; timeout_ms: number }
+  | { kind: "Io"; path: string | null; message: string }
+  | { kind: "PermissionDenied"; path: string | null; message: string }
+  | { kind: "InvalidInput"; message: string }
+  | { kind: "NotFound"; message: string }
+  | { kind: "Cancelled" }
+  | { kind: "Internal"; message: string };
@@ -38,6 +38,35 @@ export const AutoCopyFormats = {
 export type AutoCopyFormat =
   (typeof AutoCopyFormats)[keyof typeof AutoCopyFormats];
 
+export const ClipboardBackends = {
+  ARBOARD: "arboard",
+  WL_CLIPBOARD: "wl-clipboard",
+  WL_COPY: "wl-copy",
+  XCLIP: "xclip",
+  XSEL: "xsel",
+} as const;
+export type ClipboardBackend =
+  (typeof ClipboardBackends)[keyof typeof ClipboardBackends];
+
+/** Which selections a copy is written to; PRIMARY is pasted with middle-click (Linux) */
+export const ClipboardSelections = {
+  CLIPBOARD: "clipboard",
+  BOTH: "both",
+  PRIMARY: "primary",
+} as const;
+export type ClipboardSelection =
+  (typeof ClipboardSelections)[keyof typeof ClipboardSelections];
+
+/** Extra formats published alongside the copied image */
+export type ClipboardFormatSettings = {
+  png: boolean;
+  jpeg: boolean;
+  /** A link to a temp file, for apps that only accept pasted files */
+  fileUri: boolean;
+  /** An HTML snippet with the image inlined */
+  html: boolean;
+};
+
 /**
  * HOTKEY DEFINITIONS
  */
@@ -103,8 +132,61 @@ export type CopySettings = {
   autoCopyJpegQuality: number;
   autoCopyOnChange: boolean;
   autoCopyShowToast: boolean;
+  /** Longest side of auto-copies in pixels; 0 keeps the full size */
+  autoCopyMaxDimension: number;
+  /** Size budget for auto-copies in bytes; 0 disables it */
+  autoCopyMaxBytes: number;
   manualCopyFormat: AutoCopyFormat;
   manualCopyJpegQuality: number;
+  manualCopyMaxDimension: number;
+  manualCopyMaxBytes: number;
+  /** Clipboard backends to try, in priority order */
+  backendOrder: ClipboardBackend[];
+  /** Per-backend timeout before falling through to the next one */
+  backendTimeoutsMs: Record<ClipboardBackend, number>;
+  /** Keep serving the last copied image after the app quits (Linux) */
+  keepClipboardAfterExit: boolean;
+  extraFormats: ClipboardFormatSettings;
+  /** Recent copies kept for re-copying from the tray; 0 disables it */
+  historySize: number;
+  /** Save recent copies to disk so they survive restarts */
+  saveHistoryToDisk: boolean;
+  clipboardSelection: ClipboardSelection;
+};
+
+/** JPEG chroma subsampling; 4:4:4 keeps thin coloured lines and text sharp */
+export const ChromaSubsamplings = {
+  YUV444: "444",
+  YUV422: "422",
+  YUV420: "420",
+} as const;
+export type ChromaSubsampling =
+  (typeof ChromaSubsamplings)[keyof typeof ChromaSubsamplings];
+
+/** Whether exports keep the source file's EXIF data and tag colour-managed sources as sRGB */
+export const ExportMetadataModes = {
+  KEEP: "keep",
+  STRIP: "strip",
+} as const;
+export type ExportMetadataMode =
+  (typeof ExportMetadataModes)[keyof typeof ExportMetadataModes];
+
+export type ExportSettings = {
+  /** Deflate level, 0 (fastest) to 9 (smallest) */
+  pngCompression: number;
+  /** Write an indexed PNG when the image has at most 256 colours */
+  pngPalette: boolean;
+  jpegQuality: number;
+  jpegSubsampling: ChromaSubsampling;
+  webpLossless: boolean;
+  /** Only used for lossy WebP */
+  webpQuality: number;
+  /** Stripping removes GPS coordinates and camera serials before sharing */
+  metadata: ExportMetadataMode;
+  /** Tag exports with an sRGB ICC profile */
+  embedSrgb: boolean;
+  /** Embed the original image and strokes in PNG exports, so they stay editable */
+  embedProject: boolean;
 };
 
 export type MiscSettings = {
@@ -113,6 +195,8 @@ export type MiscSettings = {
   closeWindowBehavior: CloseWindowBehavior;
   showDebugInfo: boolean;
   restoreFromTrayOnCliImage: boolean;
+  /** Tabs opened at once from the command line or a folder; the rest wait for confirmation */
+  cliTabLimit: number;
 };
 
 /**
@@ -125,6 +209,7 @@ export type AppSettings = {
   toolConfigs: ToolConfigs;
   hotkeys: HotkeySettings;
   copySettings: CopySettings;
+  exportSettings: ExportSettings;
   miscSettings: MiscSettings;
 };
 
@@ -137,7 +222,8 @@ export type ServiceEvents = {
   activeDocumentChanged: { id: string | null };
   documentAdded: { id: string };
   documentChanged: { id: string };
-  documentClosed: { id: string };
+  /** `discarded` is set when unsaved changes were thrown away */
+  documentClosed: { id: string; discarded: boolean };
   settingsChanged: AppSettings;
   settingsSaved: AppSettings;
   themeLoaded: Theme;
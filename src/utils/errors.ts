@@ -0,0 +1,71 @@
+import type { RejectReason } from "~/services/IOService";
+import type { AppError } from "~/types";
+
+/**
+ * Checks whether a rejected invoke carries a typed backend error.
+ */
+export function isAppError(error: unknown): error is AppError {
+  return (
+    typeof error === "object" &&
+    error !== null &&
+    typeof (error as { kind?: unknown }).kind === "string"
+  );
+}
+
+/**
+ * Turns a backend error into a short, user-facing description for toasts.
+ */
+export function describeError(error: unknown): string {
+  if (!isAppError(error)) {
+    return error instanceof Error ? error.message : String(error);
+  }
+
+  switch (error.kind) {
+    case "DecodeFailed":
+      return `Could not read the image${error.format ? ` (${error.format})` : ""}`;
+    case "EncodeFailed":
+      return `Could not encode the image as ${error.format}`;
+    case "BackendUnavailable":
+      return error.backend
+        ? `Clipboard unavailable (${error.backend})`
+        : "Clipboard unavailable";
+    case "Timeout":
+      return `${error.operation} timed out after ${error.timeout_ms}ms`;
+    case "Io":
+      return error.path ? `Could not access ${error.path}` : error.message;
+    case "PermissionDenied":
+      return error.path ? `Permission denied: ${error.path}` : "Permission denied";
+    case "Cancelled":
+      return "Cancelled";
+    case "InvalidInput":
+    case "NotFound":
+    case "Internal":
+      return error.message;
+  }
+}
+
+/**
+ * Turns the reason a command line file was rejected into a short message.
+ */
+export function describeRejection(reason: RejectReason): string {
+  switch (reason.kind) {
+    case "NotFound":
+      return "File not found";
+    case "NoImages":
+      return reason.recursive
+        ? "No images in this folder or its subfolders"
+        : "No images in this folder";
+    case "TooManyImages":
+      return `Folder is too large, only the first ${reason.opened} images were found`;
+    case "NotAnImage":
+      return "Not an image";
+    case "TooLarge":
+      return `Larger than the ${Math.round(reason.limit / 1024 / 1024)} MB limit`;
+    case "Unreadable":
+      return "File cannot be read";
+    case "Corrupt":
+      return "Image is damaged";
+    case "InvalidArgument":
+      return reason.message;
+  }
+}
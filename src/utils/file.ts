@@ -2,12 +2,148 @@
  * Utility functions for file operations
  */
 
-const IMAGE_EXTENSIONS = ['.png', '.jpg', '.jpeg', '.webp', '.gif'];
+/** Extensions the webview can display directly */
+const WEBVIEW_IMAGE_EXTENSIONS = ['.png', '.jpg', '.jpeg', '.webp', '.gif', '.bmp'];
+
+/** Extensions that are decoded by the Rust backend instead */
+const BACKEND_IMAGE_EXTENSIONS = ['.tif', '.tiff', '.ico', '.qoi'];
+
+/** Every image extension we can open, without the leading dot */
+export const OPENABLE_IMAGE_EXTENSIONS = [
+  ...WEBVIEW_IMAGE_EXTENSIONS,
+  ...BACKEND_IMAGE_EXTENSIONS,
+].map((ext) => ext.substring(1));
+
+/** Extension of editable `.ursa` project files, without the leading dot */
+export const PROJECT_EXTENSION = 'ursa';
+
+function extensionOf(filePath: string): string {
+  return filePath.toLowerCase().substring(filePath.lastIndexOf('.'));
+}
 
 /**
  * Checks if a file path corresponds to an image file based on its extension.
  */
 export function isImageFile(filePath: string): boolean {
-  const ext = filePath.toLowerCase().substring(filePath.lastIndexOf('.'));
-  return IMAGE_EXTENSIONS.includes(ext);
-}
\ No newline at end of file
+  const ext = extensionOf(filePath);
+  return WEBVIEW_IMAGE_EXTENSIONS.includes(ext) || BACKEND_IMAGE_EXTENSIONS.includes(ext);
+}
+
+/**
+ * Checks if a file path is a `.ursa` project file.
+ */
+export function isProjectFile(filePath: string): boolean {
+  return extensionOf(filePath) === `.${PROJECT_EXTENSION}`;
+}
+
+/**
+ * Checks for the PNG signature. Only the backend looks inside PNGs for an
+ * embedded project, so they are decoded there when a file is loaded.
+ */
+export function isPng(data: Uint8Array): boolean {
+  return (
+    data.length >= 8 &&
+    new DataView(data.buffer, data.byteOffset, data.byteLength).getUint32(0) === 0x89504e47
+  );
+}
+
+/**
+ * Checks if an image file has to be decoded by the Rust backend.
+ *
+ * Besides formats the webview cannot display, rotated JPEGs and images with
+ * an ICC profile go through the backend too, so orientation and colour
+ * conversion to sRGB are applied the same way on every platform.
+ */
+export function needsBackendDecode(filePath: string, fileData: Uint8Array): boolean {
+  return (
+    BACKEND_IMAGE_EXTENSIONS.includes(extensionOf(filePath)) ||
+    jpegExifOrientation(fileData) !== 1 ||
+    hasIccProfile(fileData)
+  );
+}
+
+/**
+ * Walks the segments of a JPEG up to the image data, returning the offset of
+ * the first one `matches` accepts, or -1.
+ */
+function findJpegSegment(
+  data: Uint8Array,
+  matches: (view: DataView, marker: number, offset: number) => boolean,
+): number {
+  if (data[0] !== 0xff || data[1] !== 0xd8) return -1;
+  const view = new DataView(data.buffer, data.byteOffset, data.byteLength);
+
+  let offset = 2;
+  while (offset + 10 <= data.length && data[offset] === 0xff) {
+    const marker = data[offset + 1];
+    // Start of scan: no metadata follows
+    if (marker === 0xda) return -1;
+    if (matches(view, marker, offset)) return offset;
+    offset += 2 + view.getUint16(offset + 2);
+  }
+  return -1;
+}
+
+/**
+ * Reads the EXIF orientation (1-8, 1 = upright) of a JPEG from its APP1 segment.
+ * Returns 1 for anything else.
+ */
+function jpegExifOrientation(data: Uint8Array): number {
+  // APP1 starting with "Exif"
+  const offset = findJpegSegment(
+    data,
+    (view, marker, offset) => marker === 0xe1 && view.getUint32(offset + 4) === 0x45786966,
+  );
+  if (offset < 0) return 1;
+  return tiffOrientation(new DataView(data.buffer, data.byteOffset, data.byteLength), offset + 10);
+}
+
+function tiffOrientation(view: DataView, start: number): number {
+  if (start + 8 > view.byteLength) return 1;
+  const littleEndian = view.getUint16(start) === 0x4949;
+  const ifd = start + view.getUint32(start + 4, littleEndian);
+  if (ifd + 2 > view.byteLength) return 1;
+
+  const entries = view.getUint16(ifd, littleEndian);
+  for (let i = 0; i < entries; i++) {
+    const entry = ifd + 2 + i * 12;
+    if (entry + 12 > view.byteLength) return 1;
+    if (view.getUint16(entry, littleEndian) === 0x0112) {
+      return view.getUint16(entry + 8, littleEndian);
+    }
+  }
+  return 1;
+}
+/**
+ * Checks for an embedded ICC profile in a PNG (iCCP chunk), JPEG (APP2
+ * "ICC_PROFILE" segment) or WebP (VP8X ICC flag).
+ */
+function hasIccProfile(data: Uint8Array): boolean {
+  if (data.length < 30) return false;
+  const view = new DataView(data.buffer, data.byteOffset, data.byteLength);
+
+  // PNG: iCCP must come before the first IDAT
+  if (view.getUint32(0) === 0x89504e47) {
+    let offset = 8;
+    while (offset + 8 <= data.length) {
+      const type = view.getUint32(offset + 4);
+      if (type === 0x69434350) return true; // iCCP
+      if (type === 0x49444154) return false; // IDAT
+      offset += 12 + view.getUint32(offset);
+    }
+    return false;
+  }
+
+  // WebP: "RIFF" .... "WEBP" "VP8X" with the ICC flag set
+  if (view.getUint32(0) === 0x52494646 && view.getUint32(12) === 0x56503858) {
+    return (data[20] & 0x20) !== 0;
+  }
+
+  // JPEG: APP2 starting with "ICC_"
+  return (
+    findJpegSegment(
+      data,
+      (view, marker, offset) => marker === 0xe2 && view.getUint32(offset + 4) === 0x4943435f,
+    ) >= 0
+  );
+}